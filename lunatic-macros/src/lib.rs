@@ -51,6 +51,8 @@ fn parse(input: syn::ItemFn, is_test: bool) -> Result<TokenStream, syn::Error> {
         quote! {}
     };
 
+    // Forward other attributes, like `#[ignore]` or `#[cfg_attr(...)]`.
+    let attrs = input.attrs;
     let name = input.sig.ident;
     let arguments = input.sig.inputs;
     let block = input.block;
@@ -87,6 +89,7 @@ fn parse(input: syn::ItemFn, is_test: bool) -> Result<TokenStream, syn::Error> {
 
     let result = quote! {
         #header
+        #(#attrs)*
         #body
     };
 
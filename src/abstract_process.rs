@@ -0,0 +1,199 @@
+use std::time::Duration;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    environment::ThisModule,
+    error::LunaticError,
+    mailbox::{LinkMailbox, Mailbox, TransformMailbox},
+    process::{self, Process},
+    request::{Request, RequestError},
+    tag::Tag,
+};
+
+/// A process that keeps state between messages and answers requests.
+///
+/// Instead of writing the receive loop by hand, the loop is provided by the library and only the
+/// handlers need to be implemented. The process is started with [`start`](AbstractProcess::start)
+/// or one of its variants, that return a [`ProcessRef`] handle to it.
+///
+/// # Example
+///
+/// ```no_run
+/// use lunatic::{AbstractProcess, Mailbox};
+///
+/// struct Counter;
+///
+/// impl AbstractProcess for Counter {
+///     type Arg = u64;
+///     type State = u64;
+///     type Request = ();
+///     type Response = u64;
+///     type Message = u64;
+///
+///     fn init(start: u64) -> u64 {
+///         start
+///     }
+///
+///     fn handle_request(count: &mut u64, _: ()) -> u64 {
+///         *count
+///     }
+///
+///     fn handle_message(count: &mut u64, add: u64) {
+///         *count += add;
+///     }
+/// }
+///
+/// #[lunatic::main]
+/// fn main(_: Mailbox<()>) {
+///     let counter = Counter::start(5).unwrap();
+///     counter.send(10);
+///     assert_eq!(counter.request(()).unwrap(), 15);
+/// }
+/// ```
+pub trait AbstractProcess: Sized + 'static {
+    /// Argument passed to [`init`](AbstractProcess::init) when the process is started.
+    type Arg: Serialize + DeserializeOwned;
+    /// State that is kept between messages.
+    type State;
+    /// Request sent with [`ProcessRef::request`].
+    type Request: Serialize + DeserializeOwned;
+    /// Response to a request.
+    type Response: Serialize + DeserializeOwned;
+    /// Message sent with [`ProcessRef::send`], that doesn't get a response.
+    type Message: Serialize + DeserializeOwned;
+
+    /// Called inside the new process to create the initial state.
+    fn init(arg: Self::Arg) -> Self::State;
+
+    /// Called for every request. The returned value is sent back to the requesting process.
+    fn handle_request(state: &mut Self::State, request: Self::Request) -> Self::Response;
+
+    /// Called for every message.
+    fn handle_message(_state: &mut Self::State, _message: Self::Message) {}
+
+    /// Called when the process is shut down with [`ProcessRef::shutdown`].
+    fn terminate(_state: Self::State) {}
+
+    /// Starts the process.
+    fn start(arg: Self::Arg) -> Result<ProcessRef<Self>, LunaticError> {
+        let process = process::spawn_with(arg, entry::<Self>)?;
+        Ok(ProcessRef { process })
+    }
+
+    /// Starts the process and links it to the parent.
+    fn start_link<P, M>(
+        mailbox: M,
+        arg: Self::Arg,
+    ) -> Result<(ProcessRef<Self>, Tag, LinkMailbox<P>), LunaticError>
+    where
+        P: Serialize + DeserializeOwned,
+        M: TransformMailbox<P>,
+    {
+        let (process, tag, mailbox) = process::spawn_link_with(mailbox, arg, entry::<Self>)?;
+        Ok((ProcessRef { process }, tag, mailbox))
+    }
+
+    /// Starts the process inside of `module`.
+    fn start_in(module: &ThisModule, arg: Self::Arg) -> Result<ProcessRef<Self>, LunaticError> {
+        let process = module.spawn_with(arg, entry::<Self>)?;
+        Ok(ProcessRef { process })
+    }
+
+    /// Starts the process inside of `module` and links it to the parent.
+    fn start_link_in<P, M>(
+        module: &ThisModule,
+        mailbox: M,
+        arg: Self::Arg,
+    ) -> Result<(ProcessRef<Self>, Tag, LinkMailbox<P>), LunaticError>
+    where
+        P: Serialize + DeserializeOwned,
+        M: TransformMailbox<P>,
+    {
+        let (process, tag, mailbox) = module.spawn_link_with(mailbox, arg, entry::<Self>)?;
+        Ok((ProcessRef { process }, tag, mailbox))
+    }
+}
+
+// Messages understood by the receive loop of an abstract process.
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+enum ServerMessage<A: AbstractProcess> {
+    Request(Request<A::Request, A::Response>),
+    Message(A::Message),
+    Shutdown(Request<(), ()>),
+}
+
+// Entry point of every abstract process.
+fn entry<A: AbstractProcess>(arg: A::Arg, mailbox: Mailbox<ServerMessage<A>>) {
+    let mut state = A::init(arg);
+    loop {
+        match mailbox.receive() {
+            Ok(ServerMessage::Request(request)) => {
+                let (request, tag, sender) = request.into_inner();
+                let response = A::handle_request(&mut state, request);
+                sender.tag_send(tag, response);
+            }
+            Ok(ServerMessage::Message(message)) => A::handle_message(&mut state, message),
+            Ok(ServerMessage::Shutdown(request)) => {
+                A::terminate(state);
+                request.reply(());
+                return;
+            }
+            // Messages that can't be deserialized are dropped.
+            Err(_) => continue,
+        }
+    }
+}
+
+/// A handle to a running [`AbstractProcess`].
+///
+/// The handle can be cloned and sent to other processes.
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct ProcessRef<A: AbstractProcess> {
+    process: Process<ServerMessage<A>>,
+}
+
+impl<A: AbstractProcess> Clone for ProcessRef<A> {
+    fn clone(&self) -> Self {
+        Self {
+            process: self.process.clone(),
+        }
+    }
+}
+
+impl<A: AbstractProcess> ProcessRef<A> {
+    /// Returns the id of the process.
+    pub fn id(&self) -> u128 {
+        self.process.id()
+    }
+
+    /// Sends a request to the process and waits for the response.
+    pub fn request(&self, request: A::Request) -> Result<A::Response, RequestError> {
+        self.process
+            .request_wrapped(request, ServerMessage::Request, None)
+    }
+
+    /// Same as [`request`](ProcessRef::request), but only waits for the duration of timeout for
+    /// the response.
+    pub fn request_timeout(
+        &self,
+        request: A::Request,
+        timeout: Duration,
+    ) -> Result<A::Response, RequestError> {
+        self.process
+            .request_wrapped(request, ServerMessage::Request, Some(timeout))
+    }
+
+    /// Sends a message to the process.
+    pub fn send(&self, message: A::Message) {
+        self.process.send(ServerMessage::Message(message));
+    }
+
+    /// Shuts the process down and waits for [`terminate`](AbstractProcess::terminate) to finish.
+    pub fn shutdown(&self) -> Result<(), RequestError> {
+        self.process
+            .request_wrapped((), ServerMessage::Shutdown, None)
+    }
+}
@@ -0,0 +1,114 @@
+/*! Codecs that turn messages into bytes and back */
+
+use std::{
+    error::Error,
+    io::{Read, Write},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+
+/// Encodes and decodes messages that are sent between processes.
+///
+/// The codec is part of the [`Process`](crate::process::Process) and
+/// [`Mailbox`](crate::Mailbox) types and defaults to [`MessagePack`]. Both sides need to use the
+/// same codec, otherwise receiving fails with a
+/// [`DeserializationFailed`](crate::ReceiveError::DeserializationFailed) error. Spawned processes
+/// always start with a `MessagePack` mailbox, a different codec can be picked with
+/// [`Mailbox::with_codec`](crate::Mailbox::with_codec) and
+/// [`Process::with_codec`](crate::process::Process::with_codec).
+///
+/// Resources, like processes and TCP streams, are moved next to the message data and only their
+/// index is encoded, so they work with every codec that supports `u64` values.
+///
+/// # Example
+///
+/// ```no_run
+/// use lunatic::{codec::Json, process, Mailbox};
+///
+/// #[lunatic::main]
+/// fn main(_: Mailbox<()>) {
+///     let child = process::spawn(|mailbox: Mailbox<String>| {
+///         let mailbox = mailbox.with_codec::<Json>();
+///         println!("{}", mailbox.receive().unwrap());
+///     })
+///     .unwrap();
+///     child.with_codec::<Json>().send("Hello".to_string());
+/// }
+/// ```
+pub trait Codec: 'static {
+    /// Writes `value` into `writer`.
+    fn encode<T: Serialize, W: Write>(writer: &mut W, value: &T) -> Result<(), EncodeError>;
+    /// Reads a value from `reader`.
+    fn decode<T: DeserializeOwned, R: Read>(reader: &mut R) -> Result<T, DecodeError>;
+}
+
+/// The [MessagePack](https://msgpack.org) format, the default codec.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MessagePack;
+
+impl Codec for MessagePack {
+    fn encode<T: Serialize, W: Write>(writer: &mut W, value: &T) -> Result<(), EncodeError> {
+        rmp_serde::encode::write(writer, value).map_err(EncodeError::new)
+    }
+
+    fn decode<T: DeserializeOwned, R: Read>(reader: &mut R) -> Result<T, DecodeError> {
+        rmp_serde::from_read(reader).map_err(DecodeError::new)
+    }
+}
+
+/// The compact [bincode](https://docs.rs/bincode) format.
+#[cfg(feature = "bincode")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Bincode;
+
+#[cfg(feature = "bincode")]
+impl Codec for Bincode {
+    fn encode<T: Serialize, W: Write>(writer: &mut W, value: &T) -> Result<(), EncodeError> {
+        bincode::serialize_into(writer, value).map_err(EncodeError::new)
+    }
+
+    fn decode<T: DeserializeOwned, R: Read>(reader: &mut R) -> Result<T, DecodeError> {
+        bincode::deserialize_from(reader).map_err(DecodeError::new)
+    }
+}
+
+/// Human readable JSON, useful for debugging.
+#[cfg(feature = "json")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Json;
+
+#[cfg(feature = "json")]
+impl Codec for Json {
+    fn encode<T: Serialize, W: Write>(writer: &mut W, value: &T) -> Result<(), EncodeError> {
+        serde_json::to_writer(writer, value).map_err(EncodeError::new)
+    }
+
+    fn decode<T: DeserializeOwned, R: Read>(reader: &mut R) -> Result<T, DecodeError> {
+        serde_json::from_reader(reader).map_err(DecodeError::new)
+    }
+}
+
+/// Error returned by a [`Codec`] if a message can't be encoded.
+#[derive(Error, Debug)]
+#[error(transparent)]
+pub struct EncodeError(Box<dyn Error + Send + Sync>);
+
+impl EncodeError {
+    /// Wraps the error of a serialization format.
+    pub fn new<E: Error + Send + Sync + 'static>(error: E) -> Self {
+        Self(Box::new(error))
+    }
+}
+
+/// Error returned by a [`Codec`] if a message can't be decoded.
+#[derive(Error, Debug)]
+#[error(transparent)]
+pub struct DecodeError(Box<dyn Error + Send + Sync>);
+
+impl DecodeError {
+    /// Wraps the error of a serialization format.
+    pub fn new<E: Error + Send + Sync + 'static>(error: E) -> Self {
+        Self(Box::new(error))
+    }
+}
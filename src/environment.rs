@@ -1,4 +1,4 @@
-use std::{fmt::Display, u128};
+use std::{cell::UnsafeCell, fmt::Display, u128};
 
 use serde::{de::DeserializeOwned, Serialize};
 use thiserror::Error;
@@ -10,24 +10,46 @@ use crate::{
     process::{spawn_, Context, Process},
     tag::Tag,
 };
+#[cfg(feature = "unstable-host")]
+use crate::{process::this_env, resource::transferable};
 
 /// Environment configuration
 pub struct Config {
     id: u64,
+    // If the configuration is serialized it will be removed from our resources, so we can't call
+    // `drop_config()` anymore on it.
+    consumed: UnsafeCell<bool>,
 }
 
 impl Drop for Config {
     fn drop(&mut self) {
-        unsafe { host_api::process::drop_config(self.id) };
+        // Only drop configuration if it's not already consumed
+        if unsafe { !*self.consumed.get() } {
+            unsafe { host_api::process::drop_config(self.id) };
+        }
     }
 }
 
+#[cfg(feature = "unstable-host")]
+transferable!(
+    Config,
+    host_api::message::push_config,
+    host_api::message::take_config
+);
+
 impl Config {
+    pub(crate) fn from(id: u64) -> Self {
+        Config {
+            id,
+            consumed: UnsafeCell::new(false),
+        }
+    }
+
     /// Create a new configuration
     pub fn new(max_memory: u64, max_fuel: Option<u64>) -> Self {
         let max_fuel = max_fuel.unwrap_or(0);
         let id = unsafe { host_api::process::create_config(max_memory, max_fuel) };
-        Self { id }
+        Self::from(id)
     }
 
     /// Allow a host function namespace to be used by processes spawned with this configuration.
@@ -73,19 +95,38 @@ impl Display for RegistryError {
 }
 
 /// Environments can define characteristics of processes that are spawned into it.
+///
+/// Environments, like [`Config`]s and [`Module`]s, can be sent to other processes inside of
+/// messages. The handle is moved with the message and can't be used by the sender anymore.
 pub struct Environment {
     id: u64,
+    // If the environment is serialized it will be removed from our resources, so we can't call
+    // `drop_environment()` anymore on it.
+    consumed: UnsafeCell<bool>,
 }
 
 impl Drop for Environment {
     fn drop(&mut self) {
-        unsafe { host_api::process::drop_environment(self.id) };
+        // Only drop environment if it's not already consumed
+        if unsafe { !*self.consumed.get() } {
+            unsafe { host_api::process::drop_environment(self.id) };
+        }
     }
 }
 
+#[cfg(feature = "unstable-host")]
+transferable!(
+    Environment,
+    host_api::message::push_environment,
+    host_api::message::take_environment
+);
+
 impl Environment {
     pub(crate) fn from(id: u64) -> Self {
-        Environment { id }
+        Environment {
+            id,
+            consumed: UnsafeCell::new(false),
+        }
     }
 
     /// Create a new environment from a configurationS
@@ -95,9 +136,7 @@ impl Environment {
             host_api::process::create_environment(config.id, &mut env_or_error_id as *mut u64)
         };
         if result == 0 {
-            Ok(Self {
-                id: env_or_error_id,
-            })
+            Ok(Self::from(env_or_error_id))
         } else {
             Err(LunaticError::from(env_or_error_id))
         }
@@ -115,9 +154,7 @@ impl Environment {
             )
         };
         if result == 0 {
-            Ok(Module {
-                id: module_or_error_id,
-            })
+            Ok(Module::from(module_or_error_id))
         } else {
             Err(LunaticError::from(module_or_error_id))
         }
@@ -130,9 +167,7 @@ impl Environment {
             host_api::process::add_this_module(self.id, &mut module_or_error_id as *mut u64)
         };
         if result == 0 {
-            Ok(ThisModule {
-                id: module_or_error_id,
-            })
+            Ok(ThisModule::from(module_or_error_id))
         } else {
             Err(LunaticError::from(module_or_error_id))
         }
@@ -211,6 +246,42 @@ pub fn lookup<T: Serialize + DeserializeOwned>(
     }
 }
 
+// Starts the process returned by `spawn` and registers it under `name`, unless a process matching
+// `query` is already registered, and returns the registered process.
+//
+// Two callers can both find the name free and start a process. Registering overwrites, so the
+// name is looked up again afterwards. A caller whose process was replaced kills it again and uses
+// the one registered by the other caller. This doesn't close the race: if the other caller only
+// registers after the lookup, both callers keep their own process and the replaced one keeps
+// running unregistered. The registry has no compare-and-set, so processes that must be unique
+// need to be started once, e.g. at the beginning of `main`.
+#[cfg(feature = "unstable-host")]
+pub(crate) fn start_registered<T, F>(
+    name: &str,
+    version: &str,
+    query: &str,
+    spawn: F,
+) -> Result<Process<T>, LunaticError>
+where
+    T: Serialize + DeserializeOwned,
+    F: FnOnce() -> Result<Process<T>, LunaticError>,
+{
+    if let Some(process) = lookup(name, query).expect("Valid semver query") {
+        return Ok(process);
+    }
+    let process = spawn()?;
+    this_env()
+        .register(name, version, process.clone())
+        .expect("Valid semver");
+    match lookup(name, query).expect("Valid semver query") {
+        Some(registered) if registered != process => {
+            process.kill();
+            Ok(registered)
+        }
+        _ => Ok(process),
+    }
+}
+
 /// A compiled instance of a WebAssembly module.
 ///
 /// Modules belong to [`Environments`](Environment) and processes spawned from the modules will
@@ -219,15 +290,35 @@ pub fn lookup<T: Serialize + DeserializeOwned>(
 /// Creating a module will also JIT compile it, this can be a compute intensive tasks.
 pub struct Module {
     id: u64,
+    // If the module is serialized it will be removed from our resources, so we can't call
+    // `drop_module()` anymore on it.
+    consumed: UnsafeCell<bool>,
 }
 
 impl Drop for Module {
     fn drop(&mut self) {
-        unsafe { host_api::process::drop_module(self.id) };
+        // Only drop module if it's not already consumed
+        if unsafe { !*self.consumed.get() } {
+            unsafe { host_api::process::drop_module(self.id) };
+        }
     }
 }
 
+#[cfg(feature = "unstable-host")]
+transferable!(
+    Module,
+    host_api::message::push_module,
+    host_api::message::take_module
+);
+
 impl Module {
+    pub(crate) fn from(id: u64) -> Self {
+        Module {
+            id,
+            consumed: UnsafeCell::new(false),
+        }
+    }
+
     /// Spawn a new process and use `function` as the entry point. If the function takes arguments
     /// the passed in `params` need to exactly match their types.
     pub fn spawn<T: Serialize + DeserializeOwned>(
@@ -298,15 +389,35 @@ impl Module {
 /// processes from, otherwise we could not reference them by table id.
 pub struct ThisModule {
     id: u64,
+    // If the module is serialized it will be removed from our resources, so we can't call
+    // `drop_module()` anymore on it.
+    consumed: UnsafeCell<bool>,
 }
 
 impl Drop for ThisModule {
     fn drop(&mut self) {
-        unsafe { host_api::process::drop_module(self.id) };
+        // Only drop module if it's not already consumed
+        if unsafe { !*self.consumed.get() } {
+            unsafe { host_api::process::drop_module(self.id) };
+        }
     }
 }
 
+#[cfg(feature = "unstable-host")]
+transferable!(
+    ThisModule,
+    host_api::message::push_module,
+    host_api::message::take_module
+);
+
 impl ThisModule {
+    pub(crate) fn from(id: u64) -> Self {
+        ThisModule {
+            id,
+            consumed: UnsafeCell::new(false),
+        }
+    }
+
     /// Spawns a new process from a function.
     ///
     /// - `function` is the starting point of the new process. The new process doesn't share
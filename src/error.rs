@@ -1,7 +1,39 @@
 use crate::host_api::error;
+#[cfg(feature = "unstable-host")]
+use crate::host_api::error::io_error_kind;
 use std::fmt::{Debug, Display, Formatter};
+use std::io::{self, ErrorKind};
 use thiserror::Error;
 
+// Kinds of I/O errors the host can report. `io_error_kind` returns the index into this list plus
+// one, or 0 if the error didn't come from an I/O operation.
+pub(crate) const IO_ERROR_KINDS: [ErrorKind; 18] = [
+    ErrorKind::NotFound,
+    ErrorKind::PermissionDenied,
+    ErrorKind::ConnectionRefused,
+    ErrorKind::ConnectionReset,
+    ErrorKind::ConnectionAborted,
+    ErrorKind::NotConnected,
+    ErrorKind::AddrInUse,
+    ErrorKind::AddrNotAvailable,
+    ErrorKind::BrokenPipe,
+    ErrorKind::AlreadyExists,
+    ErrorKind::WouldBlock,
+    ErrorKind::InvalidInput,
+    ErrorKind::InvalidData,
+    ErrorKind::TimedOut,
+    ErrorKind::WriteZero,
+    ErrorKind::Interrupted,
+    ErrorKind::UnexpectedEof,
+    ErrorKind::OutOfMemory,
+];
+
+// The v0.6.0 runtime doesn't report kinds, all of its errors are treated as `Other`.
+#[cfg(not(feature = "unstable-host"))]
+unsafe fn io_error_kind(_error_id: u64) -> u32 {
+    0
+}
+
 /// An opaque error returned from host calls.
 ///
 /// Host calls can have a big number of failure reasons and it's impossible to enumerate all of
@@ -23,6 +55,27 @@ impl LunaticError {
     pub(crate) fn from(id: u64) -> Self {
         Self { id }
     }
+
+    /// Returns the kind of I/O error that caused this error.
+    ///
+    /// Errors that are not related to I/O, or have a kind without a more specific match, return
+    /// [`ErrorKind::Other`]. Without the `unstable-host` feature the kind is always `Other`.
+    pub fn kind(&self) -> ErrorKind {
+        let kind = unsafe { io_error_kind(self.id) } as usize;
+        match kind.checked_sub(1) {
+            Some(index) => IO_ERROR_KINDS
+                .get(index)
+                .copied()
+                .unwrap_or(ErrorKind::Other),
+            None => ErrorKind::Other,
+        }
+    }
+}
+
+impl From<LunaticError> for io::Error {
+    fn from(error: LunaticError) -> Self {
+        io::Error::new(error.kind(), error)
+    }
 }
 
 impl Debug for LunaticError {
@@ -0,0 +1,343 @@
+/*! Typed messages over byte streams, split into frames */
+
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    marker::PhantomData,
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    codec::{Codec, DecodeError, EncodeError, MessagePack},
+    error::LunaticError,
+    net::TcpStream,
+    process::{self, Process},
+    Mailbox,
+};
+
+/// Splits a byte stream into frames and turns them into values of type `T`.
+pub trait Framing<T> {
+    /// Appends the frame containing `value` to `buffer`.
+    fn encode(&mut self, value: &T, buffer: &mut Vec<u8>) -> Result<(), FrameError>;
+    /// Removes the first frame from `buffer` and decodes it, or returns `None` if the frame isn't
+    /// complete yet.
+    fn decode(&mut self, buffer: &mut Vec<u8>) -> Result<Option<T>, FrameError>;
+}
+
+/// Frames that start with their length as a 4 byte big-endian integer.
+///
+/// The content of a frame is encoded with the [`Codec`] `C`, by default MessagePack. Frames
+/// larger than the maximum frame size are rejected on both sides, before they are written or
+/// before the content is read into memory.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(bound = "")]
+pub struct LengthDelimited<C: Codec = MessagePack> {
+    max_frame_size: usize,
+    _codec: PhantomData<C>,
+}
+
+impl<C: Codec> Clone for LengthDelimited<C> {
+    fn clone(&self) -> Self {
+        Self {
+            max_frame_size: self.max_frame_size,
+            _codec: PhantomData,
+        }
+    }
+}
+
+impl Default for LengthDelimited {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LengthDelimited {
+    /// Creates length-delimited MessagePack framing that accepts frames up to 8 MiB.
+    pub fn new() -> Self {
+        Self::with_max_frame_size(8 * 1024 * 1024)
+    }
+
+    /// Creates length-delimited MessagePack framing that accepts frames up to
+    /// `max_frame_size` bytes.
+    pub fn with_max_frame_size(max_frame_size: usize) -> Self {
+        Self {
+            max_frame_size,
+            _codec: PhantomData,
+        }
+    }
+}
+
+impl<C: Codec> LengthDelimited<C> {
+    /// Switches to a different codec for the content of frames.
+    pub fn with_codec<D: Codec>(self) -> LengthDelimited<D> {
+        LengthDelimited {
+            max_frame_size: self.max_frame_size,
+            _codec: PhantomData,
+        }
+    }
+
+    /// Returns the largest accepted frame size in bytes, without the length prefix.
+    pub fn max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+}
+
+impl<T, C> Framing<T> for LengthDelimited<C>
+where
+    T: Serialize + DeserializeOwned,
+    C: Codec,
+{
+    fn encode(&mut self, value: &T, buffer: &mut Vec<u8>) -> Result<(), FrameError> {
+        let start = buffer.len();
+        // Reserve space for the length and fill it in after encoding.
+        buffer.extend_from_slice(&[0; 4]);
+        C::encode(buffer, value)?;
+        let size = buffer.len() - start - 4;
+        if size > self.max_frame_size || size > u32::MAX as usize {
+            buffer.truncate(start);
+            return Err(FrameError::TooLarge(size));
+        }
+        buffer[start..start + 4].copy_from_slice(&(size as u32).to_be_bytes());
+        Ok(())
+    }
+
+    fn decode(&mut self, buffer: &mut Vec<u8>) -> Result<Option<T>, FrameError> {
+        if buffer.len() < 4 {
+            return Ok(None);
+        }
+        let size = u32::from_be_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as usize;
+        if size > self.max_frame_size {
+            return Err(FrameError::TooLarge(size));
+        }
+        if buffer.len() < 4 + size {
+            return Ok(None);
+        }
+        let value = C::decode(&mut &buffer[4..4 + size])?;
+        buffer.drain(..4 + size);
+        Ok(Some(value))
+    }
+}
+
+/// Text frames that end with a line break.
+///
+/// Incoming lines can end with `\n` or `\r\n`, the line break is not part of the value. Outgoing
+/// lines end with `\n` and can't contain line breaks themselves.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lines {
+    max_line_length: usize,
+}
+
+impl Default for Lines {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Lines {
+    /// Creates line framing that accepts lines up to 64 KiB.
+    pub fn new() -> Self {
+        Self::with_max_line_length(64 * 1024)
+    }
+
+    /// Creates line framing that accepts lines up to `max_line_length` bytes.
+    pub fn with_max_line_length(max_line_length: usize) -> Self {
+        Self { max_line_length }
+    }
+}
+
+impl Framing<String> for Lines {
+    fn encode(&mut self, value: &String, buffer: &mut Vec<u8>) -> Result<(), FrameError> {
+        if value.contains('\n') {
+            return Err(FrameError::LineBreak);
+        }
+        if value.len() > self.max_line_length {
+            return Err(FrameError::TooLarge(value.len()));
+        }
+        buffer.extend_from_slice(value.as_bytes());
+        buffer.push(b'\n');
+        Ok(())
+    }
+
+    fn decode(&mut self, buffer: &mut Vec<u8>) -> Result<Option<String>, FrameError> {
+        let end = match buffer.iter().position(|&byte| byte == b'\n') {
+            Some(end) => end,
+            // The buffer can end with the `\r` of a line that has the maximum length.
+            None if buffer.len() > self.max_line_length + 1 => {
+                return Err(FrameError::TooLarge(buffer.len()))
+            }
+            None => return Ok(None),
+        };
+        let mut line: Vec<u8> = buffer.drain(..=end).collect();
+        line.pop();
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        if line.len() > self.max_line_length {
+            return Err(FrameError::TooLarge(line.len()));
+        }
+        String::from_utf8(line)
+            .map(Some)
+            .map_err(|_| FrameError::InvalidUtf8)
+    }
+}
+
+/// Error returned when sending or receiving a frame fails.
+#[derive(Error, Debug)]
+pub enum FrameError {
+    #[error("i/o error: {0}")]
+    Io(#[from] io::Error),
+    #[error("frame of {0} bytes exceeds the size limit")]
+    TooLarge(usize),
+    #[error("encoding failed: {0}")]
+    Encode(#[from] EncodeError),
+    #[error("decoding failed: {0}")]
+    Decode(#[from] DecodeError),
+    #[error("line is not valid UTF-8")]
+    InvalidUtf8,
+    #[error("line contains a line break")]
+    LineBreak,
+}
+
+/// Sends and receives values of type `T` over the stream `S`, split into frames by `F`.
+///
+/// By default values are sent as [`LengthDelimited`] MessagePack frames.
+///
+/// # Example
+///
+/// ```no_run
+/// use lunatic::{framed::Framed, net::TcpStream, Mailbox};
+///
+/// #[lunatic::main]
+/// fn main(_: Mailbox<()>) {
+///     let stream = TcpStream::connect("127.0.0.1:1337").unwrap();
+///     let mut framed = Framed::<_, (String, u32)>::new(stream);
+///     framed.send(&("ping".to_string(), 1)).unwrap();
+///     let reply = framed.receive().unwrap();
+///     println!("{:?}", reply);
+/// }
+/// ```
+pub struct Framed<S, T, F = LengthDelimited> {
+    stream: S,
+    framing: F,
+    // Data that was read from the stream, but doesn't form a complete frame yet.
+    buffer: Vec<u8>,
+    _phantom: PhantomData<T>,
+}
+
+impl<S, T> Framed<S, T, LengthDelimited>
+where
+    S: Read + Write,
+    T: Serialize + DeserializeOwned,
+{
+    /// Creates a framed stream with the default [`LengthDelimited`] framing.
+    pub fn new(stream: S) -> Self {
+        Self::with_framing(stream, LengthDelimited::new())
+    }
+}
+
+impl<S, T, F> Framed<S, T, F>
+where
+    S: Read + Write,
+    F: Framing<T>,
+{
+    /// Creates a framed stream with the given framing.
+    pub fn with_framing(stream: S, framing: F) -> Self {
+        Self {
+            stream,
+            framing,
+            buffer: Vec::new(),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Writes `value` as a single frame.
+    pub fn send(&mut self, value: &T) -> Result<(), FrameError> {
+        let mut frame = Vec::new();
+        self.framing.encode(value, &mut frame)?;
+        self.stream.write_all(&frame)?;
+        self.stream.flush()?;
+        Ok(())
+    }
+
+    /// Reads the next frame and decodes it.
+    ///
+    /// Returns `None` if the stream ended between two frames. If it ends in the middle of a frame
+    /// an [`UnexpectedEof`](ErrorKind::UnexpectedEof) error is returned.
+    pub fn receive(&mut self) -> Result<Option<T>, FrameError> {
+        let mut chunk = [0; 4096];
+        loop {
+            if let Some(value) = self.framing.decode(&mut self.buffer)? {
+                return Ok(Some(value));
+            }
+            let read = self.stream.read(&mut chunk)?;
+            if read == 0 {
+                if self.buffer.is_empty() {
+                    return Ok(None);
+                }
+                return Err(io::Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "stream ended inside a frame",
+                )
+                .into());
+            }
+            self.buffer.extend_from_slice(&chunk[..read]);
+        }
+    }
+
+    /// Returns a reference to the underlying stream.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Returns a mutable reference to the underlying stream.
+    ///
+    /// Reading from the stream directly can corrupt the framing.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Consumes the framed stream and returns the underlying stream.
+    ///
+    /// Data that was already read, but didn't form a complete frame, is lost.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<T, F> Framed<TcpStream, T, F>
+where
+    T: Serialize + DeserializeOwned,
+    F: Framing<T> + Serialize + DeserializeOwned + Clone,
+{
+    /// Spawns a process that reads frames from a clone of the stream and sends every decoded
+    /// value to `receiver`.
+    ///
+    /// The reader stops when the stream ends or a frame can't be read. It can be monitored to
+    /// find out when this happens. Data that was already read by this framed stream is handed
+    /// over to the reader, so no frames are lost. Writing is still done through `send`.
+    pub fn spawn_reader<C>(&mut self, receiver: Process<T, C>) -> Result<Process<()>, LunaticError>
+    where
+        C: Codec,
+    {
+        let buffer = std::mem::take(&mut self.buffer);
+        let context = (self.stream.clone(), self.framing.clone(), buffer, receiver);
+        process::spawn_with(context, reader::<T, F, C>)
+    }
+}
+
+// Entry point of reader processes.
+fn reader<T, F, C>(
+    (stream, framing, buffer, receiver): (TcpStream, F, Vec<u8>, Process<T, C>),
+    _: Mailbox<()>,
+) where
+    T: Serialize + DeserializeOwned,
+    F: Framing<T>,
+    C: Codec,
+{
+    let mut framed = Framed::with_framing(stream, framing);
+    framed.buffer = buffer;
+    while let Ok(Some(value)) = framed.receive() {
+        receiver.send(value);
+    }
+}
@@ -0,0 +1,229 @@
+/*! Named groups of processes that messages can be broadcast to */
+
+use std::{collections::HashMap, marker::PhantomData};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    environment::{lookup, start_registered},
+    error::LunaticError,
+    mailbox::{Mailbox, Message, TransformMailbox},
+    process::{self, Monitor, Process},
+    request::{Request, RequestError},
+};
+
+// The coordinator is registered under this name in the environment.
+const COORDINATOR_NAME: &str = "lunatic::group";
+const COORDINATOR_VERSION: &str = "1.0.0";
+const COORDINATOR_QUERY: &str = "^1";
+
+/// Starts the group coordinator for the current environment.
+///
+/// The coordinator keeps track of all groups and their members. It's registered in the
+/// environment, so that every process spawned into it can find it. The function needs to be
+/// called once before groups are used, e.g. at the beginning of `main`, and does nothing if the
+/// coordinator is already running.
+pub fn start() -> Result<(), GroupError> {
+    start_registered(
+        COORDINATOR_NAME,
+        COORDINATOR_VERSION,
+        COORDINATOR_QUERY,
+        || process::spawn(coordinator_entry),
+    )?;
+    Ok(())
+}
+
+fn coordinator() -> Option<Process<GroupMessage>> {
+    lookup(COORDINATOR_NAME, COORDINATOR_QUERY).expect("Valid semver query")
+}
+
+/// A named group of processes that receive messages of type `T`.
+///
+/// Processes can join and leave groups at any time. The coordinator monitors all members and
+/// removes them from all groups when they die. A failing coordinator doesn't take the members
+/// down with it. Every message that is broadcast to a group is
+/// sent to each member.
+///
+/// All processes using the same group name need to agree on the message type.
+///
+/// # Example
+///
+/// ```no_run
+/// use lunatic::{group::{self, Group}, process, Mailbox};
+///
+/// #[lunatic::main]
+/// fn main(mailbox: Mailbox<String>) {
+///     group::start().unwrap();
+///     let chat = Group::<String>::new("chat");
+///     chat.join(&process::this(&mailbox)).unwrap();
+///     chat.broadcast("Hello!".to_string()).unwrap();
+///     assert_eq!(mailbox.receive().unwrap(), "Hello!");
+/// }
+/// ```
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct Group<T: Serialize + DeserializeOwned> {
+    name: String,
+    _phantom: PhantomData<T>,
+}
+
+impl<T: Serialize + DeserializeOwned> Clone for Group<T> {
+    fn clone(&self) -> Self {
+        Self::new(&self.name)
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> Group<T> {
+    /// Returns a handle to the group with the name `name`.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Returns the name of the group.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Adds the process to the group.
+    ///
+    /// Joining a group the process is already a member of does nothing.
+    pub fn join(&self, process: &Process<T>) -> Result<(), GroupError> {
+        let member = process.clone().cast();
+        Ok(self.coordinator()?.request_wrapped(
+            (self.name.clone(), member),
+            GroupMessage::Join,
+            None,
+        )?)
+    }
+
+    /// Removes the process from the group.
+    pub fn leave(&self, process: &Process<T>) -> Result<(), GroupError> {
+        Ok(self.coordinator()?.request_wrapped(
+            (self.name.clone(), process.id()),
+            GroupMessage::Leave,
+            None,
+        )?)
+    }
+
+    /// Returns all current members of the group.
+    pub fn members(&self) -> Result<Vec<Process<T>>, GroupError> {
+        let members: Vec<Process<()>> =
+            self.coordinator()?
+                .request_wrapped(self.name.clone(), GroupMessage::Members, None)?;
+        Ok(members.into_iter().map(Process::cast).collect())
+    }
+
+    /// Sends a clone of the message to every member of the group.
+    pub fn broadcast(&self, message: T) -> Result<(), GroupError>
+    where
+        T: Clone,
+    {
+        for member in self.members()? {
+            member.send(message.clone());
+        }
+        Ok(())
+    }
+
+    fn coordinator(&self) -> Result<Process<GroupMessage>, GroupError> {
+        coordinator().ok_or(GroupError::NotStarted)
+    }
+}
+
+/// Represents an error while using a group.
+#[derive(Error, Debug)]
+pub enum GroupError {
+    #[error("Group coordinator is not started")]
+    NotStarted,
+    #[error("Failed to start group coordinator: {0}")]
+    StartFailed(#[from] LunaticError),
+    #[error("Group coordinator request failed: {0}")]
+    Request(#[from] RequestError),
+}
+
+// Messages understood by the group coordinator.
+#[derive(Serialize, Deserialize)]
+enum GroupMessage {
+    Join(Request<(String, Process<()>), ()>),
+    Leave(Request<(String, u128), ()>),
+    Members(Request<String, Vec<Process<()>>>),
+}
+
+// A process that is a member of at least one group.
+struct Member {
+    // Monitor of the coordinator on the member.
+    monitor: Monitor,
+    process: Process<()>,
+    groups: usize,
+}
+
+// Entry point of the group coordinator.
+fn coordinator_entry(mailbox: Mailbox<GroupMessage>) {
+    // Down notifications of members can only be received through a link mailbox.
+    let mailbox = mailbox.catch_link_panic();
+    let mut groups: HashMap<String, Vec<u128>> = HashMap::new();
+    let mut members: HashMap<u128, Member> = HashMap::new();
+    loop {
+        match mailbox.receive() {
+            Message::Normal(Ok(GroupMessage::Join(request))) => {
+                let (name, process) = request.data();
+                let id = process.id();
+                let group = groups.entry(name.clone()).or_default();
+                if !group.contains(&id) {
+                    group.push(id);
+                    let member = members.entry(id).or_insert_with(|| Member {
+                        monitor: process.monitor(&mailbox),
+                        process: process.clone(),
+                        groups: 0,
+                    });
+                    member.groups += 1;
+                }
+                request.reply(());
+            }
+            Message::Normal(Ok(GroupMessage::Leave(request))) => {
+                let (name, id) = request.data();
+                if let Some(group) = groups.get_mut(name) {
+                    if let Some(index) = group.iter().position(|member| member == id) {
+                        group.remove(index);
+                        let member = members.get_mut(id).unwrap();
+                        member.groups -= 1;
+                        if member.groups == 0 {
+                            member.process.demonitor(member.monitor);
+                            members.remove(id);
+                        }
+                    }
+                    if group.is_empty() {
+                        groups.remove(name);
+                    }
+                }
+                request.reply(());
+            }
+            Message::Normal(Ok(GroupMessage::Members(request))) => {
+                let group = groups
+                    .get(request.data())
+                    .map(|group| group.iter().map(|id| members[id].process.clone()).collect())
+                    .unwrap_or_default();
+                request.reply(group);
+            }
+            // Messages that can't be deserialized are dropped.
+            Message::Normal(Err(_)) => continue,
+            // A member died, remove it from all groups.
+            Message::Down {
+                monitor, process, ..
+            } => {
+                if !matches!(members.get(&process), Some(member) if member.monitor == monitor) {
+                    continue;
+                }
+                members.remove(&process);
+                groups.retain(|_, group| {
+                    group.retain(|member| *member != process);
+                    !group.is_empty()
+                });
+            }
+            Message::Signal(_, _) => continue,
+        }
+    }
+}
@@ -1,35 +1,79 @@
 // TODO: Move out into separate crate (lunatic-bindings?) & auto generate from lunatic's source?
 
+// Host functions that the lunatic v0.6.0 runtime doesn't provide are only imported with the
+// `unstable-host` feature, so that the default build can still be instantiated by it.
+
+// Native builds can replace the runtime with an in-process mock.
+#[cfg(feature = "mock")]
+pub use crate::mock::{error, message, networking, now, process, timer};
+
+// The current time. It's virtual when running inside of a simulation on the mock host.
+#[cfg(not(feature = "mock"))]
+pub fn now() -> std::time::Instant {
+    std::time::Instant::now()
+}
+
+#[cfg(not(feature = "mock"))]
 pub mod error {
     #[link(wasm_import_module = "lunatic::error")]
     extern "C" {
         pub fn string_size(error_id: u64) -> u32;
         pub fn to_string(error_id: u64, error_str: *mut u8);
+        #[cfg(feature = "unstable-host")]
+        pub fn io_error_kind(error_id: u64) -> u32;
         pub fn drop(error_id: u64);
     }
 }
 
+#[cfg(not(feature = "mock"))]
 pub mod message {
     #[link(wasm_import_module = "lunatic::message")]
     extern "C" {
         pub fn create_data(tag: i64, capacity: u64);
         pub fn write_data(data: *const u8, data_len: usize) -> usize;
         pub fn read_data(data: *mut u8, data_len: usize) -> usize;
-        #[allow(dead_code)]
         pub fn seek_data(position: u64);
         pub fn get_tag() -> i64;
-        #[allow(dead_code)]
         pub fn data_size() -> u64;
         pub fn push_process(process_id: u64) -> u64;
         pub fn take_process(index: u64) -> u64;
         pub fn push_tcp_stream(tcp_stream_id: u64) -> u64;
         pub fn take_tcp_stream(index: u64) -> u64;
+        #[cfg(feature = "unstable-host")]
+        pub fn push_tcp_listener(tcp_listener_id: u64) -> u64;
+        #[cfg(feature = "unstable-host")]
+        pub fn take_tcp_listener(index: u64) -> u64;
+        #[cfg(feature = "unstable-host")]
+        pub fn push_environment(env_id: u64) -> u64;
+        #[cfg(feature = "unstable-host")]
+        pub fn take_environment(index: u64) -> u64;
+        #[cfg(feature = "unstable-host")]
+        pub fn push_module(module_id: u64) -> u64;
+        #[cfg(feature = "unstable-host")]
+        pub fn take_module(index: u64) -> u64;
+        #[cfg(feature = "unstable-host")]
+        pub fn push_config(config_id: u64) -> u64;
+        #[cfg(feature = "unstable-host")]
+        pub fn take_config(index: u64) -> u64;
+        #[cfg(feature = "unstable-host")]
+        pub fn push_udp_socket(udp_socket_id: u64) -> u64;
+        #[cfg(feature = "unstable-host")]
+        pub fn take_udp_socket(index: u64) -> u64;
+        #[cfg(feature = "tls")]
+        pub fn push_tls_stream(tls_stream_id: u64) -> u64;
+        #[cfg(feature = "tls")]
+        pub fn take_tls_stream(index: u64) -> u64;
         pub fn send(process_id: u64);
         pub fn send_receive_skip_search(process_id: u64, timeout: u32) -> u32;
         pub fn receive(tag: i64, timeout: u32) -> u32;
+        #[cfg(feature = "unstable-host")]
+        pub fn signal_reason() -> u32;
+        #[cfg(feature = "unstable-host")]
+        pub fn signal_process_id(uuid: *mut [u8; 16]);
     }
 }
 
+#[cfg(not(feature = "mock"))]
 pub mod networking {
     #[link(wasm_import_module = "lunatic::networking")]
     extern "C" {
@@ -44,6 +88,15 @@ pub mod networking {
             flow_info: *mut u32,
             scope_id: *mut u32,
         ) -> u32;
+        #[cfg(feature = "unstable-host")]
+        pub fn resolve_reverse(
+            addr_type: u32,
+            addr: *const u8,
+            timeout: u32,
+            name: *mut u8,
+            name_len: usize,
+            opaque: *mut u64,
+        ) -> u32;
         pub fn tcp_bind(
             addr_type: u32,
             addr: *const u8,
@@ -80,9 +133,209 @@ pub mod networking {
             opaque: *mut u64,
         ) -> u32;
         pub fn tcp_flush(tcp_stream_id: u64, error_id: *mut u64) -> u32;
+        #[cfg(feature = "unstable-host")]
+        pub fn tcp_peek(
+            tcp_stream_id: u64,
+            buffer: *mut u8,
+            buffer_len: usize,
+            timeout: u32,
+            opaque: *mut u64,
+        ) -> u32;
+        #[cfg(feature = "unstable-host")]
+        pub fn tcp_shutdown(tcp_stream_id: u64, how: u32, error_id: *mut u64) -> u32;
+        #[cfg(feature = "unstable-host")]
+        pub fn tcp_local_addr(tcp_stream_id: u64, opaque: *mut u64) -> u32;
+        #[cfg(feature = "unstable-host")]
+        pub fn tcp_peer_addr(tcp_stream_id: u64, opaque: *mut u64) -> u32;
+        #[cfg(feature = "unstable-host")]
+        pub fn tcp_listener_local_addr(tcp_listener_id: u64, opaque: *mut u64) -> u32;
+        #[cfg(feature = "unstable-host")]
+        pub fn set_tcp_stream_nodelay(tcp_stream_id: u64, value: u32, error_id: *mut u64) -> u32;
+        #[cfg(feature = "unstable-host")]
+        pub fn get_tcp_stream_nodelay(tcp_stream_id: u64, opaque: *mut u64) -> u32;
+        #[cfg(feature = "unstable-host")]
+        pub fn set_tcp_stream_ttl(tcp_stream_id: u64, value: u32, error_id: *mut u64) -> u32;
+        #[cfg(feature = "unstable-host")]
+        pub fn get_tcp_stream_ttl(tcp_stream_id: u64, opaque: *mut u64) -> u32;
+        #[cfg(feature = "unstable-host")]
+        pub fn set_tcp_stream_keepalive(
+            tcp_stream_id: u64,
+            interval_ms: u64,
+            error_id: *mut u64,
+        ) -> u32;
+        #[cfg(feature = "unstable-host")]
+        pub fn get_tcp_stream_keepalive(tcp_stream_id: u64, opaque: *mut u64) -> u32;
+        #[cfg(feature = "unstable-host")]
+        pub fn udp_bind(
+            addr_type: u32,
+            addr: *const u8,
+            port: u32,
+            flow_info: u32,
+            scope_id: u32,
+            id: *mut u64,
+        ) -> u32;
+        #[cfg(feature = "unstable-host")]
+        pub fn drop_udp_socket(udp_socket_id: u64);
+        #[cfg(feature = "unstable-host")]
+        pub fn clone_udp_socket(udp_socket_id: u64) -> u64;
+        #[cfg(feature = "unstable-host")]
+        pub fn udp_connect(
+            udp_socket_id: u64,
+            addr_type: u32,
+            addr: *const u8,
+            port: u32,
+            flow_info: u32,
+            scope_id: u32,
+            error_id: *mut u64,
+        ) -> u32;
+        #[cfg(feature = "unstable-host")]
+        pub fn udp_send(
+            udp_socket_id: u64,
+            buffer: *const u8,
+            buffer_len: usize,
+            timeout: u32,
+            opaque: *mut u64,
+        ) -> u32;
+        #[cfg(feature = "unstable-host")]
+        pub fn udp_send_to(
+            udp_socket_id: u64,
+            buffer: *const u8,
+            buffer_len: usize,
+            addr_type: u32,
+            addr: *const u8,
+            port: u32,
+            flow_info: u32,
+            scope_id: u32,
+            timeout: u32,
+            opaque: *mut u64,
+        ) -> u32;
+        #[cfg(feature = "unstable-host")]
+        pub fn udp_receive(
+            udp_socket_id: u64,
+            buffer: *mut u8,
+            buffer_len: usize,
+            peek: u32,
+            timeout: u32,
+            opaque: *mut u64,
+        ) -> u32;
+        #[cfg(feature = "unstable-host")]
+        pub fn udp_receive_from(
+            udp_socket_id: u64,
+            buffer: *mut u8,
+            buffer_len: usize,
+            peek: u32,
+            timeout: u32,
+            opaque: *mut u64,
+            peer_dns_iter: *mut u64,
+        ) -> u32;
+        #[cfg(feature = "unstable-host")]
+        pub fn udp_local_addr(udp_socket_id: u64, opaque: *mut u64) -> u32;
+        #[cfg(feature = "unstable-host")]
+        pub fn udp_peer_addr(udp_socket_id: u64, opaque: *mut u64) -> u32;
+        #[cfg(feature = "unstable-host")]
+        pub fn set_udp_socket_broadcast(udp_socket_id: u64, value: u32, error_id: *mut u64) -> u32;
+        #[cfg(feature = "unstable-host")]
+        pub fn get_udp_socket_broadcast(udp_socket_id: u64, opaque: *mut u64) -> u32;
+        #[cfg(feature = "unstable-host")]
+        pub fn set_udp_socket_ttl(udp_socket_id: u64, value: u32, error_id: *mut u64) -> u32;
+        #[cfg(feature = "unstable-host")]
+        pub fn get_udp_socket_ttl(udp_socket_id: u64, opaque: *mut u64) -> u32;
+        #[cfg(feature = "unstable-host")]
+        pub fn set_udp_socket_multicast_loop_v4(
+            udp_socket_id: u64,
+            value: u32,
+            error_id: *mut u64,
+        ) -> u32;
+        #[cfg(feature = "unstable-host")]
+        pub fn get_udp_socket_multicast_loop_v4(udp_socket_id: u64, opaque: *mut u64) -> u32;
+        #[cfg(feature = "unstable-host")]
+        pub fn set_udp_socket_multicast_ttl_v4(
+            udp_socket_id: u64,
+            value: u32,
+            error_id: *mut u64,
+        ) -> u32;
+        #[cfg(feature = "unstable-host")]
+        pub fn get_udp_socket_multicast_ttl_v4(udp_socket_id: u64, opaque: *mut u64) -> u32;
+        #[cfg(feature = "unstable-host")]
+        pub fn set_udp_socket_multicast_loop_v6(
+            udp_socket_id: u64,
+            value: u32,
+            error_id: *mut u64,
+        ) -> u32;
+        #[cfg(feature = "unstable-host")]
+        pub fn get_udp_socket_multicast_loop_v6(udp_socket_id: u64, opaque: *mut u64) -> u32;
+        #[cfg(feature = "unstable-host")]
+        pub fn udp_join_multicast_v4(
+            udp_socket_id: u64,
+            multiaddr: *const u8,
+            interface: *const u8,
+            error_id: *mut u64,
+        ) -> u32;
+        #[cfg(feature = "unstable-host")]
+        pub fn udp_leave_multicast_v4(
+            udp_socket_id: u64,
+            multiaddr: *const u8,
+            interface: *const u8,
+            error_id: *mut u64,
+        ) -> u32;
+        #[cfg(feature = "unstable-host")]
+        pub fn udp_join_multicast_v6(
+            udp_socket_id: u64,
+            multiaddr: *const u8,
+            interface: u32,
+            error_id: *mut u64,
+        ) -> u32;
+        #[cfg(feature = "unstable-host")]
+        pub fn udp_leave_multicast_v6(
+            udp_socket_id: u64,
+            multiaddr: *const u8,
+            interface: u32,
+            error_id: *mut u64,
+        ) -> u32;
+        #[cfg(feature = "tls")]
+        pub fn tls_client_handshake(
+            tcp_stream_id: u64,
+            server_name: *const u8,
+            server_name_len: usize,
+            roots: *const u8,
+            roots_len: usize,
+            timeout: u32,
+            id: *mut u64,
+        ) -> u32;
+        #[cfg(feature = "tls")]
+        pub fn tls_server_handshake(
+            tcp_stream_id: u64,
+            cert_chain: *const u8,
+            cert_chain_len: usize,
+            key: *const u8,
+            key_len: usize,
+            timeout: u32,
+            id: *mut u64,
+        ) -> u32;
+        #[cfg(feature = "tls")]
+        pub fn drop_tls_stream(tls_stream_id: u64);
+        #[cfg(feature = "tls")]
+        pub fn tls_write_vectored(
+            tls_stream_id: u64,
+            ciovec_array: *const u32,
+            ciovec_array_len: usize,
+            timeout: u32,
+            opaque: *mut u64,
+        ) -> u32;
+        #[cfg(feature = "tls")]
+        pub fn tls_read(
+            tls_stream_id: u64,
+            buffer: *mut u8,
+            buffer_len: usize,
+            timeout: u32,
+            opaque: *mut u64,
+        ) -> u32;
+        #[cfg(feature = "tls")]
+        pub fn tls_flush(tls_stream_id: u64, timeout: u32, error_id: *mut u64) -> u32;
     }
 }
 
+#[cfg(not(feature = "mock"))]
 pub mod process {
     #[link(wasm_import_module = "lunatic::process")]
     extern "C" {
@@ -123,14 +376,22 @@ pub mod process {
             id: *mut u64,
         ) -> u32;
         pub fn drop_process(process_id: u64);
+        #[cfg(feature = "unstable-host")]
+        pub fn kill(process_id: u64);
         pub fn clone_process(process_id: u64) -> u64;
         pub fn sleep_ms(millis: u64);
         pub fn die_when_link_dies(trap: u32);
+        #[cfg(feature = "unstable-host")]
+        pub fn set_panic_message(message: *const u8, message_len: usize);
         pub fn this() -> u64;
         pub fn id(process_id: u64, uuid: *mut [u8; 16]);
         pub fn this_env() -> u64;
         pub fn link(tag: i64, process_id: u64);
         pub fn unlink(process_id: u64);
+        #[cfg(feature = "unstable-host")]
+        pub fn monitor(tag: i64, process_id: u64);
+        #[cfg(feature = "unstable-host")]
+        pub fn demonitor(tag: i64, process_id: u64);
         pub fn register(
             name: *const u8,
             name_len: usize,
@@ -155,3 +416,12 @@ pub mod process {
         ) -> u32;
     }
 }
+
+#[cfg(all(not(feature = "mock"), feature = "unstable-host"))]
+pub mod timer {
+    #[link(wasm_import_module = "lunatic::timer")]
+    extern "C" {
+        pub fn send_after(process_id: u64, delay: u64) -> u64;
+        pub fn cancel_timer(timer_id: u64) -> u32;
+    }
+}
@@ -0,0 +1,333 @@
+use std::{
+    io::{self, ErrorKind},
+    net::SocketAddr,
+    time::Duration,
+};
+
+use super::{
+    connection::{BodyLength, Connection},
+    HttpError, Method, Request, Response, Version,
+};
+use crate::net::{self, TcpStream};
+
+/// A blocking HTTP/1.1 client.
+///
+/// Connections are kept open after a response and reused by later requests to the same host and
+/// port. Because resources belong to a process, each process that makes requests needs its own
+/// client.
+///
+/// Redirects are followed up to a limit. `301`, `302` and `303` redirects of requests other than
+/// `GET` and `HEAD` are turned into `GET` requests without a body, `307` and `308` redirects
+/// repeat the original request. The `Authorization` and `Cookie` header fields are dropped when
+/// a redirect leads to a different host.
+///
+/// # Example
+///
+/// ```no_run
+/// use lunatic::{http::Client, Mailbox};
+///
+/// #[lunatic::main]
+/// fn main(_: Mailbox<()>) {
+///     let mut client = Client::new();
+///     let response = client.get("http://example.com/").unwrap();
+///     println!("{}", String::from_utf8_lossy(response.body()));
+/// }
+/// ```
+pub struct Client {
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    max_redirects: usize,
+    max_head_size: usize,
+    max_body_size: usize,
+    // Open connections, together with the `host:port` they are connected to.
+    idle: Vec<(String, Connection)>,
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Client {
+    /// Creates a client without open connections.
+    ///
+    /// By default connecting and each read and write time out after 30 seconds, up to 10
+    /// redirects are followed, response heads are limited to 16 KiB and bodies to 16 MiB.
+    pub fn new() -> Self {
+        Self {
+            connect_timeout: Some(Duration::from_secs(30)),
+            read_timeout: Some(Duration::from_secs(30)),
+            write_timeout: Some(Duration::from_secs(30)),
+            max_redirects: 10,
+            max_head_size: 16 * 1024,
+            max_body_size: 16 * 1024 * 1024,
+            idle: Vec::new(),
+        }
+    }
+
+    /// Sets the time resolving the host name and connecting can take.
+    ///
+    /// If the value specified is `None`, connecting blocks indefinitely.
+    pub fn set_connect_timeout(&mut self, timeout: Option<Duration>) {
+        self.connect_timeout = timeout;
+    }
+
+    /// Sets the read timeout of connections, see [`TcpStream::set_read_timeout`].
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.read_timeout = timeout;
+        // Open connections keep the old timeout.
+        self.idle.clear();
+    }
+
+    /// Sets the write timeout of connections, see [`TcpStream::set_write_timeout`].
+    pub fn set_write_timeout(&mut self, timeout: Option<Duration>) {
+        self.write_timeout = timeout;
+        self.idle.clear();
+    }
+
+    /// Sets how many redirects are followed. If it's 0, redirect responses are returned.
+    pub fn set_max_redirects(&mut self, max_redirects: usize) {
+        self.max_redirects = max_redirects;
+    }
+
+    /// Sets the largest accepted size of a response body in bytes.
+    pub fn set_max_body_size(&mut self, size: usize) {
+        self.max_body_size = size;
+    }
+
+    /// Sends a `GET` request to `url`.
+    pub fn get(&mut self, url: &str) -> Result<Response, HttpError> {
+        self.send(Request::new(Method::Get, url))
+    }
+
+    /// Sends a `POST` request with `body` to `url`.
+    pub fn post<B: Into<Vec<u8>>>(&mut self, url: &str, body: B) -> Result<Response, HttpError> {
+        let mut request = Request::new(Method::Post, url);
+        request.set_body(body);
+        self.send(request)
+    }
+
+    /// Sends `request` and returns the response after following redirects.
+    ///
+    /// The target of the request needs to be an absolute `http://` URL. A `Host` header field is
+    /// added if it's missing.
+    pub fn send(&mut self, mut request: Request) -> Result<Response, HttpError> {
+        let mut url = Url::parse(request.target())?;
+        let mut redirects = 0;
+        loop {
+            let response = self.send_once(&url, &request)?;
+            let location = match response.status() {
+                301 | 302 | 303 | 307 | 308 => response.header("Location"),
+                _ => None,
+            };
+            let location = match location {
+                Some(location) if redirects < self.max_redirects => location,
+                Some(_) if self.max_redirects > 0 => return Err(HttpError::TooManyRedirects),
+                _ => return Ok(response),
+            };
+            redirects += 1;
+
+            let next = url.join(location)?;
+            if next.authority() != url.authority() {
+                request.headers_mut().remove("Authorization");
+                request.headers_mut().remove("Cookie");
+                request.headers_mut().remove("Host");
+            }
+            let keep_method = matches!(response.status(), 307 | 308)
+                || matches!(request.method(), Method::Get | Method::Head);
+            if !keep_method {
+                request.set_method(Method::Get);
+                request.set_body(Vec::new());
+                request.headers_mut().remove("Content-Type");
+                request.headers_mut().remove("Content-Length");
+            }
+            request.set_target(&next.to_string());
+            url = next;
+        }
+    }
+
+    // Sends the request over an open connection if there is one, otherwise over a new one.
+    fn send_once(&mut self, url: &Url, request: &Request) -> Result<Response, HttpError> {
+        let authority = url.authority();
+        if let Some(index) = self.idle.iter().position(|(idle, _)| idle == &authority) {
+            let (_, connection) = self.idle.swap_remove(index);
+            // The server could have closed the connection in the meantime. Nothing was processed
+            // if it didn't respond, so the request can be retried on a new connection.
+            if let Some(response) = self.exchange(connection, url, request)? {
+                return Ok(response);
+            }
+        }
+        let connection = self.connect(url)?;
+        match self.exchange(connection, url, request)? {
+            Some(response) => Ok(response),
+            None => Err(HttpError::Io(io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "connection closed before the response",
+            ))),
+        }
+    }
+
+    fn connect(&self, url: &Url) -> Result<Connection, HttpError> {
+        let authority = url.authority();
+        let addrs: Vec<SocketAddr> = match self.connect_timeout {
+            Some(timeout) => net::resolve_timeout(&authority, timeout),
+            None => net::resolve(&authority),
+        }
+        .map_err(io::Error::from)?
+        .collect();
+        let mut stream = match self.connect_timeout {
+            Some(timeout) => TcpStream::connect_timeout(&addrs[..], timeout)?,
+            None => TcpStream::connect(&addrs[..])?,
+        };
+        stream.set_write_timeout(self.write_timeout);
+        Ok(Connection::new(stream, self.read_timeout))
+    }
+
+    // Writes the request and reads the response. Returns `None` if the connection was closed
+    // before the response started.
+    fn exchange(
+        &mut self,
+        mut connection: Connection,
+        url: &Url,
+        request: &Request,
+    ) -> Result<Option<Response>, HttpError> {
+        let mut request = request.clone();
+        if !request.headers().contains("Host") {
+            request.set_header("Host", &url.host_header());
+        }
+        if let Err(error) = request.write(connection.stream(), &url.path) {
+            return match error.kind() {
+                ErrorKind::BrokenPipe | ErrorKind::ConnectionReset => Ok(None),
+                _ => Err(error.into()),
+            };
+        }
+
+        // Informational responses are skipped, except for `101 Switching Protocols`.
+        let (mut response, version) = loop {
+            let head = match connection.read_head(self.max_head_size, None) {
+                Ok(Some(head)) => head,
+                Ok(None) => return Ok(None),
+                Err(HttpError::Io(error)) if error.kind() == ErrorKind::ConnectionReset => {
+                    return Ok(None)
+                }
+                Err(error) => return Err(error),
+            };
+            let (response, version) = Response::parse(&head)?;
+            if response.status() >= 200 || response.status() == 101 {
+                break (response, version);
+            }
+        };
+
+        let status = response.status();
+        let bodyless =
+            request.method() == &Method::Head || status < 200 || status == 204 || status == 304;
+        let length = match BodyLength::from_headers(response.headers())? {
+            _ if bodyless => BodyLength::Fixed(0),
+            Some(length) => length,
+            None => BodyLength::UntilClose,
+        };
+        let body = connection.read_body(length, self.max_body_size, None)?;
+        response.set_body(body);
+
+        let keep_alive = match version {
+            Version::Http10 => response.headers().has_token("Connection", "keep-alive"),
+            Version::Http11 => !response.headers().has_token("Connection", "close"),
+        };
+        if keep_alive && length != BodyLength::UntilClose && status != 101 {
+            self.idle.push((url.authority(), connection));
+        }
+        Ok(Some(response))
+    }
+}
+
+// The parts of an `http://` URL that are needed to send a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Url {
+    host: String,
+    port: u16,
+    // Path and query.
+    pub(crate) path: String,
+}
+
+impl Url {
+    pub(crate) fn parse(url: &str) -> Result<Self, HttpError> {
+        let rest = match url.split_once("://") {
+            Some((scheme, rest)) if scheme.eq_ignore_ascii_case("http") => rest,
+            Some(_) => return Err(HttpError::InvalidUrl("unsupported scheme")),
+            None => return Err(HttpError::InvalidUrl("missing scheme")),
+        };
+        // The fragment is never sent.
+        let rest = rest.split('#').next().unwrap_or_default();
+        let (authority, path) = match rest.find(&['/', '?'][..]) {
+            Some(index) => rest.split_at(index),
+            None => (rest, "/"),
+        };
+        let path = if path.starts_with('?') {
+            format!("/{}", path)
+        } else {
+            path.to_string()
+        };
+        // User information isn't supported and dropped.
+        let authority = authority.rsplit('@').next().unwrap_or_default();
+        let (host, port) = match authority.rfind(':') {
+            Some(index) if !authority[index..].contains(']') => {
+                let port = authority[index + 1..]
+                    .parse()
+                    .map_err(|_| HttpError::InvalidUrl("invalid port"))?;
+                (&authority[..index], port)
+            }
+            _ => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(HttpError::InvalidUrl("missing host"));
+        }
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            path,
+        })
+    }
+
+    // Resolves the `Location` of a redirect relative to this URL.
+    fn join(&self, location: &str) -> Result<Self, HttpError> {
+        if location.contains("://") {
+            return Url::parse(location);
+        }
+        if location.starts_with("//") {
+            return Url::parse(&format!("http:{}", location));
+        }
+        let path = if location.starts_with('/') {
+            location.to_string()
+        } else {
+            let path = self.path.split('?').next().unwrap_or_default();
+            let directory = &path[..path.rfind('/').map_or(0, |index| index + 1)];
+            format!("{}{}", directory, location)
+        };
+        Ok(Self {
+            host: self.host.clone(),
+            port: self.port,
+            path,
+        })
+    }
+
+    // Returns `host:port`, the name that is resolved when connecting.
+    pub(crate) fn authority(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    // Returns the value of the `Host` header field, which leaves out the default port.
+    pub(crate) fn host_header(&self) -> String {
+        match self.port {
+            80 => self.host.clone(),
+            port => format!("{}:{}", self.host, port),
+        }
+    }
+}
+
+impl std::fmt::Display for Url {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "http://{}{}", self.host_header(), self.path)
+    }
+}
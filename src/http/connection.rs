@@ -0,0 +1,238 @@
+use std::{
+    io::{self, ErrorKind, Read},
+    time::{Duration, Instant},
+};
+
+use super::{Headers, HttpError};
+use crate::{host_api, net::TcpStream};
+
+// Longest line accepted inside of a chunked body.
+const MAX_LINE: usize = 4096;
+
+// How the length of a message body is determined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BodyLength {
+    Fixed(usize),
+    Chunked,
+    // Only used for responses without a length, the body ends when the connection is closed.
+    UntilClose,
+}
+
+impl BodyLength {
+    // Reads the body length from the `Transfer-Encoding` and `Content-Length` headers, or
+    // returns `None` if the message doesn't define one.
+    pub(crate) fn from_headers(headers: &Headers) -> Result<Option<Self>, HttpError> {
+        if let Some(coding) = headers
+            .get_all("Transfer-Encoding")
+            .flat_map(|value| value.split(','))
+            .last()
+        {
+            if coding.trim().eq_ignore_ascii_case("chunked") {
+                return Ok(Some(BodyLength::Chunked));
+            }
+            return Ok(Some(BodyLength::UntilClose));
+        }
+        let mut length = None;
+        for value in headers.get_all("Content-Length") {
+            let value = value
+                .trim()
+                .parse()
+                .map_err(|_| HttpError::Malformed("invalid content length"))?;
+            if matches!(length.replace(value), Some(previous) if previous != value) {
+                return Err(HttpError::Malformed("conflicting content lengths"));
+            }
+        }
+        Ok(length.map(BodyLength::Fixed))
+    }
+}
+
+// A TCP stream with a read buffer, so that data belonging to the next message on a kept alive
+// connection isn't lost.
+pub(crate) struct Connection {
+    stream: TcpStream,
+    read_timeout: Option<Duration>,
+    buffer: Vec<u8>,
+}
+
+impl Connection {
+    pub(crate) fn new(stream: TcpStream, read_timeout: Option<Duration>) -> Self {
+        Self {
+            stream,
+            read_timeout,
+            buffer: Vec::new(),
+        }
+    }
+
+    pub(crate) fn stream(&mut self) -> &mut TcpStream {
+        &mut self.stream
+    }
+
+    // Returns the stream and the data that was read, but not consumed yet.
+    pub(crate) fn into_parts(self) -> (TcpStream, Vec<u8>) {
+        (self.stream, self.buffer)
+    }
+
+    // Reads the head of the next message, including the empty line that ends it. Returns `None`
+    // if the connection was closed before the message started.
+    //
+    // If a `deadline` is given, the whole head needs to arrive before it. This keeps clients that
+    // send the head one byte at a time from occupying the connection forever.
+    pub(crate) fn read_head(
+        &mut self,
+        max_size: usize,
+        deadline: Option<Instant>,
+    ) -> Result<Option<Vec<u8>>, HttpError> {
+        let mut searched = 0;
+        loop {
+            // Empty lines in front of a message are ignored.
+            while self.buffer.starts_with(b"\r\n") {
+                self.buffer.drain(..2);
+            }
+            if let Some(end) = find(&self.buffer[searched..], b"\r\n\r\n") {
+                let end = searched + end + 4;
+                return Ok(Some(self.buffer.drain(..end).collect()));
+            }
+            if self.buffer.len() > max_size {
+                return Err(HttpError::HeadTooLarge);
+            }
+            searched = self.buffer.len().saturating_sub(3);
+            if self.fill(deadline)? == 0 {
+                if self.buffer.is_empty() {
+                    return Ok(None);
+                }
+                return Err(unexpected_eof().into());
+            }
+        }
+    }
+
+    // Reads a message body of the given length. If a `deadline` is given, the whole body needs
+    // to arrive before it, the same as the head for `read_head`.
+    pub(crate) fn read_body(
+        &mut self,
+        length: BodyLength,
+        max_size: usize,
+        deadline: Option<Instant>,
+    ) -> Result<Vec<u8>, HttpError> {
+        match length {
+            BodyLength::Fixed(length) => {
+                if length > max_size {
+                    return Err(HttpError::BodyTooLarge);
+                }
+                self.take(length, deadline)
+            }
+            BodyLength::Chunked => self.read_chunked(max_size, deadline),
+            BodyLength::UntilClose => {
+                while self.fill(deadline)? > 0 {
+                    if self.buffer.len() > max_size {
+                        return Err(HttpError::BodyTooLarge);
+                    }
+                }
+                Ok(std::mem::take(&mut self.buffer))
+            }
+        }
+    }
+
+    fn read_chunked(
+        &mut self,
+        max_size: usize,
+        deadline: Option<Instant>,
+    ) -> Result<Vec<u8>, HttpError> {
+        let mut body = Vec::new();
+        loop {
+            let line = self.read_line(deadline)?;
+            // Chunk extensions after a `;` are ignored.
+            let size = line.split(|&byte| byte == b';').next().unwrap_or_default();
+            let size = std::str::from_utf8(size)
+                .ok()
+                .and_then(|size| usize::from_str_radix(size.trim(), 16).ok())
+                .ok_or(HttpError::Malformed("invalid chunk size"))?;
+            if size == 0 {
+                break;
+            }
+            if !matches!(body.len().checked_add(size), Some(total) if total <= max_size) {
+                return Err(HttpError::BodyTooLarge);
+            }
+            body.extend(self.take(size, deadline)?);
+            if self.take(2, deadline)? != b"\r\n" {
+                return Err(HttpError::Malformed("missing line break after chunk"));
+            }
+        }
+        // Trailer fields are dropped.
+        while !self.read_line(deadline)?.is_empty() {}
+        Ok(body)
+    }
+
+    // Reads a line without the line break.
+    fn read_line(&mut self, deadline: Option<Instant>) -> Result<Vec<u8>, HttpError> {
+        let mut searched = 0;
+        loop {
+            if let Some(end) = find(&self.buffer[searched..], b"\n") {
+                let end = searched + end;
+                let mut line: Vec<u8> = self.buffer.drain(..=end).collect();
+                line.pop();
+                if line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+                return Ok(line);
+            }
+            if self.buffer.len() > MAX_LINE {
+                return Err(HttpError::Malformed("line too long"));
+            }
+            searched = self.buffer.len();
+            if self.fill(deadline)? == 0 {
+                return Err(unexpected_eof().into());
+            }
+        }
+    }
+
+    // Takes exactly `length` bytes out of the stream.
+    fn take(&mut self, length: usize, deadline: Option<Instant>) -> Result<Vec<u8>, HttpError> {
+        while self.buffer.len() < length {
+            if self.fill(deadline)? == 0 {
+                return Err(unexpected_eof().into());
+            }
+        }
+        Ok(self.buffer.drain(..length).collect())
+    }
+
+    // Reads more data into the buffer and returns how much was read. Each read waits at most for
+    // the read timeout and never past the `deadline`.
+    fn fill(&mut self, deadline: Option<Instant>) -> io::Result<usize> {
+        let mut timeout = self.read_timeout;
+        if let Some(deadline) = deadline {
+            let remaining = deadline.saturating_duration_since(host_api::now());
+            if remaining.is_zero() {
+                return Err(ErrorKind::TimedOut.into());
+            }
+            timeout = Some(timeout.map_or(remaining, |timeout| timeout.min(remaining)));
+        }
+        self.stream.set_read_timeout(timeout);
+        let mut chunk = [0; 4096];
+        let read = self.stream.read(&mut chunk)?;
+        self.buffer.extend_from_slice(&chunk[..read]);
+        Ok(read)
+    }
+}
+
+// Returns `true` if the error was caused by a read timeout.
+pub(crate) fn is_timeout(error: &HttpError) -> bool {
+    match error {
+        HttpError::Io(error) => {
+            matches!(error.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock)
+        }
+        _ => false,
+    }
+}
+
+fn unexpected_eof() -> io::Error {
+    io::Error::new(
+        ErrorKind::UnexpectedEof,
+        "connection closed in the middle of a message",
+    )
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
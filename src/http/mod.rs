@@ -0,0 +1,236 @@
+/*! HTTP/1.1 server with a process per connection, and a client
+
+Requests are routed to handler functions by a [`Router`] and served by a [`Server`]. Every
+accepted connection is handled inside of a freshly spawned process, so a panicking handler only
+closes its own connection. Connections are kept alive between requests, request bodies can be
+sent with a `Content-Length` or chunked, and responses can be streamed in chunks.
+
+Other HTTP services can be called with a [`Client`].
+
+```no_run
+use lunatic::{
+    http::{Request, Response, Router, Server},
+    net::TcpListener,
+    Mailbox,
+};
+
+#[lunatic::main]
+fn main(_: Mailbox<()>) {
+    let mut router = Router::new();
+    router.get("/", index);
+    router.get("/hello/:name", hello);
+    let listener = TcpListener::bind("127.0.0.1:8080").unwrap();
+    Server::new(router).serve(&listener).unwrap();
+}
+
+fn index(_: Request) -> Response {
+    Response::text(200, "Welcome!")
+}
+
+fn hello(request: Request) -> Response {
+    Response::text(200, &format!("Hello {}!", request.param("name").unwrap()))
+}
+```
+*/
+
+mod client;
+mod connection;
+mod request;
+mod response;
+mod router;
+mod server;
+
+use std::{fmt, io};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+pub use client::Client;
+pub(crate) use client::Url;
+pub(crate) use connection::Connection;
+pub use request::Request;
+pub use response::Response;
+pub use router::{Handler, Router};
+pub use server::Server;
+
+/// Error returned when reading or writing an HTTP message fails.
+#[derive(Error, Debug)]
+pub enum HttpError {
+    #[error("i/o error: {0}")]
+    Io(#[from] io::Error),
+    #[error("malformed message: {0}")]
+    Malformed(&'static str),
+    #[error("message head exceeds the size limit")]
+    HeadTooLarge,
+    #[error("message body exceeds the size limit")]
+    BodyTooLarge,
+    #[error("invalid url: {0}")]
+    InvalidUrl(&'static str),
+    #[error("too many redirects")]
+    TooManyRedirects,
+}
+
+/// The method of a request.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+    Connect,
+    Options,
+    Trace,
+    Patch,
+    /// Any other method, e.g. from WebDAV.
+    Other(String),
+}
+
+impl Method {
+    /// Returns the method as it appears in a request line.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Method::Get => "GET",
+            Method::Head => "HEAD",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+            Method::Connect => "CONNECT",
+            Method::Options => "OPTIONS",
+            Method::Trace => "TRACE",
+            Method::Patch => "PATCH",
+            Method::Other(method) => method,
+        }
+    }
+}
+
+impl From<&str> for Method {
+    fn from(method: &str) -> Self {
+        match method {
+            "GET" => Method::Get,
+            "HEAD" => Method::Head,
+            "POST" => Method::Post,
+            "PUT" => Method::Put,
+            "DELETE" => Method::Delete,
+            "CONNECT" => Method::Connect,
+            "OPTIONS" => Method::Options,
+            "TRACE" => Method::Trace,
+            "PATCH" => Method::Patch,
+            other => Method::Other(other.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The protocol version of a message.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    Http10,
+    Http11,
+}
+
+impl Version {
+    fn from_minor(minor: u8) -> Self {
+        match minor {
+            0 => Version::Http10,
+            _ => Version::Http11,
+        }
+    }
+}
+
+/// A list of header fields.
+///
+/// Names are compared case-insensitively and keep the order in which they were added. Control
+/// characters, like line breaks, are removed from added names and values, so that a field can't
+/// end early and inject others into a message. Tabs are kept in values, and whitespace and colons
+/// are removed from names.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Headers {
+    fields: Vec<(String, String)>,
+}
+
+impl Headers {
+    /// Creates an empty header list.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the value of the first field called `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the values of all fields called `name`.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.fields
+            .iter()
+            .filter(move |(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns `true` if a field called `name` exists.
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Replaces all fields called `name` with a single one.
+    pub fn insert(&mut self, name: &str, value: &str) {
+        let name = field_name(name);
+        self.remove(&name);
+        self.append(&name, value);
+    }
+
+    /// Adds a field, keeping the existing ones with the same name.
+    pub fn append(&mut self, name: &str, value: &str) {
+        let value = value
+            .chars()
+            .filter(|c| !c.is_control() || *c == '\t')
+            .collect();
+        self.fields.push((field_name(name), value));
+    }
+
+    /// Removes all fields called `name`.
+    pub fn remove(&mut self, name: &str) {
+        self.fields
+            .retain(|(field, _)| !field.eq_ignore_ascii_case(name));
+    }
+
+    /// Returns an iterator over all `(name, value)` pairs.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.fields
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// Returns the number of fields.
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// Returns `true` if there are no fields.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    // Returns `true` if the comma separated list in field `name` contains `token`.
+    pub(crate) fn has_token(&self, name: &str, token: &str) -> bool {
+        self.get_all(name)
+            .flat_map(|value| value.split(','))
+            .any(|item| item.trim().eq_ignore_ascii_case(token))
+    }
+}
+
+// Removes the characters that aren't allowed in field names.
+fn field_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_ascii_graphic() && *c != ':')
+        .collect()
+}
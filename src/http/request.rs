@@ -0,0 +1,198 @@
+use std::{
+    io::{self, Write},
+    net::SocketAddr,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{Headers, HttpError, Method, Version};
+
+// Most header fields accepted in a single message.
+pub(crate) const MAX_HEADERS: usize = 64;
+
+/// An HTTP request.
+///
+/// The server reads the body completely before the request is handed to a handler. Requests sent
+/// with a [`Client`](super::Client) use an absolute URL as the target.
+///
+/// # Example
+///
+/// ```
+/// use lunatic::http::{Method, Request};
+///
+/// let mut request = Request::new(Method::Post, "http://localhost:8080/users");
+/// request.set_header("Content-Type", "application/json");
+/// request.set_body(r#"{"name":"lunatic"}"#);
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    method: Method,
+    target: String,
+    version: Version,
+    headers: Headers,
+    body: Vec<u8>,
+    params: Vec<(String, String)>,
+    peer_addr: Option<SocketAddr>,
+}
+
+impl Request {
+    /// Creates an HTTP/1.1 request without header fields and with an empty body.
+    pub fn new(method: Method, target: &str) -> Self {
+        Self {
+            method,
+            target: target.to_string(),
+            version: Version::Http11,
+            headers: Headers::new(),
+            body: Vec::new(),
+            params: Vec::new(),
+            peer_addr: None,
+        }
+    }
+
+    // Parses the request line and header fields.
+    pub(crate) fn parse(head: &[u8]) -> Result<Self, HttpError> {
+        let mut fields = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut request = httparse::Request::new(&mut fields);
+        match request.parse(head) {
+            Ok(httparse::Status::Complete(_)) => {}
+            Ok(httparse::Status::Partial) => return Err(HttpError::Malformed("incomplete head")),
+            Err(httparse::Error::TooManyHeaders) => return Err(HttpError::HeadTooLarge),
+            Err(_) => return Err(HttpError::Malformed("invalid request head")),
+        }
+        let mut headers = Headers::new();
+        for field in request.headers.iter() {
+            headers.append(field.name, &String::from_utf8_lossy(field.value));
+        }
+        Ok(Self {
+            // A complete parse always contains the method, target and version.
+            method: Method::from(request.method.unwrap_or_default()),
+            target: request.path.unwrap_or_default().to_string(),
+            version: Version::from_minor(request.version.unwrap_or_default()),
+            headers,
+            body: Vec::new(),
+            params: Vec::new(),
+            peer_addr: None,
+        })
+    }
+
+    pub(crate) fn set_method(&mut self, method: Method) {
+        self.method = method;
+    }
+
+    pub(crate) fn set_target(&mut self, target: &str) {
+        self.target = target.to_string();
+    }
+
+    pub(crate) fn set_params(&mut self, params: Vec<(String, String)>) {
+        self.params = params;
+    }
+
+    pub(crate) fn set_peer_addr(&mut self, peer_addr: SocketAddr) {
+        self.peer_addr = Some(peer_addr);
+    }
+
+    /// Returns the request method.
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// Returns the request target as it was sent, including the query.
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    /// Returns the path of the request target, without the query.
+    pub fn path(&self) -> &str {
+        self.target.split('?').next().unwrap_or_default()
+    }
+
+    /// Returns the query of the request target, the part after `?`.
+    pub fn query(&self) -> Option<&str> {
+        self.target.split_once('?').map(|(_, query)| query)
+    }
+
+    /// Returns the protocol version of the request.
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// Returns all header fields.
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    /// Returns the header fields for modification.
+    pub fn headers_mut(&mut self) -> &mut Headers {
+        &mut self.headers
+    }
+
+    /// Returns the value of the first header field called `name`.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
+    }
+
+    /// Sets the header field `name`, replacing existing fields with the same name.
+    pub fn set_header(&mut self, name: &str, value: &str) {
+        self.headers.insert(name, value);
+    }
+
+    /// Returns the request body.
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Sets the body.
+    pub fn set_body<B: Into<Vec<u8>>>(&mut self, body: B) {
+        self.body = body.into();
+    }
+
+    /// Consumes the request and returns the body.
+    pub fn into_body(self) -> Vec<u8> {
+        self.body
+    }
+
+    /// Returns the value of a path parameter captured by the [`Router`](super::Router).
+    ///
+    /// For the route `/users/:id` and the path `/users/42`, `param("id")` returns `"42"`.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the address of the client that sent the request.
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    // Returns `true` if the client wants to keep the connection open after the response.
+    pub(crate) fn keep_alive(&self) -> bool {
+        match self.version {
+            Version::Http10 => self.headers.has_token("Connection", "keep-alive"),
+            Version::Http11 => !self.headers.has_token("Connection", "close"),
+        }
+    }
+
+    // Writes the request with `target` in the request line. The `Content-Length` header is set
+    // if there is a body, or the method usually expects one.
+    pub(crate) fn write<W: Write>(&self, writer: &mut W, target: &str) -> io::Result<()> {
+        let mut headers = self.headers.clone();
+        let expects_body = matches!(self.method, Method::Post | Method::Put | Method::Patch);
+        if !self.body.is_empty() || expects_body {
+            headers.remove("Transfer-Encoding");
+            headers.insert("Content-Length", &self.body.len().to_string());
+        }
+        let mut head = format!("{} {} HTTP/1.1\r\n", self.method, target);
+        for (name, value) in headers.iter() {
+            head.push_str(name);
+            head.push_str(": ");
+            head.push_str(value);
+            head.push_str("\r\n");
+        }
+        head.push_str("\r\n");
+        writer.write_all(head.as_bytes())?;
+        writer.write_all(&self.body)?;
+        writer.flush()
+    }
+}
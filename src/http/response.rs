@@ -0,0 +1,242 @@
+use std::{
+    fmt,
+    io::{self, Write},
+};
+
+use super::{request::MAX_HEADERS, Headers, HttpError, Version};
+
+/// An HTTP response.
+///
+/// Responses are built by handlers and written to the connection by the
+/// [`Server`](super::Server). Unless the body is sent in chunks, the `Content-Length` header is
+/// set automatically. Responses returned from a [`Client`](super::Client) always contain the
+/// complete body.
+///
+/// # Example
+///
+/// ```
+/// use lunatic::http::Response;
+///
+/// let mut response = Response::new(201);
+/// response.set_header("Content-Type", "application/json");
+/// response.set_body(r#"{"id":1}"#);
+/// ```
+#[derive(Debug)]
+pub struct Response {
+    status: u16,
+    headers: Headers,
+    body: Body,
+}
+
+enum Body {
+    Full(Vec<u8>),
+    Chunked(Box<dyn Iterator<Item = Vec<u8>>>),
+}
+
+impl fmt::Debug for Body {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Body::Full(body) => f.debug_tuple("Full").field(&body.len()).finish(),
+            Body::Chunked(_) => f.write_str("Chunked"),
+        }
+    }
+}
+
+impl Response {
+    /// Creates a response with the status code `status` and an empty body.
+    pub fn new(status: u16) -> Self {
+        Self {
+            status,
+            headers: Headers::new(),
+            body: Body::Full(Vec::new()),
+        }
+    }
+
+    /// Creates a response with a plain text body.
+    pub fn text(status: u16, text: &str) -> Self {
+        let mut response = Self::new(status);
+        response.set_header("Content-Type", "text/plain; charset=utf-8");
+        response.set_body(text);
+        response
+    }
+
+    // Parses the status line and header fields.
+    pub(crate) fn parse(head: &[u8]) -> Result<(Self, Version), HttpError> {
+        let mut fields = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut response = httparse::Response::new(&mut fields);
+        match response.parse(head) {
+            Ok(httparse::Status::Complete(_)) => {}
+            Ok(httparse::Status::Partial) => return Err(HttpError::Malformed("incomplete head")),
+            Err(httparse::Error::TooManyHeaders) => return Err(HttpError::HeadTooLarge),
+            Err(_) => return Err(HttpError::Malformed("invalid response head")),
+        }
+        let mut headers = Headers::new();
+        for field in response.headers.iter() {
+            headers.append(field.name, &String::from_utf8_lossy(field.value));
+        }
+        let version = Version::from_minor(response.version.unwrap_or_default());
+        let response = Self {
+            status: response.code.unwrap_or_default(),
+            headers,
+            body: Body::Full(Vec::new()),
+        };
+        Ok((response, version))
+    }
+
+    /// Returns the status code.
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// Sets the status code.
+    pub fn set_status(&mut self, status: u16) {
+        self.status = status;
+    }
+
+    /// Returns all header fields.
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    /// Returns the header fields for modification.
+    pub fn headers_mut(&mut self) -> &mut Headers {
+        &mut self.headers
+    }
+
+    /// Returns the value of the first header field called `name`.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
+    }
+
+    /// Sets the header field `name`, replacing existing fields with the same name.
+    pub fn set_header(&mut self, name: &str, value: &str) {
+        self.headers.insert(name, value);
+    }
+
+    /// Returns the body, or an empty slice if the body is sent in chunks.
+    pub fn body(&self) -> &[u8] {
+        match &self.body {
+            Body::Full(body) => body,
+            Body::Chunked(_) => &[],
+        }
+    }
+
+    /// Consumes the response and returns the body, or an empty vector if the body is sent in
+    /// chunks.
+    pub fn into_body(self) -> Vec<u8> {
+        match self.body {
+            Body::Full(body) => body,
+            Body::Chunked(_) => Vec::new(),
+        }
+    }
+
+    /// Sets the body.
+    pub fn set_body<B: Into<Vec<u8>>>(&mut self, body: B) {
+        self.body = Body::Full(body.into());
+    }
+
+    /// Sends the body with the chunked transfer coding.
+    ///
+    /// Each item of `chunks` is written as soon as the iterator produces it, so large or slowly
+    /// generated bodies don't need to be kept in memory. Empty chunks are skipped. Clients that
+    /// only speak HTTP/1.0 get all chunks joined together.
+    pub fn set_chunks<I>(&mut self, chunks: I)
+    where
+        I: IntoIterator<Item = Vec<u8>>,
+        I::IntoIter: 'static,
+    {
+        self.body = Body::Chunked(Box::new(chunks.into_iter()));
+    }
+
+    // Writes the response. The body is left out if `head_only` is set, e.g. for `HEAD`
+    // requests. Chunked bodies are joined together if the client doesn't support `chunked`.
+    pub(crate) fn write<W: Write>(
+        self,
+        writer: &mut W,
+        head_only: bool,
+        chunked: bool,
+    ) -> io::Result<()> {
+        let Response {
+            status,
+            mut headers,
+            body,
+        } = self;
+        let body = match body {
+            Body::Chunked(chunks) if !chunked => Body::Full(chunks.flatten().collect()),
+            body => body,
+        };
+        // Informational, `204 No Content` and `304 Not Modified` responses never have a body.
+        let bodyless = status < 200 || status == 204 || status == 304;
+        match &body {
+            Body::Full(_) if bodyless => {}
+            Body::Full(body) => headers.insert("Content-Length", &body.len().to_string()),
+            Body::Chunked(_) => {
+                headers.remove("Content-Length");
+                headers.insert("Transfer-Encoding", "chunked");
+            }
+        }
+
+        let mut head = format!("HTTP/1.1 {} {}\r\n", status, reason(status));
+        for (name, value) in headers.iter() {
+            head.push_str(name);
+            head.push_str(": ");
+            head.push_str(value);
+            head.push_str("\r\n");
+        }
+        head.push_str("\r\n");
+        writer.write_all(head.as_bytes())?;
+        if head_only || bodyless {
+            return writer.flush();
+        }
+
+        match body {
+            Body::Full(body) => writer.write_all(&body)?,
+            Body::Chunked(chunks) => {
+                for chunk in chunks.filter(|chunk| !chunk.is_empty()) {
+                    writer.write_all(format!("{:x}\r\n", chunk.len()).as_bytes())?;
+                    writer.write_all(&chunk)?;
+                    writer.write_all(b"\r\n")?;
+                }
+                writer.write_all(b"0\r\n\r\n")?;
+            }
+        }
+        writer.flush()
+    }
+}
+
+// Returns the reason phrase of common status codes.
+fn reason(status: u16) -> &'static str {
+    match status {
+        100 => "Continue",
+        101 => "Switching Protocols",
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        204 => "No Content",
+        206 => "Partial Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        303 => "See Other",
+        304 => "Not Modified",
+        307 => "Temporary Redirect",
+        308 => "Permanent Redirect",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        409 => "Conflict",
+        411 => "Length Required",
+        413 => "Payload Too Large",
+        415 => "Unsupported Media Type",
+        429 => "Too Many Requests",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        _ => "",
+    }
+}
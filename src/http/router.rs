@@ -0,0 +1,201 @@
+use serde::{Deserialize, Serialize};
+
+use super::{Method, Request, Response};
+
+/// A function that turns a request into a response.
+///
+/// Handlers are plain functions, because they are called from the processes that handle
+/// connections and can't capture any state of the process that set up the router.
+pub type Handler = fn(Request) -> Response;
+
+/// Dispatches requests to handlers based on the method and path.
+///
+/// Routes are matched in the order they were added. Each segment of a route pattern is either
+/// matched literally, or captures a path parameter:
+///
+/// * `:name` matches a single segment, e.g. `/users/:id` matches `/users/42`.
+/// * `*name` matches the rest of the path, e.g. `/files/*path` matches `/files/a/b.txt`.
+///
+/// Captured values are available through [`Request::param`]. `HEAD` requests are handled by
+/// `GET` routes if there is no `HEAD` route. If only the path matches, the router responds with
+/// `405 Method Not Allowed`, otherwise the fallback handler is called, which responds with
+/// `404 Not Found` by default.
+///
+/// Routers are sent to connection processes and hold function pointers, so they can only be
+/// used by processes spawned from the same module.
+#[derive(Clone)]
+pub struct Router {
+    routes: Vec<Route>,
+    fallback: Handler,
+}
+
+// A router on its way to a connection process. Only the `http` module can create and read them,
+// so handlers are only read back from messages that `Server::serve` sent.
+#[derive(Serialize, Deserialize)]
+pub(super) struct SentRouter {
+    routes: Vec<Route>,
+    #[serde(with = "crate::process::function")]
+    fallback: Handler,
+}
+
+impl From<Router> for SentRouter {
+    fn from(Router { routes, fallback }: Router) -> Self {
+        Self { routes, fallback }
+    }
+}
+
+impl From<SentRouter> for Router {
+    fn from(SentRouter { routes, fallback }: SentRouter) -> Self {
+        Self { routes, fallback }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Route {
+    method: Method,
+    segments: Vec<Segment>,
+    #[serde(with = "crate::process::function")]
+    handler: Handler,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+enum Segment {
+    Literal(String),
+    Param(String),
+    Rest(String),
+}
+
+impl Default for Router {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Router {
+    /// Creates a router without routes.
+    pub fn new() -> Self {
+        Self {
+            routes: Vec::new(),
+            fallback: not_found,
+        }
+    }
+
+    /// Adds a route for `method` and the path `pattern`.
+    pub fn add_route(&mut self, method: Method, pattern: &str, handler: Handler) {
+        let segments = segments(pattern)
+            .map(|segment| {
+                if let Some(name) = segment.strip_prefix(':') {
+                    Segment::Param(name.to_string())
+                } else if let Some(name) = segment.strip_prefix('*') {
+                    Segment::Rest(name.to_string())
+                } else {
+                    Segment::Literal(segment.to_string())
+                }
+            })
+            .collect();
+        self.routes.push(Route {
+            method,
+            segments,
+            handler,
+        });
+    }
+
+    /// Adds a `GET` route.
+    pub fn get(&mut self, pattern: &str, handler: Handler) {
+        self.add_route(Method::Get, pattern, handler);
+    }
+
+    /// Adds a `POST` route.
+    pub fn post(&mut self, pattern: &str, handler: Handler) {
+        self.add_route(Method::Post, pattern, handler);
+    }
+
+    /// Adds a `PUT` route.
+    pub fn put(&mut self, pattern: &str, handler: Handler) {
+        self.add_route(Method::Put, pattern, handler);
+    }
+
+    /// Adds a `DELETE` route.
+    pub fn delete(&mut self, pattern: &str, handler: Handler) {
+        self.add_route(Method::Delete, pattern, handler);
+    }
+
+    /// Sets the handler for requests that don't match any route.
+    pub fn set_fallback(&mut self, handler: Handler) {
+        self.fallback = handler;
+    }
+
+    /// Passes the request to the matching handler and returns the response.
+    pub fn handle(&self, mut request: Request) -> Response {
+        let path: Vec<&str> = segments(request.path()).collect();
+        let matches: Vec<(&Route, Vec<(String, String)>)> = self
+            .routes
+            .iter()
+            .filter_map(|route| route.matches(&path).map(|params| (route, params)))
+            .collect();
+
+        let method = request.method();
+        let found = matches
+            .iter()
+            .find(|(route, _)| &route.method == method)
+            .or_else(|| match method {
+                Method::Head => matches
+                    .iter()
+                    .find(|(route, _)| route.method == Method::Get),
+                _ => None,
+            });
+        if let Some((route, params)) = found {
+            let handler = route.handler;
+            request.set_params(params.clone());
+            return handler(request);
+        }
+
+        if matches.is_empty() {
+            return (self.fallback)(request);
+        }
+        let mut allow: Vec<&str> = Vec::new();
+        for (route, _) in matches.iter() {
+            if !allow.contains(&route.method.as_str()) {
+                allow.push(route.method.as_str());
+            }
+        }
+        let mut response = Response::new(405);
+        response.set_header("Allow", &allow.join(", "));
+        response
+    }
+}
+
+impl Route {
+    // Returns the captured parameters if the path matches the route.
+    fn matches(&self, path: &[&str]) -> Option<Vec<(String, String)>> {
+        let mut params = Vec::new();
+        for (i, segment) in self.segments.iter().enumerate() {
+            match segment {
+                Segment::Rest(name) => {
+                    params.push((name.clone(), path.get(i..)?.join("/")));
+                    return Some(params);
+                }
+                Segment::Param(name) => params.push((name.clone(), path.get(i)?.to_string())),
+                Segment::Literal(literal) => {
+                    if path.get(i) != Some(&literal.as_str()) {
+                        return None;
+                    }
+                }
+            }
+        }
+        if path.len() == self.segments.len() {
+            Some(params)
+        } else {
+            None
+        }
+    }
+}
+
+// Splits a path into its segments, ignoring empty ones.
+fn segments(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|segment| !segment.is_empty())
+}
+
+fn not_found(_: Request) -> Response {
+    Response::new(404)
+}
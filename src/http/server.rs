@@ -0,0 +1,213 @@
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    connection::{is_timeout, BodyLength, Connection},
+    router::SentRouter,
+    HttpError, Method, Request, Response, Router, Version,
+};
+use crate::{
+    host_api,
+    net::{TcpListener, TcpStream},
+    process, Mailbox,
+};
+
+/// Serves HTTP/1.1 requests with a [`Router`].
+///
+/// Every accepted connection is handed to a newly spawned process that reads requests from it
+/// until the client closes it or asks to close it. Handlers run inside of this process, so a
+/// panicking handler only takes down its own connection.
+///
+/// Clients that send data very slowly, or not at all, can't hold on to a connection forever:
+///
+/// * Each read from the connection waits at most for the
+///   [read timeout](Server::set_read_timeout), which is passed to
+///   [`TcpStream::set_read_timeout`].
+/// * The whole head of a request needs to arrive within the
+///   [header timeout](Server::set_header_timeout), counted from when the server starts waiting
+///   for the request. Idle kept alive connections are closed after the same time.
+/// * The whole body needs to arrive within the [body timeout](Server::set_body_timeout),
+///   counted from when the head was read.
+///
+/// Clients that exceed a timeout get a `408 Request Timeout` response, heads and bodies over
+/// the size limits are answered with `431` and `413`, and malformed requests with
+/// `400 Bad Request`. The connection is closed after each of these.
+#[derive(Clone)]
+pub struct Server {
+    router: Router,
+    limits: Limits,
+}
+
+// Everything but the router is sent to connection processes as is.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+struct Limits {
+    read_timeout: Option<Duration>,
+    header_timeout: Option<Duration>,
+    body_timeout: Option<Duration>,
+    max_head_size: usize,
+    max_body_size: usize,
+}
+
+impl Server {
+    /// Creates a server that handles requests with `router`.
+    ///
+    /// By default the read timeout is 30 seconds, the header timeout 10 seconds, the body timeout
+    /// 60 seconds, heads are limited to 16 KiB and bodies to 1 MiB.
+    pub fn new(router: Router) -> Self {
+        Self {
+            router,
+            limits: Limits {
+                read_timeout: Some(Duration::from_secs(30)),
+                header_timeout: Some(Duration::from_secs(10)),
+                body_timeout: Some(Duration::from_secs(60)),
+                max_head_size: 16 * 1024,
+                max_body_size: 1024 * 1024,
+            },
+        }
+    }
+
+    /// Sets the longest time a single read from a connection can take.
+    ///
+    /// If the value specified is `None`, reads block indefinitely.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.limits.read_timeout = timeout;
+    }
+
+    /// Sets the time a client has to send the complete head of a request.
+    ///
+    /// If the value specified is `None`, only the read timeout applies.
+    pub fn set_header_timeout(&mut self, timeout: Option<Duration>) {
+        self.limits.header_timeout = timeout;
+    }
+
+    /// Sets the time a client has to send the complete body of a request.
+    ///
+    /// If the value specified is `None`, only the read timeout applies.
+    pub fn set_body_timeout(&mut self, timeout: Option<Duration>) {
+        self.limits.body_timeout = timeout;
+    }
+
+    /// Sets the largest accepted size of a request head in bytes.
+    pub fn set_max_head_size(&mut self, size: usize) {
+        self.limits.max_head_size = size;
+    }
+
+    /// Sets the largest accepted size of a request body in bytes.
+    pub fn set_max_body_size(&mut self, size: usize) {
+        self.limits.max_body_size = size;
+    }
+
+    /// Accepts connections from `listener` and serves each of them in a new process.
+    ///
+    /// This function blocks the current process and only returns if accepting a connection or
+    /// spawning a process fails.
+    pub fn serve(self, listener: &TcpListener) -> io::Result<()> {
+        loop {
+            let (stream, _) = listener.accept()?;
+            let router = SentRouter::from(self.router.clone());
+            process::spawn_with((router, self.limits, stream), connection)?;
+        }
+    }
+
+    /// Serves requests from `stream` in the current process until the connection is closed.
+    pub fn serve_connection(&self, stream: TcpStream) {
+        let peer_addr = stream.peer_addr().ok();
+        let mut connection = Connection::new(stream, self.limits.read_timeout);
+        loop {
+            let deadline = deadline_after(self.limits.header_timeout);
+            let mut request = match self.read_request(&mut connection, deadline) {
+                Ok(Some(request)) => request,
+                Ok(None) => return,
+                Err(error) => return reject(&mut connection, error),
+            };
+            if let Some(peer_addr) = peer_addr {
+                request.set_peer_addr(peer_addr);
+            }
+
+            let version = request.version();
+            let head_only = request.method() == &Method::Head;
+            let keep_alive = request.keep_alive();
+            let mut response = self.router.handle(request);
+            let keep_alive = keep_alive && !response.headers().has_token("Connection", "close");
+            if !keep_alive {
+                response.set_header("Connection", "close");
+            } else if version == Version::Http10 {
+                response.set_header("Connection", "keep-alive");
+            }
+            let chunked = version == Version::Http11;
+            if response
+                .write(connection.stream(), head_only, chunked)
+                .is_err()
+                || !keep_alive
+            {
+                return;
+            }
+        }
+    }
+
+    // Reads the next request with its body, or returns `None` if the client closed the
+    // connection.
+    fn read_request(
+        &self,
+        connection: &mut Connection,
+        deadline: Option<Instant>,
+    ) -> Result<Option<Request>, HttpError> {
+        let head = match connection.read_head(self.limits.max_head_size, deadline)? {
+            Some(head) => head,
+            None => return Ok(None),
+        };
+        let mut request = Request::parse(&head)?;
+        let length = match BodyLength::from_headers(request.headers())? {
+            None => return Ok(Some(request)),
+            Some(BodyLength::UntilClose) => {
+                return Err(HttpError::Malformed("unsupported transfer coding"))
+            }
+            Some(length) => length,
+        };
+        if let BodyLength::Fixed(length) = length {
+            if length > self.limits.max_body_size {
+                return Err(HttpError::BodyTooLarge);
+            }
+        }
+        // The client waits for permission before sending the body.
+        if request.headers().has_token("Expect", "100-continue") {
+            Response::new(100).write(connection.stream(), true, false)?;
+        }
+        let deadline = deadline_after(self.limits.body_timeout);
+        let body = connection.read_body(length, self.limits.max_body_size, deadline)?;
+        request.set_body(body);
+        Ok(Some(request))
+    }
+}
+
+// Entry point of connection processes.
+fn connection((router, limits, stream): (SentRouter, Limits, TcpStream), _: Mailbox<()>) {
+    let server = Server {
+        router: router.into(),
+        limits,
+    };
+    server.serve_connection(stream);
+}
+
+// Returns the point in time after `timeout`, counted from now.
+fn deadline_after(timeout: Option<Duration>) -> Option<Instant> {
+    timeout.map(|timeout| host_api::now() + timeout)
+}
+
+// Answers a request that can't be handled and closes the connection.
+fn reject(connection: &mut Connection, error: HttpError) {
+    let status = match error {
+        error if is_timeout(&error) => 408,
+        HttpError::Malformed(_) => 400,
+        HttpError::HeadTooLarge => 431,
+        HttpError::BodyTooLarge => 413,
+        HttpError::Io(_) | HttpError::InvalidUrl(_) | HttpError::TooManyRedirects => return,
+    };
+    let mut response = Response::new(status);
+    response.set_header("Connection", "close");
+    let _ = response.write(connection.stream(), false, false);
+}
@@ -67,11 +67,21 @@ proc.send("World!".to_string());
 ```
 Everything that implements the **[`Serialize`](serde::Serialize)** and
 **[`Deserialize`](serde::Deserialize)** traits can be sent as a message to another process.
+Messages are encoded with MessagePack by default, other formats can be picked with a
+[`Codec`](codec::Codec). Processes that only move bytes around can skip encoding with a
+[`RawMailbox`] and [`send_bytes`](process::Process::send_bytes).
 
 Each process gets a [`Mailbox`] as an argument to the entry function. Mailboxes can be used to
 [`receive`](Mailbox::receive()) messages. If there are no messages in the mailbox the process
 will block on [`receive`](Mailbox::receive()) until a message arrives.
 
+[`receive_matching`](Mailbox::receive_matching()) and [`receive_any_tag`](Mailbox::receive_any_tag())
+only take messages that match a predicate or a set of tags. Other messages are left in the mailbox
+and are returned by later receives in the order they arrived.
+
+To send the same message to a dynamic set of processes, they can join a named
+[`Group`](group::Group) and the message can be broadcast to all members.
+
 ## Request/Reply architecture
 
 It's common in lunatic to have processes that act as servers, they receive requests and send back
@@ -104,6 +114,10 @@ It's important to notice here that the response can be a different type (`i32`)
 type (`()`). This is safe, because the call to the `request` function will block until we get back
 a response and handle it right away, so that the different type never ends up in the mailbox.
 
+Servers that keep state between requests don't need to write the receive loop by hand. They can
+implement the [`AbstractProcess`] trait and get back a [`ProcessRef`] with typed `request` and
+`send` methods when started.
+
 ## Linking
 
 Processes can be linked together. This means that if one of them fails, all the ones linked to
@@ -113,6 +127,13 @@ receive notifications about linked processes. If we would like to automatically
 one of the linked processes fails, we can turn the [`LinkMailbox`] back to a regular one with
 the `panic_if_link_panics()` function.
 
+Each signal carries the tag of the link and the [`ExitReason`] of the linked process, e.g. the
+panic message if it panicked.
+
+If a process only wants to watch another one, without being tied to its lifetime, it can use
+[`Process::monitor`](process::Process::monitor) instead. Monitors are one-way and deliver a
+[`Message::Down`] notification when the watched process dies, no matter why it died.
+
 ```
 use lunatic::{process, Mailbox};
 
@@ -129,6 +150,34 @@ fn child(_: Mailbox<()>) {
 
 ```
 
+## Supervision
+
+Instead of handling link signals by hand, a group of children can be started by a
+[`Supervisor`](supervisor::Supervisor). The supervisor links all children and restarts them if
+they fail, following one of the restart [strategies](supervisor::Strategy).
+
+```
+use lunatic::{
+    supervisor::{ChildSpec, Strategy, Supervisor},
+    Mailbox,
+};
+
+#[lunatic::main]
+fn main(mailbox: Mailbox<()>) {
+    let mut supervisor = Supervisor::new(Strategy::OneForOne);
+    supervisor.add_child(ChildSpec::new(child));
+    // Only returns if the children keep failing.
+    supervisor.supervise(mailbox);
+}
+
+fn child(_: Mailbox<()>) {
+    panic!("Error");
+}
+```
+
+Identical workers that share a load can be grouped into a [`Pool`](pool::Pool). The pool
+dispatches requests between workers and replaces the ones that die.
+
 ## Sandboxing
 
 A [`Environment`] can define characteristics that processes spawned into it have. The environment
@@ -201,22 +250,59 @@ If a process dies, either because an unsupported syscall was called or a `Kill`
 there is not going to be any output in the terminal. To get more insight set the `RUST_LOG`
 environment variable to `lunatic=debug`. E.g. `RUST_LOG=lunatic=debug cargo run`.
 
+# Testing without lunatic
+
+With the `mock` feature enabled, the host functions are implemented by the library itself and
+every process runs on a native thread. This allows running `#[lunatic::test]` tests with a plain
+`cargo test --features mock` on the host target, where native debuggers and tools work. Memory
+and compute limits are not enforced and only [`ThisModule`] can be used to spawn processes.
+
+The mock can also run processes inside of a deterministic simulation, where a seeded scheduler
+decides the order of everything that happens and time is virtual. This makes races between
+processes reproducible. Check out the `simulation` module for details.
+
 [1]: https://github.com/lunatic-solutions/lunatic
 */
 
+mod abstract_process;
+pub mod codec;
 mod environment;
 mod error;
+pub mod framed;
+#[cfg(feature = "unstable-host")]
+pub mod group;
 mod host_api;
+#[cfg(feature = "http")]
+pub mod http;
 mod mailbox;
+#[cfg(feature = "mock")]
+mod mock;
 pub mod net;
+pub mod pool;
 pub mod process;
 mod request;
+mod resource;
+#[cfg(feature = "mock")]
+pub mod simulation;
+#[cfg(feature = "unstable-host")]
+pub mod supervisor;
 mod tag;
-
+#[cfg(feature = "mock")]
+#[doc(hidden)]
+pub mod test_support;
+#[cfg(feature = "unstable-host")]
+pub mod timer;
+#[cfg(feature = "websocket")]
+pub mod websocket;
+
+pub use abstract_process::{AbstractProcess, ProcessRef};
 pub use environment::{lookup, Config, Environment, Module, Param, ThisModule};
 pub use error::LunaticError;
-pub use mailbox::{LinkMailbox, Mailbox, Message, ReceiveError, Signal, TransformMailbox};
-pub use request::Request;
+pub use mailbox::{
+    ExitReason, LinkMailbox, Mailbox, Message, RawMailbox, RawMessage, ReceiveError, Signal,
+    TransformMailbox,
+};
+pub use request::{Request, RequestError};
 pub use tag::Tag;
 
 pub use lunatic_macros::main;
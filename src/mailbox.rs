@@ -1,28 +1,36 @@
 use std::{
+    any::Any,
+    cell::RefCell,
+    collections::VecDeque,
     io::{Read, Write},
     marker::PhantomData,
     time::Duration,
 };
 
-use rmp_serde::decode;
-use serde::{de::DeserializeOwned, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use thiserror::Error;
 
 use crate::{
-    host_api::{message, process},
+    codec::{Codec, DecodeError, MessagePack},
+    host_api::{message, now, process},
+    net::TcpStream,
+    process::{Monitor, Process},
     tag::Tag,
 };
 
 const SIGNAL: u32 = 1;
-const TIMEOUT: u32 = 9027;
+pub(crate) const DOWN: u32 = 2;
+pub(crate) const TIMEOUT: u32 = 9027;
 
 /// Mailbox for processes that are not linked, or linked and set to trap on notify signals.
+///
+/// Messages are decoded with the [`Codec`] `C`.
 #[derive(Debug)]
-pub struct Mailbox<T: Serialize + DeserializeOwned> {
-    _phantom: PhantomData<T>,
+pub struct Mailbox<T: Serialize + DeserializeOwned, C: Codec = MessagePack> {
+    _phantom: PhantomData<(T, C)>,
 }
 
-impl<T: Serialize + DeserializeOwned> Mailbox<T> {
+impl<T: Serialize + DeserializeOwned, C: Codec> Mailbox<T, C> {
     /// Create a mailbox with a specific type.
     ///
     /// ### Safety
@@ -35,6 +43,45 @@ impl<T: Serialize + DeserializeOwned> Mailbox<T> {
         }
     }
 
+    /// Decode received messages with a different [`Codec`].
+    ///
+    /// Senders need to use the same codec, e.g. with
+    /// [`Process::with_codec`](crate::process::Process::with_codec).
+    pub fn with_codec<D: Codec>(self) -> Mailbox<T, D> {
+        unsafe { Mailbox::new() }
+    }
+
+    /// Turns the mailbox into a [`RawMailbox`], that receives the bytes of messages without
+    /// decoding them.
+    ///
+    /// Messages that were left in the mailbox by a selective receive are dropped.
+    pub fn into_raw(self) -> RawMailbox {
+        SAVED.with(|queue| queue.borrow_mut().clear());
+        RawMailbox::new()
+    }
+
+    // Receives the next message from the host queue, skipping the saved messages.
+    pub(crate) fn receive_host(
+        &self,
+        tag: i64,
+        timeout: Option<Duration>,
+    ) -> Result<(T, Tag), ReceiveError> {
+        let message_type = receive_data(tag, timeout);
+        // In case of timeout, return error.
+        if message_type == TIMEOUT {
+            return Err(ReceiveError::Timeout);
+        }
+        let tag = Tag::from(unsafe { message::get_tag() });
+        match C::decode(&mut MessageRw {}) {
+            Ok(result) => Ok((result, tag)),
+            Err(decode_error) => Err(ReceiveError::DeserializationFailed(decode_error)),
+        }
+    }
+}
+
+// Messages that were left in the mailbox by a selective receive are kept with their type, so
+// receiving requires it to be `'static`.
+impl<T: Serialize + DeserializeOwned + 'static, C: Codec> Mailbox<T, C> {
     /// Gets next message from process' mailbox.
     ///
     /// If the mailbox is empty, this function will block until a new message arrives.
@@ -51,9 +98,7 @@ impl<T: Serialize + DeserializeOwned> Mailbox<T> {
     ///
     /// If the mailbox is empty, this function will block until a new message arrives.
     pub fn receive_with_tag(&self) -> Result<(T, Tag), ReceiveError> {
-        let message = self.receive_(None, None)?;
-        let tag = unsafe { message::get_tag() };
-        Ok((message, Tag::from(tag)))
+        self.receive_tagged(None, None)
     }
 
     /// Gets a message with a specific tag from the mailbox.
@@ -68,36 +113,98 @@ impl<T: Serialize + DeserializeOwned> Mailbox<T> {
         self.receive_(Some(tag.id()), Some(timeout))
     }
 
+    /// Gets the first message from the mailbox for which `filter` returns true.
+    ///
+    /// Messages that don't match are left in the mailbox and are returned by later receives in
+    /// the order they arrived. If no message matches, this function will block until one arrives.
+    pub fn receive_matching<F>(&self, mut filter: F) -> Result<T, ReceiveError>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let (message, _) = self.receive_matching_(|message, _| filter(message), None)?;
+        Ok(message)
+    }
+
+    /// Same as [`receive_matching`], but only waits for the duration of timeout for a matching
+    /// message.
+    pub fn receive_matching_timeout<F>(
+        &self,
+        mut filter: F,
+        timeout: Duration,
+    ) -> Result<T, ReceiveError>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let (message, _) = self.receive_matching_(|message, _| filter(message), Some(timeout))?;
+        Ok(message)
+    }
+
+    /// Gets the first message from the mailbox that has one of the `tags`, together with the tag.
+    ///
+    /// Messages with other tags are left in the mailbox. If no message matches, this function
+    /// will block until one arrives.
+    pub fn receive_any_tag(&self, tags: &[Tag]) -> Result<(T, Tag), ReceiveError> {
+        self.receive_matching_(|_, tag| tags.contains(&tag), None)
+    }
+
+    /// Same as [`receive_any_tag`], but only waits for the duration of timeout for a matching
+    /// message.
+    pub fn receive_any_tag_timeout(
+        &self,
+        tags: &[Tag],
+        timeout: Duration,
+    ) -> Result<(T, Tag), ReceiveError> {
+        self.receive_matching_(|_, tag| tags.contains(&tag), Some(timeout))
+    }
+
     fn receive_(&self, tag: Option<i64>, timeout: Option<Duration>) -> Result<T, ReceiveError> {
-        let tag = tag.unwrap_or(0);
-        let timeout_ms = match timeout {
-            // If waiting time is smaller than 1ms, round it up to 1ms.
-            Some(timeout) => match timeout.as_millis() {
-                0 => 1,
-                other => other as u32,
-            },
-            None => 0,
-        };
-        let message_type = unsafe { message::receive(tag, timeout_ms) };
-        // Mailbox can't receive Signal messages.
-        assert_ne!(message_type, SIGNAL);
-        // In case of timeout, return error.
-        if message_type == TIMEOUT {
-            return Err(ReceiveError::Timeout);
+        let (message, _) = self.receive_tagged(tag, timeout)?;
+        Ok(message)
+    }
+
+    fn receive_tagged(
+        &self,
+        tag: Option<i64>,
+        timeout: Option<Duration>,
+    ) -> Result<(T, Tag), ReceiveError> {
+        // Messages left behind by a selective receive come first.
+        let saved = take_saved::<T, _>(|_, saved_tag| matches_tag(tag, saved_tag));
+        if let Some(saved) = saved {
+            return Ok(saved);
         }
-        match rmp_serde::from_read(MessageRw {}) {
-            Ok(result) => Ok(result),
-            Err(decode_error) => Err(ReceiveError::DeserializationFailed(decode_error)),
+        self.receive_host(tag.unwrap_or(0), timeout)
+    }
+
+    fn receive_matching_<F>(
+        &self,
+        mut filter: F,
+        timeout: Option<Duration>,
+    ) -> Result<(T, Tag), ReceiveError>
+    where
+        F: FnMut(&T, Tag) -> bool,
+    {
+        if let Some(saved) = take_saved(&mut filter) {
+            return Ok(saved);
+        }
+        let deadline = timeout.map(|timeout| now() + timeout);
+        loop {
+            let timeout = deadline.map(|deadline| deadline.saturating_duration_since(now()));
+            // The saved messages were already checked, so only new ones are received here.
+            let (message, tag) = self.receive_host(0, timeout)?;
+            if filter(&message, tag) {
+                return Ok((message, tag));
+            }
+            save(tag, message);
         }
     }
 }
 
-impl<T: Serialize + DeserializeOwned> TransformMailbox<T> for Mailbox<T> {
-    fn catch_link_panic(self) -> LinkMailbox<T> {
+impl<T: Serialize + DeserializeOwned, C: Codec> TransformMailbox<T, C> for Mailbox<T, C> {
+    fn catch_link_panic(self) -> LinkMailbox<T, C> {
         unsafe { process::die_when_link_dies(0) };
         LinkMailbox::new()
     }
-    fn panic_if_link_panics(self) -> Mailbox<T> {
+    fn panic_if_link_panics(self) -> Mailbox<T, C> {
         self
     }
 }
@@ -106,17 +213,24 @@ impl<T: Serialize + DeserializeOwned> TransformMailbox<T> for Mailbox<T> {
 ///
 /// When a process is linked to others it will also receive messages if one of the others dies.
 #[derive(Debug)]
-pub struct LinkMailbox<T: Serialize + DeserializeOwned> {
-    _phantom: PhantomData<T>,
+pub struct LinkMailbox<T: Serialize + DeserializeOwned, C: Codec = MessagePack> {
+    _phantom: PhantomData<(T, C)>,
 }
 
-impl<T: Serialize + DeserializeOwned> LinkMailbox<T> {
+impl<T: Serialize + DeserializeOwned, C: Codec> LinkMailbox<T, C> {
     pub(crate) fn new() -> Self {
         Self {
             _phantom: PhantomData {},
         }
     }
 
+    /// Decode received messages with a different [`Codec`].
+    pub fn with_codec<D: Codec>(self) -> LinkMailbox<T, D> {
+        LinkMailbox::new()
+    }
+}
+
+impl<T: Serialize + DeserializeOwned + 'static, C: Codec> LinkMailbox<T, C> {
     /// Gets next message from process' mailbox.
     ///
     /// If the mailbox is empty, this function will block until a new message arrives.
@@ -142,6 +256,11 @@ impl<T: Serialize + DeserializeOwned> LinkMailbox<T> {
     }
 
     fn receive_(&self, tag: Option<i64>, timeout: Option<Duration>) -> Message<T> {
+        // Messages left behind by a selective receive come first.
+        let saved = take_saved::<T, _>(|_, saved_tag| matches_tag(tag, saved_tag));
+        if let Some((message, _)) = saved {
+            return Message::Normal(Ok(message));
+        }
         let tag = tag.unwrap_or(0);
         let timeout_ms = match timeout {
             // If waiting time is smaller than 1ms, round it up to 1ms.
@@ -155,14 +274,26 @@ impl<T: Serialize + DeserializeOwned> LinkMailbox<T> {
 
         if message_type == SIGNAL {
             let tag = unsafe { message::get_tag() };
-            return Message::Signal(Tag::from(tag));
+            return Message::Signal(Tag::from(tag), exit_reason());
+        }
+        // Monitors, and with them down notifications, need the `unstable-host` feature.
+        #[cfg(feature = "unstable-host")]
+        if message_type == DOWN {
+            let tag = unsafe { message::get_tag() };
+            let mut uuid: [u8; 16] = [0; 16];
+            unsafe { message::signal_process_id(&mut uuid as *mut [u8; 16]) };
+            return Message::Down {
+                monitor: Monitor::from(Tag::from(tag)),
+                process: u128::from_le_bytes(uuid),
+                reason: exit_reason(),
+            };
         }
         // In case of timeout, return error.
-        else if message_type == TIMEOUT {
+        if message_type == TIMEOUT {
             return Message::Normal(Err(ReceiveError::Timeout));
         }
 
-        let message = match rmp_serde::from_read(MessageRw {}) {
+        let message = match C::decode(&mut MessageRw {}) {
             Ok(result) => Ok(result),
             Err(decode_error) => Err(ReceiveError::DeserializationFailed(decode_error)),
         };
@@ -170,48 +301,417 @@ impl<T: Serialize + DeserializeOwned> LinkMailbox<T> {
     }
 }
 
-impl<T: Serialize + DeserializeOwned> TransformMailbox<T> for LinkMailbox<T> {
-    fn catch_link_panic(self) -> LinkMailbox<T> {
+impl<T: Serialize + DeserializeOwned, C: Codec> TransformMailbox<T, C> for LinkMailbox<T, C> {
+    fn catch_link_panic(self) -> LinkMailbox<T, C> {
         self
     }
-    fn panic_if_link_panics(self) -> Mailbox<T> {
+    fn panic_if_link_panics(self) -> Mailbox<T, C> {
         unsafe { process::die_when_link_dies(1) };
         unsafe { Mailbox::new() }
     }
 }
 
+/// Mailbox that receives messages as plain bytes, without decoding them.
+///
+/// This avoids a serialization round trip for processes that only move bytes around, like
+/// proxies or binary protocols. The bytes are sent with
+/// [`Process::send_bytes`](crate::process::Process::send_bytes) or
+/// [`Process::send_raw`](crate::process::Process::send_raw), that can also attach resources to a
+/// [`RawMessage`].
+#[derive(Debug)]
+pub struct RawMailbox {
+    _private: (),
+}
+
+impl RawMailbox {
+    pub(crate) fn new() -> Self {
+        Self { _private: () }
+    }
+
+    /// Gets the data of the next message from process' mailbox.
+    ///
+    /// If the mailbox is empty, this function will block until a new message arrives.
+    pub fn receive(&self) -> Result<Vec<u8>, ReceiveError> {
+        let mut buffer = Vec::new();
+        self.receive_(&mut buffer, 0, None)?;
+        Ok(buffer)
+    }
+
+    /// Same as [`receive`], but only waits for the duration of timeout for the message.
+    pub fn receive_timeout(&self, timeout: Duration) -> Result<Vec<u8>, ReceiveError> {
+        let mut buffer = Vec::new();
+        self.receive_(&mut buffer, 0, Some(timeout))?;
+        Ok(buffer)
+    }
+
+    /// Gets the data of a message with a specific tag from the mailbox.
+    ///
+    /// If the mailbox is empty, this function will block until a new message arrives.
+    pub fn tag_receive(&self, tag: Tag) -> Result<Vec<u8>, ReceiveError> {
+        let mut buffer = Vec::new();
+        self.receive_(&mut buffer, tag.id(), None)?;
+        Ok(buffer)
+    }
+
+    /// Replaces the content of `buffer` with the data of the next message and returns its tag.
+    ///
+    /// The buffer is only grown if the message doesn't fit, so reusing it saves allocations when
+    /// receiving many messages.
+    pub fn receive_into(&self, buffer: &mut Vec<u8>) -> Result<Tag, ReceiveError> {
+        self.receive_(buffer, 0, None)
+    }
+
+    /// Same as [`receive_into`], but only waits for the duration of timeout for the message.
+    pub fn receive_into_timeout(
+        &self,
+        buffer: &mut Vec<u8>,
+        timeout: Duration,
+    ) -> Result<Tag, ReceiveError> {
+        self.receive_(buffer, 0, Some(timeout))
+    }
+
+    /// Takes a process that was attached with [`RawMessage::attach_process`] out of the last
+    /// received message.
+    ///
+    /// ### Safety
+    ///
+    /// The last received message must contain a process at `index`, that accepts messages of
+    /// type `T` encoded with `C`.
+    pub unsafe fn take_process<T, C>(&self, index: u64) -> Process<T, C>
+    where
+        T: Serialize + DeserializeOwned,
+        C: Codec,
+    {
+        Process::from(message::take_process(index))
+    }
+
+    /// Takes a TCP stream that was attached with [`RawMessage::attach_tcp_stream`] out of the
+    /// last received message.
+    ///
+    /// ### Safety
+    ///
+    /// The last received message must contain a TCP stream at `index`.
+    pub unsafe fn take_tcp_stream(&self, index: u64) -> TcpStream {
+        TcpStream::from(message::take_tcp_stream(index))
+    }
+
+    fn receive_(
+        &self,
+        buffer: &mut Vec<u8>,
+        tag: i64,
+        timeout: Option<Duration>,
+    ) -> Result<Tag, ReceiveError> {
+        let message_type = receive_data(tag, timeout);
+        // In case of timeout, return error.
+        if message_type == TIMEOUT {
+            return Err(ReceiveError::Timeout);
+        }
+        let size = unsafe { message::data_size() } as usize;
+        buffer.clear();
+        buffer.resize(size, 0);
+        unsafe {
+            // Read the whole message, no matter how much of it was read before.
+            message::seek_data(0);
+            message::read_data(buffer.as_mut_ptr(), size);
+        }
+        Ok(Tag::from(unsafe { message::get_tag() }))
+    }
+}
+
+/// Bytes and resources that are sent without encoding them.
+///
+/// Resources are moved out of the current process when they are attached. If the message is
+/// dropped before it's sent, they are dropped with it.
+///
+/// # Example
+///
+/// ```no_run
+/// use lunatic::{net::TcpStream, process, Mailbox, RawMessage};
+///
+/// #[lunatic::main]
+/// fn main(mailbox: Mailbox<()>) {
+///     let proxy = process::spawn(|mailbox: Mailbox<()>| {
+///         let mailbox = mailbox.into_raw();
+///         let header = mailbox.receive().unwrap();
+///         let stream = unsafe { mailbox.take_tcp_stream(0) };
+///         // ...
+///     })
+///     .unwrap();
+///
+///     let stream = TcpStream::connect("127.0.0.1:8080").unwrap();
+///     let mut message = RawMessage::new(b"GET".to_vec());
+///     message.attach_tcp_stream(stream);
+///     proxy.send_raw(message);
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct RawMessage {
+    tag: i64,
+    data: Vec<u8>,
+    resources: Vec<RawResource>,
+}
+
+#[derive(Debug)]
+enum RawResource {
+    Process(u64),
+    TcpStream(u64),
+}
+
+impl RawMessage {
+    /// Creates a message that contains `data`.
+    pub fn new(data: Vec<u8>) -> Self {
+        Self {
+            tag: 0,
+            data,
+            resources: Vec::new(),
+        }
+    }
+
+    /// Tags the message, so that it can be picked with [`RawMailbox::tag_receive`].
+    pub fn set_tag(&mut self, tag: Tag) {
+        self.tag = tag.id();
+    }
+
+    /// Returns the data of the message.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the data of the message for modification.
+    pub fn data_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.data
+    }
+
+    /// Attaches a process and returns the index it can be taken out with on the receiving side.
+    pub fn attach_process<T, C>(&mut self, process: Process<T, C>) -> u64
+    where
+        T: Serialize + DeserializeOwned,
+        C: Codec,
+    {
+        self.resources.push(RawResource::Process(process.into_id()));
+        self.resources.len() as u64 - 1
+    }
+
+    /// Attaches a TCP stream and returns the index it can be taken out with on the receiving
+    /// side.
+    pub fn attach_tcp_stream(&mut self, stream: TcpStream) -> u64 {
+        self.resources
+            .push(RawResource::TcpStream(stream.into_id()));
+        self.resources.len() as u64 - 1
+    }
+
+    // Writes the message into the host scratch buffer, moving the resources with it.
+    pub(crate) fn write(mut self) {
+        unsafe {
+            message::create_data(self.tag, self.data.len() as u64);
+            message::write_data(self.data.as_ptr(), self.data.len());
+            // Resources are indexed in the order they are pushed.
+            for resource in self.resources.drain(..) {
+                match resource {
+                    RawResource::Process(id) => message::push_process(id),
+                    RawResource::TcpStream(id) => message::push_tcp_stream(id),
+                };
+            }
+        }
+    }
+}
+
+impl Drop for RawMessage {
+    fn drop(&mut self) {
+        for resource in self.resources.drain(..) {
+            match resource {
+                RawResource::Process(id) => unsafe { process::drop_process(id) },
+                RawResource::TcpStream(id) => unsafe {
+                    crate::host_api::networking::drop_tcp_stream(id)
+                },
+            }
+        }
+    }
+}
+
 /// Represents an error while receiving a message.
 #[derive(Error, Debug)]
 pub enum ReceiveError {
     #[error("Deserialization failed")]
-    DeserializationFailed(#[from] decode::Error),
+    DeserializationFailed(#[from] DecodeError),
     #[error("Timed out while waiting for message")]
     Timeout,
 }
 
 /// Returned from [`LinkMailbox::receive`] to indicate if the received message was a signal or a
 /// normal message.
+///
+/// Signals carry the tag of the link and the reason why the linked process died. Down
+/// notifications are delivered for processes watched with
+/// [`Process::monitor`](crate::process::Process::monitor).
 #[derive(Debug)]
 pub enum Message<T> {
     Normal(Result<T, ReceiveError>),
-    Signal(Tag),
+    Signal(Tag, ExitReason),
+    Down {
+        monitor: Monitor,
+        process: u128,
+        reason: ExitReason,
+    },
 }
 
 impl<T> Message<T> {
     /// Returns true if received message is a signal.
     pub fn is_signal(&self) -> bool {
-        match self {
-            Message::Normal(_) => false,
-            Message::Signal(_) => true,
-        }
+        matches!(self, Message::Signal(_, _))
+    }
+
+    /// Returns true if received message is a down notification.
+    pub fn is_down(&self) -> bool {
+        matches!(self, Message::Down { .. })
     }
 
     /// Returns the message if it's a normal one or panics if not.
     pub fn normal_or_unwrap(self) -> Result<T, ReceiveError> {
         match self {
             Message::Normal(message) => message,
-            Message::Signal(_) => panic!("Message is of type Signal"),
+            Message::Signal(_, _) => panic!("Message is of type Signal"),
+            Message::Down { .. } => panic!("Message is of type Down"),
+        }
+    }
+}
+
+/// The reason why a process died.
+///
+/// Without the `unstable-host` feature the runtime doesn't report reasons and every signal carries
+/// [`ExitReason::Trap`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum ExitReason {
+    /// The process finished normally.
+    Normal,
+    /// The process was killed with [`Process::kill`](crate::process::Process::kill).
+    Killed,
+    /// The process panicked. Contains the panic message.
+    Panic(String),
+    /// The process used up all the compute its [`Environment`](crate::Environment) allows.
+    OutOfFuel,
+    /// The process tried to use more memory than its [`Environment`](crate::Environment) allows.
+    OutOfMemory,
+    /// The process trapped for another reason, e.g. by calling `std::process::exit`.
+    Trap,
+}
+
+impl ExitReason {
+    /// Returns true if the process didn't finish normally or was killed on purpose.
+    pub fn is_failure(&self) -> bool {
+        !matches!(self, ExitReason::Normal | ExitReason::Killed)
+    }
+}
+
+// Reads the exit reason out of a signal that was just received.
+#[cfg(feature = "unstable-host")]
+fn exit_reason() -> ExitReason {
+    // Exit reasons of processes that are attached to signals.
+    const REASON_NORMAL: u32 = 0;
+    const REASON_KILLED: u32 = 1;
+    const REASON_PANIC: u32 = 2;
+    const REASON_OUT_OF_FUEL: u32 = 3;
+    const REASON_OUT_OF_MEMORY: u32 = 4;
+
+    match unsafe { message::signal_reason() } {
+        REASON_NORMAL => ExitReason::Normal,
+        REASON_KILLED => ExitReason::Killed,
+        REASON_PANIC => {
+            // The panic message is the data of the signal.
+            let size = unsafe { message::data_size() } as usize;
+            let mut buffer = vec![0; size];
+            unsafe { message::read_data(buffer.as_mut_ptr(), size) };
+            ExitReason::Panic(String::from_utf8_lossy(&buffer).into_owned())
         }
+        REASON_OUT_OF_FUEL => ExitReason::OutOfFuel,
+        REASON_OUT_OF_MEMORY => ExitReason::OutOfMemory,
+        _ => ExitReason::Trap,
+    }
+}
+
+// The v0.6.0 runtime doesn't report why a linked process died, it only signals failures.
+#[cfg(not(feature = "unstable-host"))]
+fn exit_reason() -> ExitReason {
+    ExitReason::Trap
+}
+
+// A message that was taken out of the host queue by a selective receive, but didn't match.
+//
+// Different mailbox types can be used inside of one process, so every saved message keeps its
+// type and is only taken out again by a mailbox of the same type.
+struct Saved {
+    tag: Tag,
+    message: Box<dyn Any>,
+}
+
+thread_local! {
+    // Saved messages in the order they arrived.
+    static SAVED: RefCell<VecDeque<Saved>> = const { RefCell::new(VecDeque::new()) };
+}
+
+fn save<T: 'static>(tag: Tag, message: T) {
+    let saved = Saved {
+        tag,
+        message: Box::new(message),
+    };
+    SAVED.with(|queue| queue.borrow_mut().push_back(saved));
+}
+
+// Takes the first saved message of type `T` that satisfies `filter`.
+fn take_saved<T: 'static, F>(mut filter: F) -> Option<(T, Tag)>
+where
+    F: FnMut(&T, Tag) -> bool,
+{
+    SAVED.with(|queue| {
+        let mut queue = queue.borrow_mut();
+        let index = queue.iter().position(|saved| {
+            saved
+                .message
+                .downcast_ref::<T>()
+                .is_some_and(|message| filter(message, saved.tag))
+        })?;
+        let saved = queue.remove(index)?;
+        let message = saved.message.downcast::<T>().ok()?;
+        Some((*message, saved.tag))
+    })
+}
+
+// Waits for the next message with `tag`, or any message if it's 0, and returns its type.
+//
+// Down notifications can be left in the queue if a monitoring `LinkMailbox` was turned back into
+// a `Mailbox`. Nothing can receive them anymore, so they are dropped.
+fn receive_data(tag: i64, timeout: Option<Duration>) -> u32 {
+    let deadline = timeout.map(|timeout| now() + timeout);
+    loop {
+        let timeout_ms = match deadline {
+            // If waiting time is smaller than 1ms, round it up to 1ms.
+            Some(deadline) => match deadline.saturating_duration_since(now()).as_millis() {
+                0 => 1,
+                other => other as u32,
+            },
+            None => 0,
+        };
+        let message_type = unsafe { message::receive(tag, timeout_ms) };
+        // Mailbox can't receive Signal messages.
+        assert_ne!(message_type, SIGNAL);
+        if message_type != DOWN {
+            return message_type;
+        }
+    }
+}
+
+// Drops every message with `tag` from the mailbox of the current process, without waiting for
+// new ones.
+#[cfg(feature = "unstable-host")]
+pub(crate) fn drop_tagged(tag: Tag) {
+    SAVED.with(|queue| queue.borrow_mut().retain(|saved| saved.tag != tag));
+    while unsafe { message::receive(tag.id(), 1) } != TIMEOUT {}
+}
+
+fn matches_tag(tag: Option<i64>, saved_tag: Tag) -> bool {
+    match tag {
+        Some(tag) => tag == saved_tag.id(),
+        None => true,
     }
 }
 
@@ -219,9 +719,9 @@ impl<T> Message<T> {
 #[derive(Debug, Clone, Copy)]
 pub struct Signal {}
 
-pub trait TransformMailbox<T: Serialize + DeserializeOwned> {
-    fn catch_link_panic(self) -> LinkMailbox<T>;
-    fn panic_if_link_panics(self) -> Mailbox<T>;
+pub trait TransformMailbox<T: Serialize + DeserializeOwned, C: Codec = MessagePack> {
+    fn catch_link_panic(self) -> LinkMailbox<T, C>;
+    fn panic_if_link_panics(self) -> Mailbox<T, C>;
 }
 
 // A helper struct to read and write into the message scratch buffer.
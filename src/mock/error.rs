@@ -0,0 +1,27 @@
+/*! Mock of the `lunatic::error` namespace */
+
+use super::{remove_resource, with_resource, Resource};
+
+fn error(error_id: u64) -> (String, u32) {
+    with_resource(error_id, |resource| match resource {
+        Resource::Error(message, kind) => (message.clone(), *kind),
+        _ => panic!("Resource {} is not an error", error_id),
+    })
+}
+
+pub unsafe fn string_size(error_id: u64) -> u32 {
+    error(error_id).0.len() as u32
+}
+
+pub unsafe fn to_string(error_id: u64, error_str: *mut u8) {
+    let (message, _) = error(error_id);
+    std::ptr::copy_nonoverlapping(message.as_ptr(), error_str, message.len());
+}
+
+pub unsafe fn io_error_kind(error_id: u64) -> u32 {
+    error(error_id).1
+}
+
+pub unsafe fn drop(error_id: u64) {
+    remove_resource(error_id);
+}
@@ -0,0 +1,195 @@
+/*! Mock of the `lunatic::message` namespace */
+
+use super::{
+    add_resource, current, process_resource, remove_resource, sim::Event, Msg, Proc, CURSOR, DATA,
+    SCRATCH, TIMEOUT,
+};
+
+thread_local! {
+    // Length of the mailbox when the last message was created. A reply to a request can't arrive
+    // before that, so it's where `send_receive_skip_search` starts searching.
+    static SKIP: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+pub unsafe fn create_data(tag: i64, capacity: u64) {
+    let skip = current().state().mailbox.len();
+    SKIP.with(|cell| cell.set(skip));
+    SCRATCH.with(|scratch| {
+        *scratch.borrow_mut() = Msg {
+            kind: DATA,
+            tag,
+            data: Vec::with_capacity(capacity as usize),
+            ..Msg::default()
+        }
+    });
+    CURSOR.with(|cursor| cursor.set(0));
+}
+
+pub unsafe fn write_data(data: *const u8, data_len: usize) -> usize {
+    let data = super::read_bytes(data, data_len);
+    SCRATCH.with(|scratch| scratch.borrow_mut().data.extend_from_slice(data));
+    data_len
+}
+
+pub unsafe fn read_data(data: *mut u8, data_len: usize) -> usize {
+    SCRATCH.with(|scratch| {
+        let scratch = scratch.borrow();
+        let position = CURSOR.with(|cursor| cursor.get());
+        let remaining = scratch.data.len().saturating_sub(position);
+        let size = remaining.min(data_len);
+        std::ptr::copy_nonoverlapping(scratch.data[position..].as_ptr(), data, size);
+        CURSOR.with(|cursor| cursor.set(position + size));
+        size
+    })
+}
+
+pub unsafe fn seek_data(position: u64) {
+    CURSOR.with(|cursor| cursor.set(position as usize));
+}
+
+pub unsafe fn get_tag() -> i64 {
+    SCRATCH.with(|scratch| scratch.borrow().tag)
+}
+
+pub unsafe fn data_size() -> u64 {
+    SCRATCH.with(|scratch| scratch.borrow().data.len() as u64)
+}
+
+// Moves a resource into the message and returns its index.
+pub(crate) fn push_resource(id: u64) -> u64 {
+    let resource = remove_resource(id).unwrap_or_else(|| panic!("Resource {} doesn't exist", id));
+    SCRATCH.with(|scratch| {
+        let mut scratch = scratch.borrow_mut();
+        scratch.resources.push(Some(resource));
+        scratch.resources.len() as u64 - 1
+    })
+}
+
+// Moves a resource out of the message and returns its new id.
+pub(crate) fn take_resource(index: u64) -> u64 {
+    let resource = SCRATCH.with(|scratch| {
+        scratch
+            .borrow_mut()
+            .resources
+            .get_mut(index as usize)
+            .and_then(Option::take)
+            .unwrap_or_else(|| panic!("Message doesn't contain resource {}", index))
+    });
+    add_resource(resource)
+}
+
+pub unsafe fn push_process(process_id: u64) -> u64 {
+    push_resource(process_id)
+}
+
+pub unsafe fn take_process(index: u64) -> u64 {
+    take_resource(index)
+}
+
+pub unsafe fn push_tcp_stream(tcp_stream_id: u64) -> u64 {
+    push_resource(tcp_stream_id)
+}
+
+pub unsafe fn take_tcp_stream(index: u64) -> u64 {
+    take_resource(index)
+}
+
+pub unsafe fn push_tcp_listener(tcp_listener_id: u64) -> u64 {
+    push_resource(tcp_listener_id)
+}
+
+pub unsafe fn take_tcp_listener(index: u64) -> u64 {
+    take_resource(index)
+}
+
+pub unsafe fn push_environment(env_id: u64) -> u64 {
+    push_resource(env_id)
+}
+
+pub unsafe fn take_environment(index: u64) -> u64 {
+    take_resource(index)
+}
+
+pub unsafe fn push_module(module_id: u64) -> u64 {
+    push_resource(module_id)
+}
+
+pub unsafe fn take_module(index: u64) -> u64 {
+    take_resource(index)
+}
+
+pub unsafe fn push_config(config_id: u64) -> u64 {
+    push_resource(config_id)
+}
+
+pub unsafe fn take_config(index: u64) -> u64 {
+    take_resource(index)
+}
+
+pub unsafe fn push_udp_socket(udp_socket_id: u64) -> u64 {
+    push_resource(udp_socket_id)
+}
+
+pub unsafe fn take_udp_socket(index: u64) -> u64 {
+    take_resource(index)
+}
+
+#[cfg(feature = "tls")]
+pub unsafe fn push_tls_stream(tls_stream_id: u64) -> u64 {
+    push_resource(tls_stream_id)
+}
+
+#[cfg(feature = "tls")]
+pub unsafe fn take_tls_stream(index: u64) -> u64 {
+    take_resource(index)
+}
+
+// Takes the message out of the scratch buffer.
+pub(crate) fn take_scratch() -> Msg {
+    SCRATCH.with(|scratch| std::mem::take(&mut *scratch.borrow_mut()))
+}
+
+// Puts a received message into the scratch buffer and returns its kind.
+fn put_scratch(message: Msg) -> u32 {
+    let kind = message.kind;
+    SCRATCH.with(|scratch| *scratch.borrow_mut() = message);
+    CURSOR.with(|cursor| cursor.set(0));
+    kind
+}
+
+pub unsafe fn send(process_id: u64) {
+    let receiver = process_resource(process_id);
+    let this = current();
+    Proc::checkpoint(&this.state());
+    receiver.post(this.id, Event::Message(take_scratch()));
+    // Sending is a scheduling point, so that other processes can observe the message right away.
+    if let Some(sim) = &this.sim {
+        sim.yield_now(this.id);
+    }
+}
+
+pub unsafe fn send_receive_skip_search(process_id: u64, timeout: u32) -> u32 {
+    let tag = SCRATCH.with(|scratch| scratch.borrow().tag);
+    send(process_id);
+    let skip = SKIP.with(|skip| skip.get());
+    match current().wait(timeout, skip, |message| message.tag == tag) {
+        Some(message) => put_scratch(message),
+        None => TIMEOUT,
+    }
+}
+
+pub unsafe fn receive(tag: i64, timeout: u32) -> u32 {
+    match current().wait(timeout, 0, |message| tag == 0 || message.tag == tag) {
+        Some(message) => put_scratch(message),
+        None => TIMEOUT,
+    }
+}
+
+pub unsafe fn signal_reason() -> u32 {
+    SCRATCH.with(|scratch| scratch.borrow().reason)
+}
+
+pub unsafe fn signal_process_id(uuid: *mut [u8; 16]) {
+    let process = SCRATCH.with(|scratch| scratch.borrow().process);
+    *uuid = process.to_le_bytes();
+}
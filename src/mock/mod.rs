@@ -0,0 +1,522 @@
+/*! A native host that replaces the lunatic runtime.
+
+The mock is enabled with the `mock` feature and implements the host functions that are otherwise imported from the runtime. Every
+process runs on its own thread and messages are passed through shared queues, so code using this
+library can be tested and debugged with a plain `cargo test` on the host target.
+
+Limitations compared to the real runtime:
+* Memory and compute limits of a [`Config`](crate::Config) are not enforced.
+* WebAssembly modules and plugins can't be loaded, only [`ThisModule`](crate::ThisModule) works.
+* A killed process stops the next time it sends, receives or sleeps. Calls blocked on the
+  network can't be interrupted.
+* `std::process::exit` ends the whole native process instead of just the lunatic one. The call
+  can't be turned into an unwind, because std aborts if a second thread exits after it.
+
+Processes started by a [`Simulation`](crate::simulation::Simulation) are scheduled one at a time
+by the [`sim`] module and use virtual time.
+*/
+
+pub mod error;
+pub mod message;
+pub mod networking;
+pub mod process;
+pub mod timer;
+
+mod semver;
+mod sim;
+#[cfg(feature = "tls")]
+mod tls;
+
+pub(crate) use sim::simulate;
+
+use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    collections::{HashMap, VecDeque},
+    io,
+    net::SocketAddr,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Condvar, Mutex, MutexGuard, OnceLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{error::IO_ERROR_KINDS, simulation::SimulationError};
+use semver::Version;
+use sim::{Event, Sim};
+
+// Message kinds returned from receives.
+const DATA: u32 = 0;
+const SIGNAL: u32 = 1;
+const DOWN: u32 = 2;
+const TIMEOUT: u32 = 9027;
+
+// Exit reasons attached to signals.
+const REASON_NORMAL: u32 = 0;
+const REASON_KILLED: u32 = 1;
+const REASON_PANIC: u32 = 2;
+
+// Ids of resources and processes are unique across the whole host.
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+fn next_id() -> u64 {
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Everything a process can hold a handle to.
+pub(crate) enum Resource {
+    Process(Arc<Proc>),
+    Config(Config),
+    Environment(Arc<Env>),
+    Module(Arc<Env>),
+    DnsIterator(VecDeque<SocketAddr>),
+    TcpListener(Arc<std::net::TcpListener>),
+    TcpStream(Arc<std::net::TcpStream>),
+    UdpSocket(Arc<std::net::UdpSocket>),
+    #[cfg(feature = "tls")]
+    TlsStream(Arc<Mutex<tls::TlsStream>>),
+    // The message and the `io_error_kind` code of an error.
+    Error(String, u32),
+}
+
+fn resources() -> MutexGuard<'static, HashMap<u64, Resource>> {
+    static RESOURCES: OnceLock<Mutex<HashMap<u64, Resource>>> = OnceLock::new();
+    RESOURCES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn add_resource(resource: Resource) -> u64 {
+    let id = next_id();
+    resources().insert(id, resource);
+    id
+}
+
+fn remove_resource(id: u64) -> Option<Resource> {
+    resources().remove(&id)
+}
+
+fn add_error<E: ToString>(error: E) -> u64 {
+    add_resource(Resource::Error(error.to_string(), 0))
+}
+
+fn add_io_error(error: io::Error) -> u64 {
+    let kind = IO_ERROR_KINDS
+        .iter()
+        .position(|kind| *kind == error.kind())
+        .map_or(0, |index| index as u32 + 1);
+    add_resource(Resource::Error(error.to_string(), kind))
+}
+
+// Runs `f` with the resource `id`, panicking if it's missing. Using an id that was never handed
+// out to the guest is a bug in the library, the same way it would trap on the real runtime.
+fn with_resource<R>(id: u64, f: impl FnOnce(&mut Resource) -> R) -> R {
+    let mut resources = resources();
+    let resource = resources
+        .get_mut(&id)
+        .unwrap_or_else(|| panic!("Resource {} doesn't exist", id));
+    f(resource)
+}
+
+fn process_resource(id: u64) -> Arc<Proc> {
+    with_resource(id, |resource| match resource {
+        Resource::Process(proc) => proc.clone(),
+        _ => panic!("Resource {} is not a process", id),
+    })
+}
+
+fn environment_resource(id: u64) -> Arc<Env> {
+    with_resource(id, |resource| match resource {
+        Resource::Environment(env) => env.clone(),
+        _ => panic!("Resource {} is not an environment", id),
+    })
+}
+
+/// Configuration of an environment. The limits are only stored, not enforced.
+#[allow(dead_code)]
+pub(crate) struct Config {
+    max_memory: u64,
+    max_fuel: u64,
+    namespaces: Vec<String>,
+}
+
+// Registered processes by name, each name can have multiple versions.
+type Registry = HashMap<String, Vec<(Version, Arc<Proc>)>>;
+
+/// An environment with its own process registry.
+#[derive(Default)]
+pub(crate) struct Env {
+    registry: Mutex<Registry>,
+}
+
+fn root_env() -> Arc<Env> {
+    static ROOT: OnceLock<Arc<Env>> = OnceLock::new();
+    ROOT.get_or_init(Default::default).clone()
+}
+
+/// A message in a mailbox or in the scratch buffer of a process.
+#[derive(Default)]
+pub(crate) struct Msg {
+    kind: u32,
+    tag: i64,
+    data: Vec<u8>,
+    resources: Vec<Option<Resource>>,
+    // Only used by signals and down notifications.
+    reason: u32,
+    process: u128,
+}
+
+/// A process running on its own thread.
+pub(crate) struct Proc {
+    id: u128,
+    env: Arc<Env>,
+    // Set if the process is part of a simulation.
+    sim: Option<Arc<Sim>>,
+    state: Mutex<ProcState>,
+    // Notified when a message arrives or the process is killed.
+    wake: Condvar,
+}
+
+#[derive(Default)]
+struct ProcState {
+    alive: bool,
+    mailbox: VecDeque<Msg>,
+    // Number of down notifications in the mailbox, so that demonitoring doesn't need to search
+    // it if there are none.
+    downs: usize,
+    // Tags and ids of linked processes.
+    links: Vec<(i64, u128)>,
+    // Tags and ids of processes monitoring this one.
+    monitors: Vec<(i64, u128)>,
+    // If true, signals from failed links are turned into messages.
+    trap: bool,
+    // Set when the process is killed, it unwinds the next time it calls into the host.
+    killed: Option<u32>,
+    panic_message: Option<String>,
+}
+
+impl Proc {
+    fn new(env: Arc<Env>, sim: Option<Arc<Sim>>) -> Arc<Self> {
+        let id = match &sim {
+            Some(sim) => sim.add_process(),
+            None => next_id() as u128,
+        };
+        let proc = Arc::new(Proc {
+            id,
+            env,
+            sim,
+            state: Mutex::new(ProcState {
+                alive: true,
+                ..ProcState::default()
+            }),
+            wake: Condvar::new(),
+        });
+        processes().insert(proc.id, proc.clone());
+        proc
+    }
+
+    fn state(&self) -> MutexGuard<'_, ProcState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // Adds a message to the mailbox. Messages sent to dead processes are dropped.
+    fn deliver(&self, message: Msg) {
+        let mut state = self.state();
+        if state.alive {
+            if message.kind == DOWN {
+                state.downs += 1;
+            }
+            state.mailbox.push_back(message);
+            self.wake.notify_all();
+        }
+    }
+
+    fn kill(&self, reason: u32) {
+        let mut state = self.state();
+        if state.alive && state.killed.is_none() {
+            state.killed = Some(reason);
+            self.wake.notify_all();
+        }
+    }
+
+    // Sends a message or signal from the process `from`. Inside of a simulation it's delivered
+    // whenever the scheduler decides to.
+    fn post(self: &Arc<Self>, from: u128, event: Event) {
+        match (&self.sim, event) {
+            (Some(sim), event) => sim.post(from, self.clone(), event),
+            (None, Event::Message(message)) => self.deliver(message),
+            (None, Event::Kill(reason)) => self.kill(reason),
+        }
+    }
+
+    // Unwinds the current thread if the process was killed. A thread that is already unwinding
+    // is left alone.
+    fn checkpoint(state: &MutexGuard<'_, ProcState>) {
+        if state.killed.is_some() && !thread::panicking() {
+            panic::resume_unwind(Box::new(Killed));
+        }
+    }
+
+    // Waits until a message satisfying `filter` arrives and removes it from the mailbox. The first
+    // `skip` messages are not searched. A `timeout` of 0 waits forever.
+    fn wait(&self, timeout: u32, mut skip: usize, filter: impl Fn(&Msg) -> bool) -> Option<Msg> {
+        if let Some(sim) = &self.sim {
+            return self.wait_simulated(sim, timeout, skip, filter);
+        }
+        let deadline = match timeout {
+            0 => None,
+            ms => Some(Instant::now() + Duration::from_millis(ms as u64)),
+        };
+        let mut state = self.state();
+        loop {
+            Self::checkpoint(&state);
+            if let Some(message) = Self::find(&mut state, &mut skip, &filter) {
+                return Some(message);
+            }
+            state = match deadline {
+                None => self
+                    .wake
+                    .wait(state)
+                    .unwrap_or_else(|poisoned| poisoned.into_inner()),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return None;
+                    }
+                    self.wake
+                        .wait_timeout(state, deadline - now)
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                        .0
+                }
+            };
+        }
+    }
+
+    fn wait_simulated(
+        &self,
+        sim: &Sim,
+        timeout: u32,
+        mut skip: usize,
+        filter: impl Fn(&Msg) -> bool,
+    ) -> Option<Msg> {
+        let deadline = match timeout {
+            0 => None,
+            ms => Some(sim.now() + Duration::from_millis(ms as u64)),
+        };
+        loop {
+            {
+                let mut state = self.state();
+                Self::checkpoint(&state);
+                if let Some(message) = Self::find(&mut state, &mut skip, &filter) {
+                    return Some(message);
+                }
+            }
+            if matches!(deadline, Some(deadline) if sim.now() >= deadline) {
+                return None;
+            }
+            if !sim.block(self.id, deadline, true) {
+                return None;
+            }
+        }
+    }
+
+    // Removes the first message after `skip` that satisfies `filter` from the mailbox. Other
+    // processes only append to the mailbox, so `skip` is moved past the searched messages.
+    fn find(state: &mut ProcState, skip: &mut usize, filter: impl Fn(&Msg) -> bool) -> Option<Msg> {
+        match state.mailbox.iter().skip(*skip).position(filter) {
+            Some(index) => {
+                let message = state.mailbox.remove(*skip + index)?;
+                if message.kind == DOWN {
+                    state.downs -= 1;
+                }
+                Some(message)
+            }
+            None => {
+                *skip = state.mailbox.len();
+                None
+            }
+        }
+    }
+
+    // Blocks the process for `duration`, unless it's killed.
+    fn sleep(&self, duration: Duration) {
+        if let Some(sim) = &self.sim {
+            let deadline = sim.now() + duration;
+            while sim.now() < deadline {
+                Self::checkpoint(&self.state());
+                if !sim.block(self.id, Some(deadline), false) {
+                    return;
+                }
+            }
+            return Self::checkpoint(&self.state());
+        }
+        let deadline = Instant::now() + duration;
+        let mut state = self.state();
+        loop {
+            Self::checkpoint(&state);
+            let now = Instant::now();
+            if now >= deadline {
+                return;
+            }
+            state = self
+                .wake
+                .wait_timeout(state, deadline - now)
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .0;
+        }
+    }
+}
+
+// Panic payload used to unwind killed processes.
+struct Killed;
+
+fn processes() -> MutexGuard<'static, HashMap<u128, Arc<Proc>>> {
+    static PROCESSES: OnceLock<Mutex<HashMap<u128, Arc<Proc>>>> = OnceLock::new();
+    PROCESSES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn find_process(id: u128) -> Option<Arc<Proc>> {
+    processes().get(&id).cloned()
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<Arc<Proc>>> = const { RefCell::new(None) };
+    // The scratch buffer holds the message that is being written or was just received.
+    static SCRATCH: RefCell<Msg> = RefCell::new(Msg::default());
+    static CURSOR: Cell<usize> = const { Cell::new(0) };
+}
+
+// Returns the process running on the current thread. Threads that were not spawned by the host,
+// like the ones running native tests, become processes of the root environment on first use.
+fn current() -> Arc<Proc> {
+    CURRENT.with(|current| {
+        current
+            .borrow_mut()
+            .get_or_insert_with(|| Proc::new(root_env(), None))
+            .clone()
+    })
+}
+
+// Starts `entry` as a new process on its own thread.
+fn start(proc: Arc<Proc>, entry: impl FnOnce() + Send + 'static) {
+    thread::spawn(move || {
+        CURRENT.with(|current| *current.borrow_mut() = Some(proc.clone()));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            if let Some(sim) = &proc.sim {
+                sim.acquire(proc.id);
+                Proc::checkpoint(&proc.state());
+            }
+            entry()
+        }));
+        let (reason, message) = match result {
+            Ok(()) => (REASON_NORMAL, None),
+            Err(payload) => exit_reason(&proc, payload),
+        };
+        exit(&proc, reason, message);
+    });
+}
+
+fn exit_reason(proc: &Proc, payload: Box<dyn Any + Send>) -> (u32, Option<String>) {
+    let mut state = proc.state();
+    if payload.is::<Killed>() {
+        return (state.killed.unwrap_or(REASON_KILLED), None);
+    }
+    let message =
+        state
+            .panic_message
+            .take()
+            .unwrap_or_else(|| match payload.downcast_ref::<&str>() {
+                Some(message) => message.to_string(),
+                None => match payload.downcast_ref::<String>() {
+                    Some(message) => message.clone(),
+                    None => "Box<Any>".to_string(),
+                },
+            });
+    (REASON_PANIC, Some(message))
+}
+
+// Notifies links and monitors that the process died.
+fn exit(proc: &Arc<Proc>, reason: u32, message: Option<String>) {
+    let (links, monitors) = {
+        let mut state = proc.state();
+        state.alive = false;
+        state.mailbox.clear();
+        (
+            std::mem::take(&mut state.links),
+            std::mem::take(&mut state.monitors),
+        )
+    };
+    processes().remove(&proc.id);
+    let data = message.clone().map(String::into_bytes).unwrap_or_default();
+    for (tag, id) in links {
+        if let Some(linked) = find_process(id) {
+            let trap = {
+                let mut state = linked.state();
+                state.links.retain(|(_, link)| *link != proc.id);
+                state.trap
+            };
+            if trap {
+                let signal = Msg {
+                    kind: SIGNAL,
+                    tag,
+                    data: data.clone(),
+                    reason,
+                    process: proc.id,
+                    ..Msg::default()
+                };
+                linked.post(proc.id, Event::Message(signal));
+            } else if reason != REASON_NORMAL {
+                linked.post(proc.id, Event::Kill(REASON_KILLED));
+            }
+        }
+    }
+    for (tag, id) in monitors {
+        if let Some(watcher) = find_process(id) {
+            let down = Msg {
+                kind: DOWN,
+                tag,
+                data: data.clone(),
+                reason,
+                process: proc.id,
+                ..Msg::default()
+            };
+            watcher.post(proc.id, Event::Message(down));
+        }
+    }
+    if let Some(sim) = &proc.sim {
+        let result = match (reason, message) {
+            (REASON_NORMAL, _) => Ok(()),
+            (REASON_PANIC, Some(message)) => Err(SimulationError::Panic(message)),
+            _ => Err(SimulationError::Killed),
+        };
+        sim.exit(proc.id, result);
+    }
+}
+
+/// Returns the current time, which is virtual inside of a simulation.
+pub fn now() -> Instant {
+    let sim = CURRENT.with(|current| current.borrow().as_ref().and_then(|proc| proc.sim.clone()));
+    match sim {
+        Some(sim) => sim.instant(),
+        None => Instant::now(),
+    }
+}
+
+// Helpers to move data between guest pointers and the host. The guest is the same native
+// process, so pointers can be used directly.
+unsafe fn read_str<'a>(ptr: *const u8, len: usize) -> &'a str {
+    std::str::from_utf8(std::slice::from_raw_parts(ptr, len)).expect("Invalid UTF-8 string")
+}
+
+unsafe fn read_bytes<'a>(ptr: *const u8, len: usize) -> &'a [u8] {
+    std::slice::from_raw_parts(ptr, len)
+}
@@ -0,0 +1,680 @@
+/*! Mock of the `lunatic::networking` namespace */
+
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{self, IoSlice, Read, Write},
+    net::{
+        IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, SocketAddrV4, SocketAddrV6, TcpListener,
+        TcpStream, ToSocketAddrs, UdpSocket,
+    },
+    sync::{Arc, Mutex, MutexGuard, OnceLock},
+    time::Duration,
+};
+
+use socket2::{SockRef, TcpKeepalive};
+
+#[cfg(feature = "tls")]
+pub use super::tls::{
+    drop_tls_stream, tls_client_handshake, tls_flush, tls_read, tls_server_handshake,
+    tls_write_vectored,
+};
+use super::{
+    add_io_error, add_resource, read_bytes, read_str, remove_resource, with_resource, Resource,
+};
+
+pub(super) fn timeout(ms: u32) -> Option<Duration> {
+    match ms {
+        0 => None,
+        ms => Some(Duration::from_millis(ms as u64)),
+    }
+}
+
+unsafe fn ipv4(addr: *const u8) -> Ipv4Addr {
+    let octets = read_bytes(addr, 4);
+    Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3])
+}
+
+unsafe fn ipv6(addr: *const u8) -> Ipv6Addr {
+    let mut octets = [0; 16];
+    octets.copy_from_slice(read_bytes(addr, 16));
+    Ipv6Addr::from(octets)
+}
+
+unsafe fn socket_addr(
+    addr_type: u32,
+    addr: *const u8,
+    port: u32,
+    flow_info: u32,
+    scope_id: u32,
+) -> SocketAddr {
+    match addr_type {
+        4 => SocketAddrV4::new(ipv4(addr), port as u16).into(),
+        6 => SocketAddrV6::new(ipv6(addr), port as u16, flow_info, scope_id).into(),
+        _ => panic!("Unsupported address type {}", addr_type),
+    }
+}
+
+fn tcp_listener(id: u64) -> Arc<TcpListener> {
+    with_resource(id, |resource| match resource {
+        Resource::TcpListener(listener) => listener.clone(),
+        _ => panic!("Resource {} is not a TCP listener", id),
+    })
+}
+
+fn tcp_stream(id: u64) -> Arc<TcpStream> {
+    with_resource(id, |resource| match resource {
+        Resource::TcpStream(stream) => stream.clone(),
+        _ => panic!("Resource {} is not a TCP stream", id),
+    })
+}
+
+fn udp_socket(id: u64) -> Arc<UdpSocket> {
+    with_resource(id, |resource| match resource {
+        Resource::UdpSocket(socket) => socket.clone(),
+        _ => panic!("Resource {} is not a UDP socket", id),
+    })
+}
+
+// Writes the error of a host call that only returns success or failure.
+pub(super) unsafe fn unit_result(result: io::Result<()>, error_id: *mut u64) -> u32 {
+    match result {
+        Ok(()) => 0,
+        Err(error) => {
+            *error_id = add_io_error(error);
+            1
+        }
+    }
+}
+
+// Writes the value or the error of a host call into the same place.
+pub(super) unsafe fn opaque_result(result: io::Result<u64>, opaque: *mut u64) -> u32 {
+    match result {
+        Ok(value) => {
+            *opaque = value;
+            0
+        }
+        Err(error) => {
+            *opaque = add_io_error(error);
+            1
+        }
+    }
+}
+
+// Number of host lookups of each name and IP address.
+fn lookups() -> MutexGuard<'static, HashMap<String, u64>> {
+    static LOOKUPS: OnceLock<Mutex<HashMap<String, u64>>> = OnceLock::new();
+    LOOKUPS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+pub(crate) fn lookup_count(name: &str) -> u64 {
+    lookups().get(name).copied().unwrap_or_default()
+}
+
+fn addr_resource(addr: SocketAddr) -> u64 {
+    add_resource(Resource::DnsIterator(VecDeque::from(vec![addr])))
+}
+
+pub unsafe fn resolve(
+    name_str: *const u8,
+    name_str_len: usize,
+    _timeout: u32,
+    id: *mut u64,
+) -> u32 {
+    let name = read_str(name_str, name_str_len);
+    let host = name.rsplit_once(':').map_or(name, |(host, _)| host);
+    *lookups().entry(host.to_string()).or_default() += 1;
+    match name.to_socket_addrs() {
+        Ok(addrs) => {
+            *id = add_resource(Resource::DnsIterator(addrs.collect()));
+            0
+        }
+        Err(error) => {
+            *id = add_io_error(error);
+            1
+        }
+    }
+}
+
+pub unsafe fn drop_dns_iterator(dns_iter_id: u64) {
+    remove_resource(dns_iter_id);
+}
+
+pub unsafe fn resolve_next(
+    dns_iter_id: u64,
+    addr_type: *mut u32,
+    addr: *mut u8,
+    port: *mut u16,
+    flow_info: *mut u32,
+    scope_id: *mut u32,
+) -> u32 {
+    let next = with_resource(dns_iter_id, |resource| match resource {
+        Resource::DnsIterator(addrs) => addrs.pop_front(),
+        _ => panic!("Resource {} is not a DNS iterator", dns_iter_id),
+    });
+    match next {
+        Some(SocketAddr::V4(v4)) => {
+            *addr_type = 4;
+            std::ptr::copy_nonoverlapping(v4.ip().octets().as_ptr(), addr, 4);
+            *port = v4.port();
+            0
+        }
+        Some(SocketAddr::V6(v6)) => {
+            *addr_type = 6;
+            std::ptr::copy_nonoverlapping(v6.ip().octets().as_ptr(), addr, 16);
+            *port = v6.port();
+            *flow_info = v6.flowinfo();
+            *scope_id = v6.scope_id();
+            0
+        }
+        None => 1,
+    }
+}
+
+// Writes at most `name_len` bytes of the name and returns its full length.
+pub unsafe fn resolve_reverse(
+    addr_type: u32,
+    addr: *const u8,
+    _timeout: u32,
+    name: *mut u8,
+    name_len: usize,
+    opaque: *mut u64,
+) -> u32 {
+    let ip = match addr_type {
+        4 => IpAddr::V4(ipv4(addr)),
+        6 => IpAddr::V6(ipv6(addr)),
+        _ => panic!("Unsupported address type {}", addr_type),
+    };
+    *lookups().entry(ip.to_string()).or_default() += 1;
+    let result = dns_lookup::lookup_addr(&ip).map(|found| {
+        let len = found.len().min(name_len);
+        std::ptr::copy_nonoverlapping(found.as_ptr(), name, len);
+        found.len() as u64
+    });
+    opaque_result(result, opaque)
+}
+
+pub unsafe fn tcp_bind(
+    addr_type: u32,
+    addr: *const u8,
+    port: u32,
+    flow_info: u32,
+    scope_id: u32,
+    id: *mut u64,
+) -> u32 {
+    let addr = socket_addr(addr_type, addr, port, flow_info, scope_id);
+    match TcpListener::bind(addr) {
+        Ok(listener) => {
+            *id = add_resource(Resource::TcpListener(Arc::new(listener)));
+            0
+        }
+        Err(error) => {
+            *id = add_io_error(error);
+            1
+        }
+    }
+}
+
+pub unsafe fn drop_tcp_listener(tcp_listener_id: u64) {
+    remove_resource(tcp_listener_id);
+}
+
+pub unsafe fn tcp_accept(listener_id: u64, id: *mut u64, peer_dns_iter: *mut u64) -> u32 {
+    // The resource table is not locked while blocking.
+    let listener = tcp_listener(listener_id);
+    match listener.accept() {
+        Ok((stream, peer)) => {
+            *id = add_resource(Resource::TcpStream(Arc::new(stream)));
+            *peer_dns_iter = add_resource(Resource::DnsIterator(VecDeque::from(vec![peer])));
+            0
+        }
+        Err(error) => {
+            *id = add_io_error(error);
+            1
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn tcp_connect(
+    addr_type: u32,
+    addr: *const u8,
+    port: u32,
+    flow_info: u32,
+    scope_id: u32,
+    timeout_ms: u32,
+    id: *mut u64,
+) -> u32 {
+    let addr = socket_addr(addr_type, addr, port, flow_info, scope_id);
+    let result = match timeout(timeout_ms) {
+        Some(timeout) => TcpStream::connect_timeout(&addr, timeout),
+        None => TcpStream::connect(addr),
+    };
+    match result {
+        Ok(stream) => {
+            *id = add_resource(Resource::TcpStream(Arc::new(stream)));
+            0
+        }
+        Err(error) => {
+            *id = add_io_error(error);
+            1
+        }
+    }
+}
+
+pub unsafe fn drop_tcp_stream(tcp_stream_id: u64) {
+    remove_resource(tcp_stream_id);
+}
+
+pub unsafe fn clone_tcp_stream(tcp_stream_id: u64) -> u64 {
+    add_resource(Resource::TcpStream(tcp_stream(tcp_stream_id)))
+}
+
+pub unsafe fn tcp_write_vectored(
+    tcp_stream_id: u64,
+    ciovec_array: *const u32,
+    ciovec_array_len: usize,
+    timeout_ms: u32,
+    opaque: *mut u64,
+) -> u32 {
+    // The guest passes a slice of `IoSlice`s, that are native `iovec`s here.
+    let bufs = std::slice::from_raw_parts(ciovec_array as *const IoSlice, ciovec_array_len);
+    let stream = tcp_stream(tcp_stream_id);
+    let result = stream
+        .set_write_timeout(timeout(timeout_ms))
+        .and_then(|_| (&*stream).write_vectored(bufs));
+    match result {
+        Ok(written) => {
+            *opaque = written as u64;
+            0
+        }
+        Err(error) => {
+            *opaque = add_io_error(error);
+            1
+        }
+    }
+}
+
+pub unsafe fn tcp_read(
+    tcp_stream_id: u64,
+    buffer: *mut u8,
+    buffer_len: usize,
+    timeout_ms: u32,
+    opaque: *mut u64,
+) -> u32 {
+    let buffer = std::slice::from_raw_parts_mut(buffer, buffer_len);
+    let stream = tcp_stream(tcp_stream_id);
+    let result = stream
+        .set_read_timeout(timeout(timeout_ms))
+        .and_then(|_| (&*stream).read(buffer));
+    match result {
+        Ok(read) => {
+            *opaque = read as u64;
+            0
+        }
+        Err(error) => {
+            *opaque = add_io_error(error);
+            1
+        }
+    }
+}
+
+pub unsafe fn tcp_flush(tcp_stream_id: u64, error_id: *mut u64) -> u32 {
+    match (&*tcp_stream(tcp_stream_id)).flush() {
+        Ok(()) => 0,
+        Err(error) => {
+            *error_id = add_io_error(error);
+            1
+        }
+    }
+}
+
+pub unsafe fn tcp_peek(
+    tcp_stream_id: u64,
+    buffer: *mut u8,
+    buffer_len: usize,
+    timeout_ms: u32,
+    opaque: *mut u64,
+) -> u32 {
+    let buffer = std::slice::from_raw_parts_mut(buffer, buffer_len);
+    let stream = tcp_stream(tcp_stream_id);
+    let result = stream
+        .set_read_timeout(timeout(timeout_ms))
+        .and_then(|_| stream.peek(buffer))
+        .map(|read| read as u64);
+    opaque_result(result, opaque)
+}
+
+pub unsafe fn tcp_shutdown(tcp_stream_id: u64, how: u32, error_id: *mut u64) -> u32 {
+    let how = match how {
+        0 => Shutdown::Read,
+        1 => Shutdown::Write,
+        _ => Shutdown::Both,
+    };
+    unit_result(tcp_stream(tcp_stream_id).shutdown(how), error_id)
+}
+
+pub unsafe fn tcp_local_addr(tcp_stream_id: u64, opaque: *mut u64) -> u32 {
+    let result = tcp_stream(tcp_stream_id).local_addr().map(addr_resource);
+    opaque_result(result, opaque)
+}
+
+pub unsafe fn tcp_peer_addr(tcp_stream_id: u64, opaque: *mut u64) -> u32 {
+    let result = tcp_stream(tcp_stream_id).peer_addr().map(addr_resource);
+    opaque_result(result, opaque)
+}
+
+pub unsafe fn tcp_listener_local_addr(tcp_listener_id: u64, opaque: *mut u64) -> u32 {
+    let result = tcp_listener(tcp_listener_id)
+        .local_addr()
+        .map(addr_resource);
+    opaque_result(result, opaque)
+}
+
+pub unsafe fn set_tcp_stream_nodelay(tcp_stream_id: u64, value: u32, error_id: *mut u64) -> u32 {
+    unit_result(tcp_stream(tcp_stream_id).set_nodelay(value != 0), error_id)
+}
+
+pub unsafe fn get_tcp_stream_nodelay(tcp_stream_id: u64, opaque: *mut u64) -> u32 {
+    let result = tcp_stream(tcp_stream_id).nodelay().map(u64::from);
+    opaque_result(result, opaque)
+}
+
+pub unsafe fn set_tcp_stream_ttl(tcp_stream_id: u64, value: u32, error_id: *mut u64) -> u32 {
+    unit_result(tcp_stream(tcp_stream_id).set_ttl(value), error_id)
+}
+
+pub unsafe fn get_tcp_stream_ttl(tcp_stream_id: u64, opaque: *mut u64) -> u32 {
+    let result = tcp_stream(tcp_stream_id).ttl().map(u64::from);
+    opaque_result(result, opaque)
+}
+
+pub unsafe fn set_tcp_stream_keepalive(
+    tcp_stream_id: u64,
+    interval_ms: u64,
+    error_id: *mut u64,
+) -> u32 {
+    // The standard library has no keepalive options, so they are set on the raw socket.
+    let stream = tcp_stream(tcp_stream_id);
+    let socket = SockRef::from(&*stream);
+    let result = match interval_ms {
+        0 => socket.set_keepalive(false),
+        ms => {
+            let keepalive = TcpKeepalive::new().with_time(Duration::from_millis(ms));
+            socket.set_tcp_keepalive(&keepalive)
+        }
+    };
+    unit_result(result, error_id)
+}
+
+pub unsafe fn get_tcp_stream_keepalive(tcp_stream_id: u64, opaque: *mut u64) -> u32 {
+    let stream = tcp_stream(tcp_stream_id);
+    let socket = SockRef::from(&*stream);
+    let result = socket.keepalive().and_then(|enabled| match enabled {
+        true => keepalive_time(&socket),
+        false => Ok(0),
+    });
+    opaque_result(result, opaque)
+}
+
+#[cfg(unix)]
+fn keepalive_time(socket: &SockRef<'_>) -> io::Result<u64> {
+    socket.keepalive_time().map(|time| time.as_millis() as u64)
+}
+
+#[cfg(not(unix))]
+fn keepalive_time(_: &SockRef<'_>) -> io::Result<u64> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "Reading the keepalive time is not supported on this system",
+    ))
+}
+
+pub unsafe fn udp_bind(
+    addr_type: u32,
+    addr: *const u8,
+    port: u32,
+    flow_info: u32,
+    scope_id: u32,
+    id: *mut u64,
+) -> u32 {
+    let addr = socket_addr(addr_type, addr, port, flow_info, scope_id);
+    let result =
+        UdpSocket::bind(addr).map(|socket| add_resource(Resource::UdpSocket(Arc::new(socket))));
+    opaque_result(result, id)
+}
+
+pub unsafe fn drop_udp_socket(udp_socket_id: u64) {
+    remove_resource(udp_socket_id);
+}
+
+pub unsafe fn clone_udp_socket(udp_socket_id: u64) -> u64 {
+    add_resource(Resource::UdpSocket(udp_socket(udp_socket_id)))
+}
+
+pub unsafe fn udp_connect(
+    udp_socket_id: u64,
+    addr_type: u32,
+    addr: *const u8,
+    port: u32,
+    flow_info: u32,
+    scope_id: u32,
+    error_id: *mut u64,
+) -> u32 {
+    let addr = socket_addr(addr_type, addr, port, flow_info, scope_id);
+    unit_result(udp_socket(udp_socket_id).connect(addr), error_id)
+}
+
+pub unsafe fn udp_send(
+    udp_socket_id: u64,
+    buffer: *const u8,
+    buffer_len: usize,
+    timeout_ms: u32,
+    opaque: *mut u64,
+) -> u32 {
+    let buffer = read_bytes(buffer, buffer_len);
+    let socket = udp_socket(udp_socket_id);
+    let result = socket
+        .set_write_timeout(timeout(timeout_ms))
+        .and_then(|_| socket.send(buffer))
+        .map(|sent| sent as u64);
+    opaque_result(result, opaque)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn udp_send_to(
+    udp_socket_id: u64,
+    buffer: *const u8,
+    buffer_len: usize,
+    addr_type: u32,
+    addr: *const u8,
+    port: u32,
+    flow_info: u32,
+    scope_id: u32,
+    timeout_ms: u32,
+    opaque: *mut u64,
+) -> u32 {
+    let buffer = read_bytes(buffer, buffer_len);
+    let addr = socket_addr(addr_type, addr, port, flow_info, scope_id);
+    let socket = udp_socket(udp_socket_id);
+    let result = socket
+        .set_write_timeout(timeout(timeout_ms))
+        .and_then(|_| socket.send_to(buffer, addr))
+        .map(|sent| sent as u64);
+    opaque_result(result, opaque)
+}
+
+pub unsafe fn udp_receive(
+    udp_socket_id: u64,
+    buffer: *mut u8,
+    buffer_len: usize,
+    peek: u32,
+    timeout_ms: u32,
+    opaque: *mut u64,
+) -> u32 {
+    let buffer = std::slice::from_raw_parts_mut(buffer, buffer_len);
+    let socket = udp_socket(udp_socket_id);
+    let result = socket
+        .set_read_timeout(timeout(timeout_ms))
+        .and_then(|_| match peek {
+            0 => socket.recv(buffer),
+            _ => socket.peek(buffer),
+        })
+        .map(|read| read as u64);
+    opaque_result(result, opaque)
+}
+
+pub unsafe fn udp_receive_from(
+    udp_socket_id: u64,
+    buffer: *mut u8,
+    buffer_len: usize,
+    peek: u32,
+    timeout_ms: u32,
+    opaque: *mut u64,
+    peer_dns_iter: *mut u64,
+) -> u32 {
+    let buffer = std::slice::from_raw_parts_mut(buffer, buffer_len);
+    let socket = udp_socket(udp_socket_id);
+    let result = socket
+        .set_read_timeout(timeout(timeout_ms))
+        .and_then(|_| match peek {
+            0 => socket.recv_from(buffer),
+            _ => socket.peek_from(buffer),
+        });
+    match result {
+        Ok((read, peer)) => {
+            *opaque = read as u64;
+            *peer_dns_iter = addr_resource(peer);
+            0
+        }
+        Err(error) => {
+            *opaque = add_io_error(error);
+            1
+        }
+    }
+}
+
+pub unsafe fn udp_local_addr(udp_socket_id: u64, opaque: *mut u64) -> u32 {
+    let result = udp_socket(udp_socket_id).local_addr().map(addr_resource);
+    opaque_result(result, opaque)
+}
+
+pub unsafe fn udp_peer_addr(udp_socket_id: u64, opaque: *mut u64) -> u32 {
+    let result = udp_socket(udp_socket_id).peer_addr().map(addr_resource);
+    opaque_result(result, opaque)
+}
+
+pub unsafe fn set_udp_socket_broadcast(udp_socket_id: u64, value: u32, error_id: *mut u64) -> u32 {
+    unit_result(
+        udp_socket(udp_socket_id).set_broadcast(value != 0),
+        error_id,
+    )
+}
+
+pub unsafe fn get_udp_socket_broadcast(udp_socket_id: u64, opaque: *mut u64) -> u32 {
+    let result = udp_socket(udp_socket_id).broadcast().map(u64::from);
+    opaque_result(result, opaque)
+}
+
+pub unsafe fn set_udp_socket_ttl(udp_socket_id: u64, value: u32, error_id: *mut u64) -> u32 {
+    unit_result(udp_socket(udp_socket_id).set_ttl(value), error_id)
+}
+
+pub unsafe fn get_udp_socket_ttl(udp_socket_id: u64, opaque: *mut u64) -> u32 {
+    let result = udp_socket(udp_socket_id).ttl().map(u64::from);
+    opaque_result(result, opaque)
+}
+
+pub unsafe fn set_udp_socket_multicast_loop_v4(
+    udp_socket_id: u64,
+    value: u32,
+    error_id: *mut u64,
+) -> u32 {
+    let socket = udp_socket(udp_socket_id);
+    unit_result(socket.set_multicast_loop_v4(value != 0), error_id)
+}
+
+pub unsafe fn get_udp_socket_multicast_loop_v4(udp_socket_id: u64, opaque: *mut u64) -> u32 {
+    let result = udp_socket(udp_socket_id).multicast_loop_v4().map(u64::from);
+    opaque_result(result, opaque)
+}
+
+pub unsafe fn set_udp_socket_multicast_ttl_v4(
+    udp_socket_id: u64,
+    value: u32,
+    error_id: *mut u64,
+) -> u32 {
+    unit_result(
+        udp_socket(udp_socket_id).set_multicast_ttl_v4(value),
+        error_id,
+    )
+}
+
+pub unsafe fn get_udp_socket_multicast_ttl_v4(udp_socket_id: u64, opaque: *mut u64) -> u32 {
+    let result = udp_socket(udp_socket_id).multicast_ttl_v4().map(u64::from);
+    opaque_result(result, opaque)
+}
+
+pub unsafe fn set_udp_socket_multicast_loop_v6(
+    udp_socket_id: u64,
+    value: u32,
+    error_id: *mut u64,
+) -> u32 {
+    let socket = udp_socket(udp_socket_id);
+    unit_result(socket.set_multicast_loop_v6(value != 0), error_id)
+}
+
+pub unsafe fn get_udp_socket_multicast_loop_v6(udp_socket_id: u64, opaque: *mut u64) -> u32 {
+    let result = udp_socket(udp_socket_id).multicast_loop_v6().map(u64::from);
+    opaque_result(result, opaque)
+}
+
+pub unsafe fn udp_join_multicast_v4(
+    udp_socket_id: u64,
+    multiaddr: *const u8,
+    interface: *const u8,
+    error_id: *mut u64,
+) -> u32 {
+    let socket = udp_socket(udp_socket_id);
+    let result = socket.join_multicast_v4(&ipv4(multiaddr), &ipv4(interface));
+    unit_result(result, error_id)
+}
+
+pub unsafe fn udp_leave_multicast_v4(
+    udp_socket_id: u64,
+    multiaddr: *const u8,
+    interface: *const u8,
+    error_id: *mut u64,
+) -> u32 {
+    let socket = udp_socket(udp_socket_id);
+    let result = socket.leave_multicast_v4(&ipv4(multiaddr), &ipv4(interface));
+    unit_result(result, error_id)
+}
+
+pub unsafe fn udp_join_multicast_v6(
+    udp_socket_id: u64,
+    multiaddr: *const u8,
+    interface: u32,
+    error_id: *mut u64,
+) -> u32 {
+    let socket = udp_socket(udp_socket_id);
+    unit_result(
+        socket.join_multicast_v6(&ipv6(multiaddr), interface),
+        error_id,
+    )
+}
+
+pub unsafe fn udp_leave_multicast_v6(
+    udp_socket_id: u64,
+    multiaddr: *const u8,
+    interface: u32,
+    error_id: *mut u64,
+) -> u32 {
+    let socket = udp_socket(udp_socket_id);
+    unit_result(
+        socket.leave_multicast_v6(&ipv6(multiaddr), interface),
+        error_id,
+    )
+}
@@ -0,0 +1,334 @@
+/*! Mock of the `lunatic::process` namespace */
+
+use std::{sync::Arc, time::Duration};
+
+use super::{
+    add_error, add_resource, current, environment_resource, process_resource, read_bytes, read_str,
+    remove_resource,
+    semver::{Query, Version},
+    sim::Event,
+    start, with_resource, Config, Env, Msg, Proc, Resource, DOWN, REASON_KILLED, REASON_NORMAL,
+    SIGNAL,
+};
+
+pub unsafe fn create_config(max_memory: u64, max_fuel: u64) -> u64 {
+    add_resource(Resource::Config(Config {
+        max_memory,
+        max_fuel,
+        namespaces: Vec::new(),
+    }))
+}
+
+pub unsafe fn drop_config(config_id: u64) {
+    remove_resource(config_id);
+}
+
+pub unsafe fn allow_namespace(config_id: u64, name: *const u8, name_len: usize) {
+    let name = read_str(name, name_len).to_string();
+    with_resource(config_id, |resource| match resource {
+        Resource::Config(config) => config.namespaces.push(name),
+        _ => panic!("Resource {} is not a config", config_id),
+    });
+}
+
+pub unsafe fn add_plugin(
+    _config_id: u64,
+    _plugin_data: *const u8,
+    _plugin_data_len: usize,
+    id: *mut u64,
+) -> u32 {
+    *id = add_error("Plugins are not supported by the mock host");
+    1
+}
+
+pub unsafe fn create_environment(_config_id: u64, id: *mut u64) -> u32 {
+    *id = add_resource(Resource::Environment(Arc::new(Env::default())));
+    0
+}
+
+pub unsafe fn drop_environment(env_id: u64) {
+    remove_resource(env_id);
+}
+
+pub unsafe fn add_module(
+    _env_id: u64,
+    _module_data: *const u8,
+    _module_data_len: usize,
+    id: *mut u64,
+) -> u32 {
+    *id = add_error("Loading WebAssembly modules is not supported by the mock host");
+    1
+}
+
+pub unsafe fn add_this_module(env_id: u64, id: *mut u64) -> u32 {
+    *id = add_resource(Resource::Module(environment_resource(env_id)));
+    0
+}
+
+pub unsafe fn drop_module(mod_id: u64) {
+    remove_resource(mod_id);
+}
+
+pub unsafe fn spawn(
+    link: i64,
+    module_id: u64,
+    function: *const u8,
+    function_len: usize,
+    params: *const u8,
+    params_len: usize,
+    id: *mut u64,
+) -> u32 {
+    let env = with_resource(module_id, |resource| match resource {
+        Resource::Module(env) => env.clone(),
+        _ => panic!("Resource {} is not a module", module_id),
+    });
+    spawn_in(env, link, function, function_len, params, params_len, id)
+}
+
+pub unsafe fn inherit_spawn(
+    link: i64,
+    function: *const u8,
+    function_len: usize,
+    params: *const u8,
+    params_len: usize,
+    id: *mut u64,
+) -> u32 {
+    let env = current().env.clone();
+    spawn_in(env, link, function, function_len, params, params_len, id)
+}
+
+unsafe fn spawn_in(
+    env: Arc<Env>,
+    link: i64,
+    function: *const u8,
+    function_len: usize,
+    params: *const u8,
+    params_len: usize,
+    id: *mut u64,
+) -> u32 {
+    // Only functions of this module can be started, and there is just one entry point for them.
+    let function = read_str(function, function_len);
+    if function != "_lunatic_spawn_by_index" {
+        *id = add_error(format!(
+            "Function {} can't be spawned by the mock host",
+            function
+        ));
+        return 1;
+    }
+    // Each parameter is encoded as a type byte followed by a 16 byte little-endian value.
+    let params: Vec<usize> = read_bytes(params, params_len)
+        .chunks(17)
+        .map(|param| {
+            let mut value = [0; 16];
+            value.copy_from_slice(&param[1..]);
+            u128::from_le_bytes(value) as usize
+        })
+        .collect();
+    let (type_helper, entry) = match params[..] {
+        [type_helper, entry] => (type_helper, entry),
+        _ => {
+            *id = add_error("Wrong number of parameters");
+            return 1;
+        }
+    };
+
+    let parent = current();
+    let child = Proc::new(env, parent.sim.clone());
+    if link != 0 {
+        parent.state().links.push((link, child.id));
+        child.state().links.push((link, parent.id));
+    }
+    *id = add_resource(Resource::Process(child.clone()));
+    start(child, move || {
+        crate::process::spawn_by_index(type_helper, entry);
+    });
+    0
+}
+
+pub unsafe fn drop_process(process_id: u64) {
+    remove_resource(process_id);
+}
+
+pub unsafe fn kill(process_id: u64) {
+    process_resource(process_id).post(current().id, Event::Kill(REASON_KILLED));
+}
+
+pub unsafe fn clone_process(process_id: u64) -> u64 {
+    add_resource(Resource::Process(process_resource(process_id)))
+}
+
+pub unsafe fn sleep_ms(millis: u64) {
+    current().sleep(Duration::from_millis(millis));
+}
+
+pub unsafe fn die_when_link_dies(trap: u32) {
+    current().state().trap = trap == 0;
+}
+
+pub unsafe fn set_panic_message(message: *const u8, message_len: usize) {
+    let message = String::from_utf8_lossy(read_bytes(message, message_len)).into_owned();
+    current().state().panic_message = Some(message);
+}
+
+pub unsafe fn this() -> u64 {
+    add_resource(Resource::Process(current()))
+}
+
+pub unsafe fn id(process_id: u64, uuid: *mut [u8; 16]) {
+    *uuid = process_resource(process_id).id.to_le_bytes();
+}
+
+pub unsafe fn this_env() -> u64 {
+    add_resource(Resource::Environment(current().env.clone()))
+}
+
+pub unsafe fn link(tag: i64, process_id: u64) {
+    let this = current();
+    let other = process_resource(process_id);
+    let alive = {
+        let mut state = other.state();
+        if state.alive {
+            state.links.push((tag, this.id));
+        }
+        state.alive
+    };
+    if alive {
+        this.state().links.push((tag, other.id));
+    } else if this.state().trap {
+        // Linking to a dead process is reported right away.
+        this.deliver(Msg {
+            kind: SIGNAL,
+            tag,
+            reason: REASON_NORMAL,
+            process: other.id,
+            ..Msg::default()
+        });
+    }
+}
+
+pub unsafe fn unlink(process_id: u64) {
+    let this = current();
+    let other = process_resource(process_id);
+    this.state().links.retain(|(_, id)| *id != other.id);
+    other.state().links.retain(|(_, id)| *id != this.id);
+}
+
+pub unsafe fn monitor(tag: i64, process_id: u64) {
+    let this = current();
+    let other = process_resource(process_id);
+    let alive = {
+        let mut state = other.state();
+        if state.alive {
+            state.monitors.push((tag, this.id));
+        }
+        state.alive
+    };
+    if !alive {
+        // Monitoring a dead process delivers the notification right away.
+        this.deliver(Msg {
+            kind: DOWN,
+            tag,
+            reason: REASON_NORMAL,
+            process: other.id,
+            ..Msg::default()
+        });
+    }
+}
+
+pub unsafe fn demonitor(tag: i64, process_id: u64) {
+    let this = current();
+    let other = process_resource(process_id);
+    other
+        .state()
+        .monitors
+        .retain(|monitor| *monitor != (tag, this.id));
+    // Remove the notification if it was already delivered.
+    let mut state = this.state();
+    if state.downs > 0 {
+        let before = state.mailbox.len();
+        state
+            .mailbox
+            .retain(|message| !(message.kind == DOWN && message.tag == tag));
+        state.downs -= before - state.mailbox.len();
+    }
+}
+
+pub unsafe fn register(
+    name: *const u8,
+    name_len: usize,
+    version: *const u8,
+    version_len: usize,
+    env_id: u64,
+    process_id: u64,
+) -> u32 {
+    let name = read_str(name, name_len).to_string();
+    let version = match Version::parse(read_str(version, version_len)) {
+        Some(version) => version,
+        None => return 1,
+    };
+    let env = environment_resource(env_id);
+    let process = process_resource(process_id);
+    let mut registry = env.registry.lock().unwrap();
+    let versions = registry.entry(name).or_default();
+    versions.retain(|(existing, _)| *existing != version);
+    versions.push((version, process));
+    0
+}
+
+pub unsafe fn unregister(
+    name: *const u8,
+    name_len: usize,
+    version: *const u8,
+    version_len: usize,
+    env_id: u64,
+) -> u32 {
+    let name = read_str(name, name_len);
+    let version = match Version::parse(read_str(version, version_len)) {
+        Some(version) => version,
+        None => return 1,
+    };
+    let env = environment_resource(env_id);
+    let mut registry = env.registry.lock().unwrap();
+    let versions = match registry.get_mut(name) {
+        Some(versions) => versions,
+        None => return 2,
+    };
+    let before = versions.len();
+    versions.retain(|(existing, _)| *existing != version);
+    if versions.len() == before {
+        2
+    } else {
+        0
+    }
+}
+
+pub unsafe fn lookup(
+    name: *const u8,
+    name_len: usize,
+    query: *const u8,
+    query_len: usize,
+    id: *mut u64,
+) -> u32 {
+    let name = read_str(name, name_len);
+    let query = match Query::parse(read_str(query, query_len)) {
+        Some(query) => query,
+        None => return 1,
+    };
+    let env = current().env.clone();
+    let registry = env.registry.lock().unwrap();
+    // The newest matching version is returned.
+    let found = registry.get(name).and_then(|versions| {
+        versions
+            .iter()
+            .filter(|(version, _)| query.matches(*version))
+            .max_by_key(|(version, _)| *version)
+            .map(|(_, process)| process.clone())
+    });
+    match found {
+        Some(process) => {
+            *id = add_resource(Resource::Process(process));
+            0
+        }
+        None => 2,
+    }
+}
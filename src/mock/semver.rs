@@ -0,0 +1,139 @@
+// Just enough semver to implement the process registry.
+
+use std::cmp::Ordering;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct Version(u64, u64, u64);
+
+impl Version {
+    /// Parses a full `major.minor.patch` version.
+    pub(crate) fn parse(version: &str) -> Option<Self> {
+        let parts = parse_parts(version)?;
+        match parts[..] {
+            [major, minor, patch] => Some(Version(major, minor, patch)),
+            _ => None,
+        }
+    }
+}
+
+/// A version requirement like `^1.2`, `~1.2.3`, `>=1`, `=1.0.0` or `*`.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Query {
+    op: Op,
+    parts: Vec<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Exact,
+    Caret,
+    Tilde,
+    Greater,
+    GreaterEq,
+    Less,
+    LessEq,
+    Any,
+}
+
+impl Query {
+    pub(crate) fn parse(query: &str) -> Option<Self> {
+        let query = query.trim();
+        if query == "*" {
+            return Some(Query {
+                op: Op::Any,
+                parts: Vec::new(),
+            });
+        }
+        let (op, rest) = [
+            (">=", Op::GreaterEq),
+            ("<=", Op::LessEq),
+            (">", Op::Greater),
+            ("<", Op::Less),
+            ("=", Op::Exact),
+            ("^", Op::Caret),
+            ("~", Op::Tilde),
+        ]
+        .iter()
+        .find_map(|(prefix, op)| query.strip_prefix(prefix).map(|rest| (*op, rest)))
+        // A requirement without an operator is the same as a caret requirement.
+        .unwrap_or((Op::Caret, query));
+        let parts = parse_parts(rest.trim())?;
+        if parts.len() > 3 {
+            return None;
+        }
+        Some(Query { op, parts })
+    }
+
+    pub(crate) fn matches(&self, version: Version) -> bool {
+        let Version(major, minor, patch) = version;
+        let given = [major, minor, patch];
+        // Compares only the components present in the query.
+        let compare = || given[..self.parts.len()].cmp(&self.parts[..]);
+        match self.op {
+            Op::Any => true,
+            Op::Exact => compare() == Ordering::Equal,
+            Op::Greater => compare() == Ordering::Greater,
+            Op::GreaterEq => compare() != Ordering::Less,
+            Op::Less => compare() == Ordering::Less,
+            Op::LessEq => compare() != Ordering::Greater,
+            Op::Tilde => {
+                // Everything after the minor version (or major if it's missing) can change.
+                let fixed = self.parts.len().min(2);
+                given[..fixed] == self.parts[..fixed] && compare() != Ordering::Less
+            }
+            Op::Caret => {
+                // Everything after the first non-zero component can change.
+                let fixed = match self.parts.iter().position(|part| *part != 0) {
+                    Some(position) => position + 1,
+                    None => self.parts.len(),
+                };
+                given[..fixed] == self.parts[..fixed] && compare() != Ordering::Less
+            }
+        }
+    }
+}
+
+fn parse_parts(version: &str) -> Option<Vec<u64>> {
+    version
+        .split('.')
+        .map(|part| part.parse().ok())
+        .collect::<Option<Vec<u64>>>()
+        .filter(|parts| !parts.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Query, Version};
+
+    fn matches(query: &str, version: &str) -> bool {
+        Query::parse(query)
+            .unwrap()
+            .matches(Version::parse(version).unwrap())
+    }
+
+    #[test]
+    fn caret() {
+        assert!(matches("^1.2", "1.4.0"));
+        assert!(matches("1", "1.9.9"));
+        assert!(!matches("^1.2", "1.1.0"));
+        assert!(!matches("^1.2", "2.0.0"));
+        assert!(matches("^0.2.3", "0.2.5"));
+        assert!(!matches("^0.2.3", "0.3.0"));
+    }
+
+    #[test]
+    fn tilde_and_ranges() {
+        assert!(matches("~1.2.3", "1.2.9"));
+        assert!(!matches("~1.2.3", "1.3.0"));
+        assert!(matches(">=1.2", "3.0.0"));
+        assert!(!matches("<1", "1.0.1"));
+        assert!(matches("=1.0.0", "1.0.0"));
+        assert!(matches("*", "7.0.0"));
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Version::parse("1.0").is_none());
+        assert!(Query::parse("^x").is_none());
+    }
+}
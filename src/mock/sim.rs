@@ -0,0 +1,367 @@
+/*! Deterministic scheduling of simulated processes.
+
+Processes of a simulation still run on their own threads, but only the one holding the run token
+makes progress. Whenever it blocks, sends a message or exits, the token is passed on by the
+scheduler. The scheduler uses a seeded random number generator to pick the next step: running one
+of the runnable processes or delivering one of the messages and signals that are in flight. If all
+processes are blocked, the virtual time jumps forward to the next deadline.
+*/
+
+use std::{
+    collections::BTreeMap,
+    panic,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::{next_id, Env, Killed, Msg, Proc};
+use crate::simulation::SimulationError;
+
+/// A running simulation, shared by all its processes.
+pub(crate) struct Sim {
+    // Process ids are unique across simulations and deterministic inside of one.
+    index: u64,
+    // Virtual time is counted from here.
+    start: Instant,
+    state: Mutex<SimState>,
+    // Notified when the run token is passed on or the simulation finishes.
+    wake: Condvar,
+}
+
+struct SimState {
+    rng: Rng,
+    steps: u64,
+    max_steps: u64,
+    now: Duration,
+    next_process: u64,
+    root: u128,
+    running: Option<u128>,
+    // Ordered by id, so that picking a random process doesn't depend on hashing.
+    processes: BTreeMap<u128, Status>,
+    in_flight: Vec<InFlight>,
+    timers: Vec<Timer>,
+    result: Option<Result<(), SimulationError>>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Runnable,
+    Blocked {
+        deadline: Option<Duration>,
+        // Sleeping processes don't wake up on messages.
+        on_message: bool,
+    },
+}
+
+/// Something that is delivered to a process at a time picked by the scheduler.
+pub(crate) enum Event {
+    Message(Msg),
+    Kill(u32),
+}
+
+struct InFlight {
+    from: u128,
+    to: Arc<Proc>,
+    event: Event,
+}
+
+struct Timer {
+    id: u64,
+    deadline: Duration,
+    to: Arc<Proc>,
+    message: Msg,
+}
+
+impl Sim {
+    fn new(seed: u64, max_steps: u64) -> Arc<Self> {
+        Arc::new(Sim {
+            index: next_id(),
+            start: Instant::now(),
+            state: Mutex::new(SimState {
+                rng: Rng(seed),
+                steps: 0,
+                max_steps,
+                now: Duration::ZERO,
+                next_process: 0,
+                root: 0,
+                running: None,
+                processes: BTreeMap::new(),
+                in_flight: Vec::new(),
+                timers: Vec::new(),
+                result: None,
+            }),
+            wake: Condvar::new(),
+        })
+    }
+
+    fn state(&self) -> MutexGuard<'_, SimState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // Registers a new runnable process and returns its id.
+    pub(crate) fn add_process(&self) -> u128 {
+        let mut state = self.state();
+        state.next_process += 1;
+        let id = (self.index as u128) << 64 | state.next_process as u128;
+        state.processes.insert(id, Status::Runnable);
+        id
+    }
+
+    pub(crate) fn now(&self) -> Duration {
+        self.state().now
+    }
+
+    pub(crate) fn instant(&self) -> Instant {
+        self.start + self.now()
+    }
+
+    pub(crate) fn post(&self, from: u128, to: Arc<Proc>, event: Event) {
+        self.state().in_flight.push(InFlight { from, to, event });
+    }
+
+    pub(crate) fn add_timer(&self, id: u64, delay: Duration, to: Arc<Proc>, message: Msg) {
+        let mut state = self.state();
+        let deadline = state.now + delay;
+        state.timers.push(Timer {
+            id,
+            deadline,
+            to,
+            message,
+        });
+    }
+
+    pub(crate) fn cancel_timer(&self, id: u64) -> bool {
+        let mut state = self.state();
+        let before = state.timers.len();
+        state.timers.retain(|timer| timer.id != id);
+        state.timers.len() != before
+    }
+
+    // Waits until the process `id` gets the run token for the first time.
+    pub(crate) fn acquire(&self, id: u128) {
+        let state = self.state();
+        self.wait_turn(state, id);
+    }
+
+    // Gives up the run token until the process is woken up by a message or the deadline. Returns
+    // false if the simulation finished in the meantime.
+    pub(crate) fn block(&self, id: u128, deadline: Option<Duration>, on_message: bool) -> bool {
+        self.pass(
+            id,
+            Status::Blocked {
+                deadline,
+                on_message,
+            },
+        )
+    }
+
+    // Gives up the run token, but stays runnable.
+    pub(crate) fn yield_now(&self, id: u128) -> bool {
+        self.pass(id, Status::Runnable)
+    }
+
+    fn pass(&self, id: u128, status: Status) -> bool {
+        let mut state = self.state();
+        if state.result.is_some() {
+            return self.wait_turn(state, id);
+        }
+        state.processes.insert(id, status);
+        self.schedule(&mut state);
+        self.wait_turn(state, id)
+    }
+
+    fn wait_turn(&self, mut state: MutexGuard<'_, SimState>, id: u128) -> bool {
+        loop {
+            if state.result.is_some() {
+                // Processes that are left over when the simulation finishes are killed. If the
+                // thread is already unwinding, it's allowed to finish the cleanup.
+                if thread::panicking() {
+                    return false;
+                }
+                drop(state);
+                panic::resume_unwind(Box::new(Killed));
+            }
+            if state.running == Some(id) {
+                return true;
+            }
+            state = self
+                .wake
+                .wait(state)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+    }
+
+    // Removes an exited process and passes the run token on. The simulation finishes with the
+    // root process.
+    pub(crate) fn exit(&self, id: u128, result: Result<(), SimulationError>) {
+        let mut state = self.state();
+        state.processes.remove(&id);
+        if state.result.is_some() {
+            return;
+        }
+        if id == state.root {
+            self.finish(&mut state, result);
+        } else if state.running == Some(id) {
+            self.schedule(&mut state);
+        }
+    }
+
+    fn finish(&self, state: &mut SimState, result: Result<(), SimulationError>) {
+        state.result = Some(result);
+        state.running = None;
+        self.wake.notify_all();
+    }
+
+    // Picks the next step until a process can run.
+    fn schedule(&self, state: &mut SimState) {
+        loop {
+            state.steps += 1;
+            if state.steps > state.max_steps {
+                return self.finish(state, Err(SimulationError::StepLimit));
+            }
+
+            let runnable: Vec<u128> = state
+                .processes
+                .iter()
+                .filter(|(_, status)| **status == Status::Runnable)
+                .map(|(id, _)| *id)
+                .collect();
+            // Events between two processes are delivered in the order they were sent, so only the
+            // oldest event of each pair can be picked.
+            let mut deliverable: Vec<(u128, u128, usize)> = Vec::new();
+            for (index, in_flight) in state.in_flight.iter().enumerate() {
+                let pair = (in_flight.to.id, in_flight.from);
+                if !deliverable.iter().any(|(to, from, _)| (*to, *from) == pair) {
+                    deliverable.push((pair.0, pair.1, index));
+                }
+            }
+            deliverable.sort_unstable();
+
+            let choices = runnable.len() + deliverable.len();
+            if choices == 0 {
+                if !self.advance_time(state) {
+                    return self.finish(state, Err(SimulationError::Deadlock));
+                }
+                continue;
+            }
+            let choice = state.rng.below(choices);
+            if choice < runnable.len() {
+                state.running = Some(runnable[choice]);
+                self.wake.notify_all();
+                return;
+            }
+            let (_, _, index) = deliverable[choice - runnable.len()];
+            let InFlight { to, event, .. } = state.in_flight.remove(index);
+            let wakes = match event {
+                Event::Message(message) => {
+                    to.deliver(message);
+                    matches!(
+                        state.processes.get(&to.id),
+                        Some(Status::Blocked {
+                            on_message: true,
+                            ..
+                        })
+                    )
+                }
+                Event::Kill(reason) => {
+                    to.kill(reason);
+                    true
+                }
+            };
+            if wakes {
+                if let Some(status) = state.processes.get_mut(&to.id) {
+                    *status = Status::Runnable;
+                }
+            }
+        }
+    }
+
+    // Moves the virtual time to the next deadline, firing timers and waking up processes. Returns
+    // false if nothing is waiting for a deadline.
+    fn advance_time(&self, state: &mut SimState) -> bool {
+        let blocked = state.processes.values().filter_map(|status| match status {
+            Status::Blocked { deadline, .. } => *deadline,
+            Status::Runnable => None,
+        });
+        let timers = state.timers.iter().map(|timer| timer.deadline);
+        let next = match blocked.chain(timers).min() {
+            Some(next) => next,
+            None => return false,
+        };
+        state.now = state.now.max(next);
+
+        let now = state.now;
+        let (mut fired, pending): (Vec<Timer>, Vec<Timer>) = std::mem::take(&mut state.timers)
+            .into_iter()
+            .partition(|timer| timer.deadline <= now);
+        state.timers = pending;
+        fired.sort_by_key(|timer| timer.deadline);
+        for timer in fired {
+            state.in_flight.push(InFlight {
+                from: 0,
+                to: timer.to,
+                event: Event::Message(timer.message),
+            });
+        }
+        for status in state.processes.values_mut() {
+            if let Status::Blocked {
+                deadline: Some(deadline),
+                ..
+            } = status
+            {
+                if *deadline <= now {
+                    *status = Status::Runnable;
+                }
+            }
+        }
+        true
+    }
+}
+
+// A small deterministic random number generator (SplitMix64).
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+/// Runs `entry` as the root process of a new simulation and waits for it to finish.
+pub(crate) fn simulate(
+    seed: u64,
+    max_steps: u64,
+    entry: impl FnOnce() + Send + 'static,
+) -> Result<(), SimulationError> {
+    let sim = Sim::new(seed, max_steps);
+    // Every simulation gets its own environment, so registered names don't leak between them.
+    let root = Proc::new(Arc::new(Env::default()), Some(sim.clone()));
+    {
+        let mut state = sim.state();
+        state.root = root.id;
+        sim.schedule(&mut state);
+    }
+    super::start(root, entry);
+
+    let mut state = sim.state();
+    loop {
+        if let Some(result) = &state.result {
+            return result.clone();
+        }
+        state = sim
+            .wake
+            .wait(state)
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+    }
+}
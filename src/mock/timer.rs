@@ -0,0 +1,45 @@
+/*! Mock of the `lunatic::timer` namespace */
+
+use std::{
+    collections::HashSet,
+    sync::{Mutex, MutexGuard, OnceLock},
+    thread,
+    time::Duration,
+};
+
+use super::{current, message::take_scratch, next_id, process_resource};
+
+// Pending timers. A timer is removed when it fires or is cancelled, whatever happens first.
+fn timers() -> MutexGuard<'static, HashSet<u64>> {
+    static TIMERS: OnceLock<Mutex<HashSet<u64>>> = OnceLock::new();
+    TIMERS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+pub unsafe fn send_after(process_id: u64, delay: u64) -> u64 {
+    let receiver = process_resource(process_id);
+    let message = take_scratch();
+    let timer_id = next_id();
+    if let Some(sim) = receiver.sim.clone() {
+        sim.add_timer(timer_id, Duration::from_millis(delay), receiver, message);
+        return timer_id;
+    }
+    timers().insert(timer_id);
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(delay));
+        if timers().remove(&timer_id) {
+            receiver.deliver(message);
+        }
+    });
+    timer_id
+}
+
+pub unsafe fn cancel_timer(timer_id: u64) -> u32 {
+    let cancelled = match &current().sim {
+        Some(sim) => sim.cancel_timer(timer_id),
+        None => timers().remove(&timer_id),
+    };
+    cancelled as u32
+}
@@ -0,0 +1,231 @@
+/*! TLS streams of the mock, implemented with rustls */
+
+use std::{
+    convert::TryFrom,
+    io::{self, ErrorKind, IoSlice, Read, Write},
+    net::TcpStream,
+    ops::DerefMut,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use rustls::{
+    Certificate, ClientConfig, ClientConnection, ConnectionCommon, OwnedTrustAnchor, PrivateKey,
+    RootCertStore, ServerConfig, ServerConnection, ServerName, SideData, StreamOwned,
+};
+
+use super::{
+    add_resource,
+    networking::{opaque_result, timeout, unit_result},
+    read_bytes, read_str, remove_resource, with_resource, Resource,
+};
+
+// The client and server sides have different connection types.
+trait Tls: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Tls for T {}
+
+/// A TLS connection together with a handle to its socket, used to set timeouts.
+pub(crate) struct TlsStream {
+    socket: TcpStream,
+    tls: Box<dyn Tls>,
+}
+
+fn tls_stream(id: u64) -> Arc<Mutex<TlsStream>> {
+    with_resource(id, |resource| match resource {
+        Resource::TlsStream(stream) => stream.clone(),
+        _ => panic!("Resource {} is not a TLS stream", id),
+    })
+}
+
+fn invalid_input<E>(error: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(ErrorKind::InvalidInput, error)
+}
+
+// Takes the socket out of the TCP stream resource. If the guest cloned the stream, the socket
+// is shared with the clones.
+fn take_socket(tcp_stream_id: u64) -> io::Result<TcpStream> {
+    match remove_resource(tcp_stream_id) {
+        Some(Resource::TcpStream(stream)) => {
+            Arc::try_unwrap(stream).or_else(|stream| stream.try_clone())
+        }
+        _ => panic!("Resource {} is not a TCP stream", tcp_stream_id),
+    }
+}
+
+// Drives the handshake to completion. A timeout of 0 waits forever.
+fn handshake<S, C>(mut connection: C, mut socket: TcpStream, timeout_ms: u32) -> io::Result<u64>
+where
+    S: SideData + 'static,
+    C: DerefMut<Target = ConnectionCommon<S>> + Send + 'static,
+{
+    let deadline = timeout(timeout_ms).map(|timeout| Instant::now() + timeout);
+    while connection.is_handshaking() {
+        let remaining = match deadline {
+            Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                Some(remaining) if !remaining.is_zero() => Some(remaining),
+                _ => {
+                    return Err(io::Error::new(
+                        ErrorKind::TimedOut,
+                        "TLS handshake timed out",
+                    ))
+                }
+            },
+            None => None,
+        };
+        socket.set_read_timeout(remaining)?;
+        socket.set_write_timeout(remaining)?;
+        connection.complete_io(&mut socket)?;
+    }
+    let stream = TlsStream {
+        socket: socket.try_clone()?,
+        tls: Box::new(StreamOwned::new(connection, socket)),
+    };
+    Ok(add_resource(Resource::TlsStream(Arc::new(Mutex::new(
+        stream,
+    )))))
+}
+
+fn client_config(roots_pem: &[u8]) -> io::Result<ClientConfig> {
+    let mut roots = RootCertStore::empty();
+    if roots_pem.is_empty() {
+        roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+            OwnedTrustAnchor::from_subject_spki_name_constraints(
+                anchor.subject,
+                anchor.spki,
+                anchor.name_constraints,
+            )
+        }));
+    } else {
+        for certificate in rustls_pemfile::certs(&mut &roots_pem[..])? {
+            roots
+                .add(&Certificate(certificate))
+                .map_err(invalid_input)?;
+        }
+    }
+    Ok(ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth())
+}
+
+fn server_config(cert_chain_pem: &[u8], key_pem: &[u8]) -> io::Result<ServerConfig> {
+    let cert_chain: Vec<Certificate> = rustls_pemfile::certs(&mut &cert_chain_pem[..])?
+        .into_iter()
+        .map(Certificate)
+        .collect();
+    let key = rustls_pemfile::read_all(&mut &key_pem[..])?
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::ECKey(key) => Some(PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or_else(|| invalid_input("no private key found"))?;
+    ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(cert_chain, key)
+        .map_err(invalid_input)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn tls_client_handshake(
+    tcp_stream_id: u64,
+    server_name: *const u8,
+    server_name_len: usize,
+    roots: *const u8,
+    roots_len: usize,
+    timeout_ms: u32,
+    id: *mut u64,
+) -> u32 {
+    let server_name = read_str(server_name, server_name_len);
+    let roots = read_bytes(roots, roots_len);
+    let result = take_socket(tcp_stream_id).and_then(|socket| {
+        let config = client_config(roots)?;
+        let server_name = ServerName::try_from(server_name).map_err(invalid_input)?;
+        let connection =
+            ClientConnection::new(Arc::new(config), server_name).map_err(invalid_input)?;
+        handshake(connection, socket, timeout_ms)
+    });
+    opaque_result(result, id)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn tls_server_handshake(
+    tcp_stream_id: u64,
+    cert_chain: *const u8,
+    cert_chain_len: usize,
+    key: *const u8,
+    key_len: usize,
+    timeout_ms: u32,
+    id: *mut u64,
+) -> u32 {
+    let cert_chain = read_bytes(cert_chain, cert_chain_len);
+    let key = read_bytes(key, key_len);
+    let result = take_socket(tcp_stream_id).and_then(|socket| {
+        let config = server_config(cert_chain, key)?;
+        let connection = ServerConnection::new(Arc::new(config)).map_err(invalid_input)?;
+        handshake(connection, socket, timeout_ms)
+    });
+    opaque_result(result, id)
+}
+
+pub unsafe fn drop_tls_stream(tls_stream_id: u64) {
+    remove_resource(tls_stream_id);
+}
+
+pub unsafe fn tls_write_vectored(
+    tls_stream_id: u64,
+    ciovec_array: *const u32,
+    ciovec_array_len: usize,
+    timeout_ms: u32,
+    opaque: *mut u64,
+) -> u32 {
+    // The guest passes a slice of `IoSlice`s, that are native `iovec`s here.
+    let bufs = std::slice::from_raw_parts(ciovec_array as *const IoSlice, ciovec_array_len);
+    let stream = tls_stream(tls_stream_id);
+    let mut stream = stream
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let result = stream
+        .socket
+        .set_write_timeout(timeout(timeout_ms))
+        .and_then(|_| stream.tls.write_vectored(bufs));
+    opaque_result(result.map(|written| written as u64), opaque)
+}
+
+pub unsafe fn tls_read(
+    tls_stream_id: u64,
+    buffer: *mut u8,
+    buffer_len: usize,
+    timeout_ms: u32,
+    opaque: *mut u64,
+) -> u32 {
+    let buffer = std::slice::from_raw_parts_mut(buffer, buffer_len);
+    let stream = tls_stream(tls_stream_id);
+    let mut stream = stream
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let result = stream
+        .socket
+        .set_read_timeout(timeout(timeout_ms))
+        .and_then(|_| stream.tls.read(buffer));
+    opaque_result(result.map(|read| read as u64), opaque)
+}
+
+pub unsafe fn tls_flush(tls_stream_id: u64, timeout_ms: u32, error_id: *mut u64) -> u32 {
+    let stream = tls_stream(tls_stream_id);
+    let mut stream = stream
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let result = stream
+        .socket
+        .set_write_timeout(timeout(timeout_ms))
+        .and_then(|_| stream.tls.flush());
+    unit_result(result, error_id)
+}
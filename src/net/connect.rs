@@ -0,0 +1,267 @@
+use std::{
+    fmt,
+    io::{Error, ErrorKind, Result},
+    net::SocketAddr,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{received_error, sendable_error, TcpStream};
+use crate::{
+    host_api,
+    process::{self, Process},
+    request::Request,
+    Mailbox, ReceiveError, Tag,
+};
+
+// Time between the starts of two connection attempts, as recommended by RFC 8305.
+const ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// The error of every address that was tried by [`TcpStream::connect`].
+///
+/// It's carried inside of the returned [`io::Error`](std::io::Error) and can be inspected with
+/// [`get_ref`](std::io::Error::get_ref) and [`downcast_ref`](std::error::Error::downcast_ref).
+///
+/// # Example
+///
+/// ```no_run
+/// use lunatic::net::{ConnectError, TcpStream};
+///
+/// if let Err(error) = TcpStream::connect("example.com:80") {
+///     if let Some(error) = error.get_ref().and_then(|error| error.downcast_ref::<ConnectError>()) {
+///         for (addr, error) in error.attempts() {
+///             println!("{}: {}", addr, error);
+///         }
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct ConnectError {
+    attempts: Vec<(SocketAddr, Error)>,
+}
+
+impl ConnectError {
+    /// Returns every attempted address together with the error of the attempt, in the order the
+    /// attempts were started.
+    pub fn attempts(&self) -> &[(SocketAddr, Error)] {
+        &self.attempts
+    }
+}
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("could not connect to any address")?;
+        for (index, (addr, error)) in self.attempts.iter().enumerate() {
+            let separator = if index == 0 { ": " } else { ", " };
+            write!(f, "{}{} ({})", separator, addr, error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConnectError {}
+
+// The result of an attempt, sent from the attempt process together with its index.
+type Outcome = (usize, std::result::Result<TcpStream, (u32, String)>);
+
+// The result of a race, sent from the coordinator to the process that connects.
+#[derive(Serialize, Deserialize)]
+enum Raced {
+    Connected(TcpStream),
+    Failed(Vec<(SocketAddr, (u32, String))>),
+    Error((u32, String)),
+}
+
+// Races connections to `addrs` as described in RFC 8305.
+pub(crate) fn race(addrs: Vec<SocketAddr>, timeout: Option<Duration>) -> Result<TcpStream> {
+    let addrs = interleave(addrs);
+    match addrs[..] {
+        [] => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "could not resolve to any addresses",
+            ))
+        }
+        // A single address doesn't need to be raced.
+        [addr] => {
+            return TcpStream::connect_addr(addr, timeout)
+                .map_err(|error| connect_error(vec![(addr, error)]))
+        }
+        _ => {}
+    }
+
+    // The race runs in its own process. Attempts that finish after the winner, or after the
+    // deadline, send their outcome into its mailbox, which goes away together with it.
+    let coordinator = process::spawn_with((addrs, timeout), coordinator)?;
+    match coordinator.request(()) {
+        Ok(Raced::Connected(stream)) => Ok(stream),
+        Ok(Raced::Failed(attempts)) => Err(connect_error(
+            attempts
+                .into_iter()
+                .map(|(addr, error)| (addr, received_error(error)))
+                .collect(),
+        )),
+        Ok(Raced::Error(error)) => Err(received_error(error)),
+        Err(error) => Err(Error::other(error)),
+    }
+}
+
+// Entry point of the process that runs a race.
+fn coordinator(
+    (addrs, timeout): (Vec<SocketAddr>, Option<Duration>),
+    mailbox: Mailbox<Request<(), Raced>>,
+) {
+    if let Ok(request) = mailbox.receive() {
+        let raced = match run(addrs, timeout) {
+            Ok(stream) => Raced::Connected(stream),
+            Err(error) => match error
+                .get_ref()
+                .and_then(|error| error.downcast_ref::<ConnectError>())
+            {
+                Some(error) => Raced::Failed(
+                    error
+                        .attempts
+                        .iter()
+                        .map(|(addr, error)| (*addr, sendable_error(error)))
+                        .collect(),
+                ),
+                None => Raced::Error(sendable_error(&error)),
+            },
+        };
+        request.reply(raced);
+    }
+}
+
+// Starts the attempts and waits for the first one that succeeds.
+fn run(addrs: Vec<SocketAddr>, timeout: Option<Duration>) -> Result<TcpStream> {
+    let deadline = timeout.map(|timeout| host_api::now() + timeout);
+    // Outcomes arrive with their own tag, so the request to the coordinator is left alone.
+    let mailbox = unsafe { Mailbox::<Outcome>::new() };
+    let this = process::this(&mailbox);
+    let tag = Tag::new();
+    // Running attempts and the errors of finished ones, by index.
+    let mut running: Vec<Option<Process<()>>> = Vec::new();
+    let mut errors: Vec<Option<Error>> = Vec::new();
+    let mut next_start = host_api::now();
+    loop {
+        let now = host_api::now();
+        if running.len() < addrs.len() && now >= next_start {
+            let index = running.len();
+            let remaining = deadline.map(|deadline| deadline.saturating_duration_since(now));
+            let context = (addrs[index], remaining, index, tag, this.clone());
+            match process::spawn_with(context, attempt) {
+                Ok(process) => {
+                    running.push(Some(process));
+                    errors.push(None);
+                    next_start = now + ATTEMPT_DELAY;
+                }
+                Err(error) => {
+                    running.push(None);
+                    errors.push(Some(error.into()));
+                }
+            }
+            continue;
+        }
+        if running.iter().all(Option::is_none) && running.len() == addrs.len() {
+            break;
+        }
+
+        // Wait until the next attempt is due, but not beyond the deadline.
+        let wait_until = match (running.len() < addrs.len(), deadline) {
+            (true, Some(deadline)) => Some(next_start.min(deadline)),
+            (true, None) => Some(next_start),
+            (false, deadline) => deadline,
+        };
+        let received = match wait_until {
+            Some(until) => mailbox.tag_receive_timeout(tag, until.saturating_duration_since(now)),
+            None => mailbox.tag_receive(tag),
+        };
+        match received {
+            Ok((index, Ok(stream))) => {
+                running[index] = None;
+                cancel(running);
+                return Ok(stream);
+            }
+            Ok((index, Err(error))) => {
+                running[index] = None;
+                errors[index] = Some(received_error(error));
+                // A failed attempt starts the next one right away.
+                next_start = host_api::now();
+            }
+            Err(ReceiveError::Timeout) => {
+                if matches!(deadline, Some(deadline) if host_api::now() >= deadline) {
+                    for (process, error) in running.iter().zip(errors.iter_mut()) {
+                        if process.is_some() {
+                            *error = Some(Error::new(ErrorKind::TimedOut, "connection timed out"));
+                        }
+                    }
+                    cancel(running);
+                    break;
+                }
+            }
+            Err(error) => {
+                cancel(running);
+                return Err(Error::other(error));
+            }
+        }
+    }
+
+    let attempts = addrs
+        .into_iter()
+        .zip(errors)
+        .filter_map(|(addr, error)| Some((addr, error?)))
+        .collect();
+    Err(connect_error(attempts))
+}
+
+// Entry point of attempt processes.
+fn attempt(
+    (addr, timeout, index, tag, parent): (
+        SocketAddr,
+        Option<Duration>,
+        usize,
+        Tag,
+        Process<Outcome>,
+    ),
+    _: Mailbox<()>,
+) {
+    let result = TcpStream::connect_addr(addr, timeout).map_err(|error| sendable_error(&error));
+    parent.tag_send(tag, (index, result));
+}
+
+#[cfg(feature = "unstable-host")]
+fn cancel(running: Vec<Option<Process<()>>>) {
+    for process in running.into_iter().flatten() {
+        process.kill();
+    }
+}
+
+// Processes can't be killed without the `unstable-host` feature. The attempts run until they
+// finish and their outcome is dropped together with the mailbox of the race.
+#[cfg(not(feature = "unstable-host"))]
+fn cancel(_running: Vec<Option<Process<()>>>) {}
+
+// Orders the addresses so that IPv6 and IPv4 alternate, starting with the family of the first
+// address. Otherwise the order of the resolver is kept.
+fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let first_is_ipv6 = addrs.first().is_none_or(SocketAddr::is_ipv6);
+    let (first, second): (Vec<_>, Vec<_>) = addrs
+        .into_iter()
+        .partition(|addr| addr.is_ipv6() == first_is_ipv6);
+    let mut second = second.into_iter();
+    let mut addrs = Vec::with_capacity(first.len() + second.len());
+    for addr in first {
+        addrs.push(addr);
+        addrs.extend(second.next());
+    }
+    addrs.extend(second);
+    addrs
+}
+
+fn connect_error(attempts: Vec<(SocketAddr, Error)>) -> Error {
+    let kind = attempts
+        .last()
+        .map_or(ErrorKind::Other, |(_, error)| error.kind());
+    Error::new(kind, ConnectError { attempts })
+}
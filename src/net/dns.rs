@@ -0,0 +1,418 @@
+#[cfg(feature = "unstable-host")]
+use std::{collections::HashMap, hash::Hash, time::Instant};
+use std::{
+    io::{Error, ErrorKind, Result},
+    net::{IpAddr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+#[cfg(feature = "unstable-host")]
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "unstable-host")]
+use super::sendable_error;
+use super::{received_error, resolver, SocketAddrIterator};
+#[cfg(feature = "unstable-host")]
+use crate::{
+    environment::{lookup, start_registered},
+    error::LunaticError,
+    host_api, process, Mailbox, Message, Tag, TransformMailbox,
+};
+use crate::{
+    process::Process,
+    request::{Request, RequestError},
+};
+
+// The resolver is registered under this name in the environment.
+#[cfg(feature = "unstable-host")]
+const RESOLVER_NAME: &str = "lunatic::net::resolver";
+#[cfg(feature = "unstable-host")]
+const RESOLVER_VERSION: &str = "1.0.0";
+#[cfg(feature = "unstable-host")]
+const RESOLVER_QUERY: &str = "^1";
+
+/// A caching DNS resolver.
+///
+/// The resolver runs as a process that is registered in the environment. Once it's started,
+/// [`resolve_all`], [`reverse_lookup`] and the [`ToSocketAddrs`](super::ToSocketAddrs)
+/// implementations for strings of every process in the environment go through it, instead of
+/// asking the host each time:
+///
+/// * Results are cached for the configured TTL. Failed lookups are cached too, for the negative
+///   TTL, so that broken names don't hit the host over and over again.
+/// * Concurrent lookups of the same name are deduplicated. Only the first one asks the host, the
+///   rest wait for its result.
+/// * Lookups run in their own processes, so a slow name doesn't hold up others.
+///
+/// The host doesn't report the TTLs of DNS records, so the same TTL is used for every name.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use lunatic::{net::{self, Resolver}, Mailbox};
+///
+/// #[lunatic::main]
+/// fn main(_: Mailbox<()>) {
+///     let mut resolver = Resolver::new();
+///     resolver.set_ttl(Duration::from_secs(300));
+///     resolver.start().unwrap();
+///
+///     let addrs = net::resolve_all("example.com", 443).unwrap();
+///     // The second lookup is answered from the cache.
+///     assert_eq!(net::resolve_all("example.com", 443).unwrap(), addrs);
+/// }
+/// ```
+#[cfg(feature = "unstable-host")]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Resolver {
+    ttl: Duration,
+    negative_ttl: Duration,
+    timeout: Option<Duration>,
+}
+
+#[cfg(feature = "unstable-host")]
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "unstable-host")]
+impl Resolver {
+    /// Creates a new resolver configuration.
+    ///
+    /// Results are cached for 60 seconds and failures for 5 seconds. Lookups don't time out.
+    pub fn new() -> Self {
+        Self {
+            ttl: Duration::from_secs(60),
+            negative_ttl: Duration::from_secs(5),
+            timeout: None,
+        }
+    }
+
+    /// Sets how long successful lookups are cached.
+    pub fn set_ttl(&mut self, ttl: Duration) {
+        self.ttl = ttl;
+    }
+
+    /// Sets how long failed lookups are cached. A TTL of zero disables negative caching.
+    pub fn set_negative_ttl(&mut self, ttl: Duration) {
+        self.negative_ttl = ttl;
+    }
+
+    /// Sets the timeout of lookups on the host.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Starts the resolver process and registers it in the current environment.
+    ///
+    /// Does nothing if a resolver is already running in the environment. Processes that start
+    /// resolvers at the same time can end up with separate ones, so it's best to start it once,
+    /// e.g. at the beginning of `main`.
+    pub fn start(self) -> std::result::Result<(), LunaticError> {
+        start_registered(RESOLVER_NAME, RESOLVER_VERSION, RESOLVER_QUERY, || {
+            process::spawn_with(self, resolver_entry)
+        })?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "unstable-host")]
+fn resolver() -> Option<Process<ResolverMessage>> {
+    lookup(RESOLVER_NAME, RESOLVER_QUERY).expect("Valid semver query")
+}
+
+// The resolver process needs the `unstable-host` feature, without it names are always looked up
+// by the host.
+#[cfg(not(feature = "unstable-host"))]
+fn resolver() -> Option<Process<ResolverMessage>> {
+    None
+}
+
+/// Resolves `host` to all of its addresses.
+///
+/// The host can be an IP address, a name or either of them followed by a port, e.g.
+/// `example.com:8080` or `[::1]:8080`. If it doesn't contain a port, `default_port` is used.
+///
+/// Names are looked up by the [`Resolver`] if one is running in the environment, otherwise by
+/// the host directly.
+pub fn resolve_all(host: &str, default_port: u16) -> Result<Vec<SocketAddr>> {
+    if let Ok(addr) = host.parse::<SocketAddr>() {
+        return Ok(vec![addr]);
+    }
+    if let Some(ip) = parse_ip(host) {
+        return Ok(vec![SocketAddr::new(ip, default_port)]);
+    }
+    let (name, port) = match host.rsplit_once(':') {
+        Some((name, port)) => match port.parse() {
+            Ok(port) => (name, port),
+            Err(_) => return Err(Error::new(ErrorKind::InvalidInput, "invalid port value")),
+        },
+        None => (host, default_port),
+    };
+    lookup_name(name, port)
+}
+
+/// Returns the name of `ip`.
+///
+/// The name is looked up by the [`Resolver`] if one is running in the environment, otherwise by
+/// the host directly.
+#[cfg(feature = "unstable-host")]
+pub fn reverse_lookup(ip: IpAddr) -> Result<String> {
+    match resolver() {
+        Some(resolver) => match resolver.request_wrapped(ip, ResolverMessage::Reverse, None) {
+            Ok(result) => result.map_err(received_error),
+            Err(RequestError::ProcessDied) => resolver::resolve_reverse(ip, None),
+            Err(error) => Err(Error::other(error)),
+        },
+        None => resolver::resolve_reverse(ip, None),
+    }
+}
+
+// Resolves a string in the `<host>:<port>` format. Names are only looked up by the resolver
+// process, if one is running.
+pub(crate) fn resolve_str(addr: &str) -> Result<SocketAddrIterator> {
+    if resolver().is_none() {
+        return Ok(resolver::resolve(addr)?);
+    }
+    if let Ok(addr) = addr.parse::<SocketAddr>() {
+        return Ok(SocketAddrIterator::from_addrs(vec![addr]));
+    }
+    let (name, port) = addr
+        .rsplit_once(':')
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "invalid socket address"))?;
+    let port = port
+        .parse()
+        .map_err(|_| Error::new(ErrorKind::InvalidInput, "invalid port value"))?;
+    Ok(SocketAddrIterator::from_addrs(lookup_name(name, port)?))
+}
+
+// Parses IP addresses, including IPv6 addresses in brackets.
+fn parse_ip(host: &str) -> Option<IpAddr> {
+    match host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+    {
+        Some(ip) => ip.parse::<Ipv6Addr>().ok().map(IpAddr::V6),
+        None => host.parse().ok(),
+    }
+}
+
+fn lookup_name(name: &str, port: u16) -> Result<Vec<SocketAddr>> {
+    let addrs = lookup_host(name)?.map(|mut addr| {
+        addr.set_port(port);
+        addr
+    });
+    Ok(addrs.collect())
+}
+
+// Looks up `name` by the resolver process if one is running, otherwise on the host. The port of
+// the returned addresses is 0.
+pub(crate) fn lookup_host(name: &str) -> Result<SocketAddrIterator> {
+    if let Some(resolver) = resolver() {
+        match resolver.request_wrapped(name.to_string(), ResolverMessage::Lookup, None) {
+            Ok(result) => {
+                let addrs = result.map_err(received_error)?;
+                return Ok(SocketAddrIterator::from_addrs(addrs));
+            }
+            Err(RequestError::ProcessDied) => {}
+            Err(error) => return Err(Error::other(error)),
+        }
+    }
+    resolve_name(name, None)
+}
+
+// Looks up `name` on the host. The port of the returned addresses is 0.
+fn resolve_name(name: &str, timeout: Option<Duration>) -> Result<SocketAddrIterator> {
+    let name = format!("{}:0", name);
+    match timeout {
+        Some(timeout) => Ok(resolver::resolve_timeout(&name, timeout)?),
+        None => Ok(resolver::resolve(&name)?),
+    }
+}
+
+type Outcome<T> = std::result::Result<T, (u32, String)>;
+
+// Messages understood by the resolver.
+#[derive(Serialize, Deserialize)]
+enum ResolverMessage {
+    Lookup(Request<String, Outcome<Vec<SocketAddr>>>),
+    Reverse(Request<IpAddr, Outcome<String>>),
+    // Sent by lookup processes when they are done.
+    Resolved(String, Outcome<Vec<SocketAddr>>),
+    Reversed(IpAddr, Outcome<String>),
+}
+
+// The key of a running lookup process.
+#[cfg(feature = "unstable-host")]
+enum Lookup {
+    Name(String),
+    Ip(IpAddr),
+}
+
+// Entry point of the resolver.
+#[cfg(feature = "unstable-host")]
+fn resolver_entry(config: Resolver, mailbox: Mailbox<ResolverMessage>) {
+    // Lookup processes are monitored, so that requests don't wait forever if one of them dies
+    // before sending its result.
+    let mailbox = mailbox.catch_link_panic();
+    let this = process::this(&mailbox);
+    let mut names = Cache::new();
+    let mut ips = Cache::new();
+    let mut lookups: HashMap<Tag, Lookup> = HashMap::new();
+    loop {
+        match mailbox.receive() {
+            Message::Normal(Ok(ResolverMessage::Lookup(request))) => {
+                let name = request.data().clone();
+                if names.get(request) {
+                    let context = (name.clone(), config.timeout, this.clone());
+                    match process::spawn_with(context, lookup_entry) {
+                        Ok(lookup) => {
+                            let monitor = lookup.monitor(&mailbox);
+                            lookups.insert(monitor.tag(), Lookup::Name(name));
+                        }
+                        Err(error) => {
+                            let error = sendable_error(&error.into());
+                            names.complete(name, Err(error), &config);
+                        }
+                    }
+                }
+            }
+            Message::Normal(Ok(ResolverMessage::Reverse(request))) => {
+                let ip = *request.data();
+                if ips.get(request) {
+                    let context = (ip, config.timeout, this.clone());
+                    match process::spawn_with(context, reverse_entry) {
+                        Ok(lookup) => {
+                            let monitor = lookup.monitor(&mailbox);
+                            lookups.insert(monitor.tag(), Lookup::Ip(ip));
+                        }
+                        Err(error) => {
+                            let error = sendable_error(&error.into());
+                            ips.complete(ip, Err(error), &config);
+                        }
+                    }
+                }
+            }
+            Message::Normal(Ok(ResolverMessage::Resolved(name, result))) => {
+                names.complete(name, result, &config)
+            }
+            Message::Normal(Ok(ResolverMessage::Reversed(ip, result))) => {
+                ips.complete(ip, result, &config)
+            }
+            // A lookup process stopped. If it didn't send a result, the waiting requests fail.
+            Message::Down { monitor, .. } => {
+                let error = Error::other("lookup process died");
+                match lookups.remove(&monitor.tag()) {
+                    Some(Lookup::Name(name)) => names.abort(&name, sendable_error(&error)),
+                    Some(Lookup::Ip(ip)) => ips.abort(&ip, sendable_error(&error)),
+                    None => {}
+                }
+            }
+            // Messages that can't be deserialized are dropped.
+            _ => continue,
+        }
+    }
+}
+
+// Entry point of processes looking up names.
+#[cfg(feature = "unstable-host")]
+fn lookup_entry(
+    (name, timeout, resolver): (String, Option<Duration>, Process<ResolverMessage>),
+    _: Mailbox<()>,
+) {
+    let result = resolve_name(&name, timeout)
+        .map(Iterator::collect)
+        .map_err(|error| sendable_error(&error));
+    resolver.send(ResolverMessage::Resolved(name, result));
+}
+
+// Entry point of processes looking up IP addresses.
+#[cfg(feature = "unstable-host")]
+fn reverse_entry(
+    (ip, timeout, resolver): (IpAddr, Option<Duration>, Process<ResolverMessage>),
+    _: Mailbox<()>,
+) {
+    let result = resolver::resolve_reverse(ip, timeout).map_err(|error| sendable_error(&error));
+    resolver.send(ResolverMessage::Reversed(ip, result));
+}
+
+#[cfg(feature = "unstable-host")]
+enum Entry<K: Serialize, V: Serialize + DeserializeOwned> {
+    // The lookup is running, the requests wait for its result.
+    Pending(Vec<Request<K, Outcome<V>>>),
+    Done(Outcome<V>, Instant),
+}
+
+#[cfg(feature = "unstable-host")]
+struct Cache<K: Serialize, V: Serialize + DeserializeOwned> {
+    entries: HashMap<K, Entry<K, V>>,
+}
+
+#[cfg(feature = "unstable-host")]
+impl<K, V> Cache<K, V>
+where
+    K: Serialize + DeserializeOwned + Clone + Eq + Hash,
+    V: Serialize + DeserializeOwned + Clone,
+{
+    fn new() -> Self {
+        Self {
+            entries: HashMap::new(),
+        }
+    }
+
+    // Answers the request from the cache if possible. Returns `true` if a new lookup needs to be
+    // started, the request is answered once it completes.
+    fn get(&mut self, request: Request<K, Outcome<V>>) -> bool {
+        let now = host_api::now();
+        match self.entries.get_mut(request.data()) {
+            Some(Entry::Done(result, expires)) if now < *expires => {
+                let result = result.clone();
+                request.reply(result);
+                false
+            }
+            Some(Entry::Pending(requests)) => {
+                requests.push(request);
+                false
+            }
+            _ => {
+                let key = request.data().clone();
+                self.entries.insert(key, Entry::Pending(vec![request]));
+                true
+            }
+        }
+    }
+
+    // Answers all requests waiting for `key` and caches the result.
+    fn complete(&mut self, key: K, result: Outcome<V>, config: &Resolver) {
+        let now = host_api::now();
+        let ttl = match result {
+            Ok(_) => config.ttl,
+            Err(_) => config.negative_ttl,
+        };
+        let entry = Entry::Done(result.clone(), now + ttl);
+        if let Some(Entry::Pending(requests)) = self.entries.insert(key, entry) {
+            for request in requests {
+                request.reply(result.clone());
+            }
+        }
+        // Drop expired results, so that the cache doesn't grow forever.
+        self.entries
+            .retain(|_, entry| !matches!(entry, Entry::Done(_, expires) if now >= *expires));
+    }
+
+    // Answers all requests waiting for `key` with `error`, if its lookup didn't complete. The
+    // error isn't cached.
+    fn abort(&mut self, key: &K, error: (u32, String)) {
+        if let Some(Entry::Pending(_)) = self.entries.get(key) {
+            if let Some(Entry::Pending(requests)) = self.entries.remove(key) {
+                for request in requests {
+                    request.reply(Err(error.clone()));
+                }
+            }
+        }
+    }
+}
@@ -1,18 +1,34 @@
 /*! Networking related functions */
 
+mod connect;
+mod dns;
 mod resolver;
 mod tcp_listener;
 mod tcp_stream;
+#[cfg(feature = "tls")]
+mod tls_stream;
+#[cfg(feature = "unstable-host")]
+mod udp_socket;
 
 use std::io::{Error, ErrorKind, Result};
 use std::iter::Cloned;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
 use std::option::IntoIter;
 use std::slice::Iter;
+use std::time::Duration;
 
-pub use resolver::{resolve, resolve_timeout, SocketAddrIterator};
-pub use tcp_listener::TcpListener;
+use crate::error::{LunaticError, IO_ERROR_KINDS};
+pub use connect::ConnectError;
+pub use dns::resolve_all;
+#[cfg(feature = "unstable-host")]
+pub use dns::{reverse_lookup, Resolver};
+pub use resolver::{resolve, resolve_timeout, HostPortIterator, SocketAddrIterator};
+pub use tcp_listener::{Incoming, TcpListener};
 pub use tcp_stream::TcpStream;
+#[cfg(feature = "tls")]
+pub use tls_stream::{TlsAcceptor, TlsConnector, TlsStream};
+#[cfg(feature = "unstable-host")]
+pub use udp_socket::UdpSocket;
 
 /// A trait for objects which can be converted or resolved to one or more
 /// [`SocketAddr`] values.
@@ -30,7 +46,17 @@ pub use tcp_stream::TcpStream;
 ///
 ///  * [`&str`]: the string should be either a string representation of a
 ///    [`SocketAddr`] as expected by its [`FromStr`] implementation or a string like
-///    `<host_name>:<port>` pair where `<port>` is a [`u16`] value.
+///    `<host_name>:<port>` pair where `<port>` is a [`u16`] value. Host names are looked up by
+///    the [`Resolver`] if one is running in the environment.
+///
+///  * `(`[`&str`]`, `[`u16`]`)`, `(`[`String`]`, `[`u16`]`)`: the string should be either a
+///    string representation of an [`IpAddr`] or a host name, the port is taken from the tuple.
+///    Host names are looked up like above and yield a [`HostPortIterator`].
+///
+///  * `[SocketAddr; N]`, `&[SocketAddr]`, `Vec<SocketAddr>`: all addresses are yielded in
+///    order.
+///
+///  * `&T` where `T` implements `ToSocketAddrs`.
 ///
 /// This trait allows constructing network objects like [`TcpStream`] easily with
 /// values of various types for the bind/connection address. It is needed because
@@ -130,10 +156,7 @@ impl ToSocketAddrs for &str {
     type Iter = SocketAddrIterator;
 
     fn to_socket_addrs(&self) -> Result<Self::Iter> {
-        match resolve(self) {
-            Ok(iter) => Ok(iter),
-            Err(err) => Err(Error::new(ErrorKind::Other, err)),
-        }
+        dns::resolve_str(self)
     }
 }
 
@@ -141,10 +164,28 @@ impl ToSocketAddrs for String {
     type Iter = SocketAddrIterator;
 
     fn to_socket_addrs(&self) -> Result<Self::Iter> {
-        match resolve(self) {
-            Ok(iter) => Ok(iter),
-            Err(err) => Err(Error::new(ErrorKind::Other, err)),
-        }
+        dns::resolve_str(self)
+    }
+}
+
+impl ToSocketAddrs for (&str, u16) {
+    type Iter = HostPortIterator;
+
+    fn to_socket_addrs(&self) -> Result<Self::Iter> {
+        let (host, port) = *self;
+        let addrs = match host.parse::<IpAddr>() {
+            Ok(ip) => SocketAddrIterator::from_addrs(vec![SocketAddr::new(ip, port)]),
+            Err(_) => dns::lookup_host(host)?,
+        };
+        Ok(HostPortIterator::new(addrs, port))
+    }
+}
+
+impl ToSocketAddrs for (String, u16) {
+    type Iter = HostPortIterator;
+
+    fn to_socket_addrs(&self) -> Result<Self::Iter> {
+        (&*self.0, self.1).to_socket_addrs()
     }
 }
 
@@ -198,3 +239,108 @@ impl<'a> ToSocketAddrs for &'a [SocketAddr] {
         <&[SocketAddr] as std::net::ToSocketAddrs>::to_socket_addrs(self)
     }
 }
+
+impl<const N: usize> ToSocketAddrs for [SocketAddr; N] {
+    type Iter = std::array::IntoIter<SocketAddr, N>;
+    fn to_socket_addrs(&self) -> Result<Self::Iter> {
+        Ok(IntoIterator::into_iter(*self))
+    }
+}
+
+impl ToSocketAddrs for Vec<SocketAddr> {
+    type Iter = std::vec::IntoIter<SocketAddr>;
+    fn to_socket_addrs(&self) -> Result<Self::Iter> {
+        Ok(self.clone().into_iter())
+    }
+}
+
+impl<T: ToSocketAddrs + ?Sized> ToSocketAddrs for &T {
+    type Iter = T::Iter;
+    fn to_socket_addrs(&self) -> Result<Self::Iter> {
+        (**self).to_socket_addrs()
+    }
+}
+
+// Splits an address into the type, ip, port, flow info and scope id arguments of host calls.
+fn addr_parts(addr: &SocketAddr) -> (u32, [u8; 16], u32, u32, u32) {
+    let mut ip = [0; 16];
+    match addr {
+        SocketAddr::V4(v4_addr) => {
+            ip[..4].copy_from_slice(&v4_addr.ip().octets());
+            (4, ip, v4_addr.port() as u32, 0, 0)
+        }
+        SocketAddr::V6(v6_addr) => {
+            ip.copy_from_slice(&v6_addr.ip().octets());
+            let port = v6_addr.port() as u32;
+            (6, ip, port, v6_addr.flowinfo(), v6_addr.scope_id())
+        }
+    }
+}
+
+fn timeout_ms(duration: Option<Duration>) -> u32 {
+    match duration {
+        None => 0,
+        // If waiting time is smaller than 1ms, round it up to 1ms.
+        Some(duration) => match duration.as_millis() {
+            0 => 1,
+            other => other as u32,
+        },
+    }
+}
+
+fn duration(timeout_ms: u32) -> Option<Duration> {
+    match timeout_ms {
+        0 => None,
+        ms => Some(Duration::from_millis(ms as u64)),
+    }
+}
+
+fn io_error(error_id: u64) -> Error {
+    Error::from(LunaticError::from(error_id))
+}
+
+// Errors can't be sent between processes, so only their kind and message are sent. The kind is
+// encoded the same way as by the host, 0 is `Other` and the rest index into `IO_ERROR_KINDS`.
+fn sendable_error(error: &Error) -> (u32, String) {
+    let kind = IO_ERROR_KINDS
+        .iter()
+        .position(|kind| *kind == error.kind())
+        .map_or(0, |index| index as u32 + 1);
+    (kind, error.to_string())
+}
+
+fn received_error((kind, message): (u32, String)) -> Error {
+    let kind = match kind.checked_sub(1) {
+        Some(index) => IO_ERROR_KINDS
+            .get(index as usize)
+            .copied()
+            .unwrap_or(ErrorKind::Other),
+        None => ErrorKind::Other,
+    };
+    Error::new(kind, message)
+}
+
+#[cfg(feature = "unstable-host")]
+fn unit_result(result: u32, error_id: u64) -> Result<()> {
+    match result {
+        0 => Ok(()),
+        _ => Err(io_error(error_id)),
+    }
+}
+
+#[cfg(feature = "unstable-host")]
+fn addr_result(result: u32, dns_iter_or_error_id: u64) -> Result<SocketAddr> {
+    if result == 0 {
+        let mut dns_iter = SocketAddrIterator::from(dns_iter_or_error_id);
+        Ok(dns_iter.next().expect("must contain one element"))
+    } else {
+        Err(io_error(dns_iter_or_error_id))
+    }
+}
+
+fn value_result(result: u32, value_or_error_id: u64) -> Result<u64> {
+    match result {
+        0 => Ok(value_or_error_id),
+        _ => Err(io_error(value_or_error_id)),
+    }
+}
@@ -1,26 +1,49 @@
+#[cfg(feature = "unstable-host")]
+use std::{io, net::IpAddr};
 use std::{
     net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
     time::Duration,
+    vec,
 };
 
+#[cfg(feature = "unstable-host")]
+use super::{addr_parts, timeout_ms, value_result};
 use crate::{error::LunaticError, host_api};
 
 /// Iterator over [`SocketAddr`]
 #[derive(Debug)]
 pub struct SocketAddrIterator {
-    id: u64,
+    inner: Inner,
+}
+
+#[derive(Debug)]
+enum Inner {
+    // Addresses of a DNS iterator on the host.
+    Host(u64),
+    // Addresses that are already known, e.g. from the cache of the resolver process.
+    Known(vec::IntoIter<SocketAddr>),
 }
 
 impl SocketAddrIterator {
     pub(crate) fn from(id: u64) -> Self {
-        Self { id }
+        Self {
+            inner: Inner::Host(id),
+        }
+    }
+
+    pub(crate) fn from_addrs(addrs: Vec<SocketAddr>) -> Self {
+        Self {
+            inner: Inner::Known(addrs.into_iter()),
+        }
     }
 }
 
 impl Drop for SocketAddrIterator {
     fn drop(&mut self) {
-        unsafe {
-            host_api::networking::drop_dns_iterator(self.id);
+        if let Inner::Host(id) = self.inner {
+            unsafe {
+                host_api::networking::drop_dns_iterator(id);
+            }
         }
     }
 }
@@ -29,6 +52,10 @@ impl Iterator for SocketAddrIterator {
     type Item = SocketAddr;
 
     fn next(&mut self) -> Option<Self::Item> {
+        let id = match &mut self.inner {
+            Inner::Host(id) => *id,
+            Inner::Known(addrs) => return addrs.next(),
+        };
         let mut addr_type: u32 = 0;
         let mut addr: [u8; 16] = [0; 16];
         let mut port: u16 = 0;
@@ -36,7 +63,7 @@ impl Iterator for SocketAddrIterator {
         let mut scope_id: u32 = 0;
         let next = unsafe {
             host_api::networking::resolve_next(
-                self.id,
+                id,
                 &mut addr_type as *mut u32,
                 addr.as_mut_ptr(),
                 &mut port as *mut u16,
@@ -65,6 +92,31 @@ impl Iterator for SocketAddrIterator {
     }
 }
 
+/// Iterator over the [`SocketAddr`]s of a host and port pair, e.g. `("example.com", 80)`.
+///
+/// Wraps the [`SocketAddrIterator`] of the host and substitutes the port of every address.
+#[derive(Debug)]
+pub struct HostPortIterator {
+    addrs: SocketAddrIterator,
+    port: u16,
+}
+
+impl HostPortIterator {
+    pub(crate) fn new(addrs: SocketAddrIterator, port: u16) -> Self {
+        Self { addrs, port }
+    }
+}
+
+impl Iterator for HostPortIterator {
+    type Item = SocketAddr;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut addr = self.addrs.next()?;
+        addr.set_port(self.port);
+        Some(addr)
+    }
+}
+
 /// Performs a DNS resolution.
 ///
 /// The returned iterator may not actually yield any values depending on the
@@ -102,8 +154,34 @@ fn resolve_timeout_(
     if result != 0 {
         Err(LunaticError::from(dns_iter_or_error_id))
     } else {
-        Ok(SocketAddrIterator {
-            id: dns_iter_or_error_id,
-        })
+        Ok(SocketAddrIterator::from(dns_iter_or_error_id))
+    }
+}
+
+// Looks up the name of `ip` on the host.
+#[cfg(feature = "unstable-host")]
+pub(crate) fn resolve_reverse(ip: IpAddr, timeout: Option<Duration>) -> io::Result<String> {
+    let (addr_type, octets, ..) = addr_parts(&SocketAddr::new(ip, 0));
+    // DNS names are at most 253 characters long.
+    let mut name = vec![0; 253];
+    let mut len_or_error_id = 0;
+    let result = unsafe {
+        host_api::networking::resolve_reverse(
+            addr_type,
+            octets.as_ptr(),
+            timeout_ms(timeout),
+            name.as_mut_ptr(),
+            name.len(),
+            &mut len_or_error_id as *mut u64,
+        )
+    };
+    let len = value_result(result, len_or_error_id)? as usize;
+    if len > name.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "name is too long",
+        ));
     }
+    name.truncate(len);
+    String::from_utf8(name).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
 }
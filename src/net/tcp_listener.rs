@@ -1,7 +1,12 @@
-use std::io::{Error, ErrorKind, Result};
+use std::cell::UnsafeCell;
+use std::io::{Error, Result};
 use std::net::SocketAddr;
 
+#[cfg(feature = "unstable-host")]
+use super::addr_result;
 use super::SocketAddrIterator;
+#[cfg(feature = "unstable-host")]
+use crate::resource::transferable;
 use crate::{error::LunaticError, host_api, net::TcpStream};
 
 /// A TCP server, listening for connections.
@@ -14,6 +19,9 @@ use crate::{error::LunaticError, host_api, net::TcpStream};
 ///
 /// [IETF RFC 793]: https://tools.ietf.org/html/rfc793
 ///
+/// A listener can be sent to other processes inside of a message, e.g. to a pool of processes
+/// that accept connections from it.
+///
 /// # Examples
 ///
 /// ```no_run
@@ -43,15 +51,35 @@ use crate::{error::LunaticError, host_api, net::TcpStream};
 #[derive(Debug)]
 pub struct TcpListener {
     id: u64,
+    // If the TCP listener is serialized it will be removed from our resources, so we can't call
+    // `drop_tcp_listener()` anymore on it.
+    consumed: UnsafeCell<bool>,
 }
 
 impl Drop for TcpListener {
     fn drop(&mut self) {
-        unsafe { host_api::networking::drop_tcp_listener(self.id) };
+        // Only drop listener if it's not already consumed
+        if unsafe { !*self.consumed.get() } {
+            unsafe { host_api::networking::drop_tcp_listener(self.id) };
+        }
     }
 }
 
+#[cfg(feature = "unstable-host")]
+transferable!(
+    TcpListener,
+    host_api::message::push_tcp_listener,
+    host_api::message::take_tcp_listener
+);
+
 impl TcpListener {
+    pub(crate) fn from(id: u64) -> Self {
+        TcpListener {
+            id,
+            consumed: UnsafeCell::new(false),
+        }
+    }
+
     /// Creates a new [`TcpListener`] bound to the given address.
     ///
     /// Binding with a port number of 0 will request that the operating system assigns an available
@@ -99,11 +127,11 @@ impl TcpListener {
                 }
             };
             if result == 0 {
-                return Ok(Self { id });
+                return Ok(Self::from(id));
             }
         }
         let lunatic_error = LunaticError::from(id);
-        Err(Error::new(ErrorKind::Other, lunatic_error))
+        Err(Error::from(lunatic_error))
     }
 
     /// Accepts a new incoming connection.
@@ -126,7 +154,49 @@ impl TcpListener {
             Ok((tcp_stream, peer))
         } else {
             let lunatic_error = LunaticError::from(tcp_stream_or_error_id);
-            Err(Error::new(ErrorKind::Other, lunatic_error))
+            Err(Error::from(lunatic_error))
         }
     }
+
+    /// Returns an iterator over incoming connections.
+    ///
+    /// It's the same as calling [`accept`](TcpListener::accept) in a loop and never returns
+    /// `None`.
+    pub fn incoming(&self) -> Incoming<'_> {
+        Incoming { listener: self }
+    }
+}
+
+// The address of a listener needs a host function that the lunatic v0.6.0 runtime doesn't
+// provide.
+#[cfg(feature = "unstable-host")]
+impl TcpListener {
+    /// Returns the address this listener is bound to.
+    ///
+    /// This can be used to find out the port the operating system picked when binding to port 0.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        let mut dns_iter_or_error_id = 0;
+        let result = unsafe {
+            host_api::networking::tcp_listener_local_addr(
+                self.id,
+                &mut dns_iter_or_error_id as *mut u64,
+            )
+        };
+        addr_result(result, dns_iter_or_error_id)
+    }
+}
+
+/// An iterator over the connections of a [`TcpListener`], created by
+/// [`incoming`](TcpListener::incoming).
+#[derive(Debug)]
+pub struct Incoming<'a> {
+    listener: &'a TcpListener,
+}
+
+impl<'a> Iterator for Incoming<'a> {
+    type Item = Result<TcpStream>;
+
+    fn next(&mut self) -> Option<Result<TcpStream>> {
+        Some(self.listener.accept().map(|(stream, _)| stream))
+    }
 }
@@ -1,16 +1,16 @@
+#[cfg(feature = "unstable-host")]
+use std::net::Shutdown;
 use std::{
     cell::UnsafeCell,
-    io::{Error, ErrorKind, IoSlice, Read, Result, Write},
+    io::{Error, IoSlice, Read, Result, Write},
     net::SocketAddr,
     time::Duration,
 };
 
-use serde::{
-    de::{self, Visitor},
-    Deserialize, Deserializer, Serialize, Serializer,
-};
-
-use crate::{error::LunaticError, host_api};
+use super::{addr_parts, duration, timeout_ms, value_result};
+#[cfg(feature = "unstable-host")]
+use super::{addr_result, unit_result};
+use crate::{error::LunaticError, host_api, resource::transferable};
 
 /// A TCP connection.
 ///
@@ -56,43 +56,12 @@ impl Clone for TcpStream {
     }
 }
 
-impl Serialize for TcpStream {
-    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        // Mark process as consumed
-        unsafe { *self.consumed.get() = true };
-        // TODO: Timeout info is not serialized
-        let index = unsafe { host_api::message::push_tcp_stream(self.id) };
-        serializer.serialize_u64(index)
-    }
-}
-struct TcpStreamVisitor;
-impl<'de> Visitor<'de> for TcpStreamVisitor {
-    type Value = TcpStream;
-
-    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-        formatter.write_str("an u64 index")
-    }
-
-    fn visit_u64<E>(self, index: u64) -> std::result::Result<Self::Value, E>
-    where
-        E: de::Error,
-    {
-        let id = unsafe { host_api::message::take_tcp_stream(index) };
-        Ok(TcpStream::from(id))
-    }
-}
-
-impl<'de> Deserialize<'de> for TcpStream {
-    fn deserialize<D>(deserializer: D) -> std::result::Result<TcpStream, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        deserializer.deserialize_u64(TcpStreamVisitor)
-    }
-}
+// TODO: Timeout info is not transferred
+transferable!(
+    TcpStream,
+    host_api::message::push_tcp_stream,
+    host_api::message::take_tcp_stream
+);
 
 impl TcpStream {
     pub(crate) fn from(id: u64) -> Self {
@@ -104,102 +73,218 @@ impl TcpStream {
         }
     }
 
+    // Gives up ownership of the stream resource without dropping it.
+    pub(crate) fn into_id(self) -> u64 {
+        unsafe { *self.consumed.get() = true };
+        self.id
+    }
+
     /// Sets the read timeout.
     ///
     /// If the value specified is `None`, then read calls will block indefinitely.
     pub fn set_read_timeout(&mut self, duration: Option<Duration>) {
-        match duration {
-            None => self.read_timeout = 0,
-            Some(duration) => self.read_timeout = duration.as_millis() as u32,
-        }
+        self.read_timeout = timeout_ms(duration);
     }
 
     /// Sets the write timeout.
     ///
     /// If the value specified is `None`, then write calls will block indefinitely.
     pub fn set_write_timeout(&mut self, duration: Option<Duration>) {
-        match duration {
-            None => self.write_timeout = 0,
-            Some(duration) => self.write_timeout = duration.as_millis() as u32,
-        }
+        self.write_timeout = timeout_ms(duration);
     }
 
     /// Creates a TCP connection to the specified address.
     ///
     /// This method will create a new TCP socket and attempt to connect it to the provided `addr`,
     ///
-    /// If `addr` yields multiple addresses, connecting will be attempted with each of the
-    /// addresses until connecting to one succeeds. If none of the addresses result in a successful
-    /// connection, the error from the last connect attempt is returned.
+    /// If `addr` yields multiple addresses, they are raced against each other as described in
+    /// [RFC 8305] ("Happy Eyeballs"). IPv6 and IPv4 addresses are tried alternately, starting
+    /// with the family of the first address, and a new attempt is started every 250 ms or as
+    /// soon as the previous one failed. Each attempt runs in its own process. The first
+    /// established connection is returned and the other attempts are cancelled.
+    ///
+    /// If none of the addresses result in a successful connection, the returned error carries
+    /// a [`ConnectError`](super::ConnectError) with the error of every attempted address. Its
+    /// kind is the kind of the last error.
+    ///
+    /// [RFC 8305]: https://tools.ietf.org/html/rfc8305
     pub fn connect<A>(addr: A) -> Result<Self>
     where
         A: super::ToSocketAddrs,
     {
-        TcpStream::connect_timeout_(addr, None)
+        super::connect::race(addr.to_socket_addrs()?.collect(), None)
     }
 
     /// Same as [`TcpStream::connect`], but only waits for the duration of timeout to connect.
+    ///
+    /// The timeout applies to the whole race, not to the individual attempts.
     pub fn connect_timeout<A>(addr: A, timeout: Duration) -> Result<Self>
     where
         A: super::ToSocketAddrs,
     {
-        TcpStream::connect_timeout_(addr, Some(timeout))
+        super::connect::race(addr.to_socket_addrs()?.collect(), Some(timeout))
     }
 
-    fn connect_timeout_<A>(addr: A, timeout: Option<Duration>) -> Result<Self>
-    where
-        A: super::ToSocketAddrs,
-    {
+    // Connects to a single address.
+    pub(crate) fn connect_addr(addr: SocketAddr, timeout: Option<Duration>) -> Result<Self> {
+        let (addr_type, ip, port, flow_info, scope_id) = addr_parts(&addr);
         let mut id = 0;
-        for addr in addr.to_socket_addrs()? {
-            let timeout_ms = match timeout {
-                // If waiting time is smaller than 1ms, round it up to 1ms.
-                Some(timeout) => match timeout.as_millis() {
-                    0 => 1,
-                    other => other as u32,
-                },
-                None => 0,
-            };
-            let result = match addr {
-                SocketAddr::V4(v4_addr) => {
-                    let ip = v4_addr.ip().octets();
-                    let port = v4_addr.port() as u32;
-                    unsafe {
-                        host_api::networking::tcp_connect(
-                            4,
-                            ip.as_ptr(),
-                            port,
-                            0,
-                            0,
-                            timeout_ms,
-                            &mut id as *mut u64,
-                        )
-                    }
-                }
-                SocketAddr::V6(v6_addr) => {
-                    let ip = v6_addr.ip().octets();
-                    let port = v6_addr.port() as u32;
-                    let flow_info = v6_addr.flowinfo();
-                    let scope_id = v6_addr.scope_id();
-                    unsafe {
-                        host_api::networking::tcp_connect(
-                            6,
-                            ip.as_ptr(),
-                            port,
-                            flow_info,
-                            scope_id,
-                            timeout_ms,
-                            &mut id as *mut u64,
-                        )
-                    }
-                }
-            };
-            if result == 0 {
-                return Ok(TcpStream::from(id));
-            }
-        }
-        let lunatic_error = LunaticError::from(id);
-        Err(Error::new(ErrorKind::Other, lunatic_error))
+        let result = unsafe {
+            host_api::networking::tcp_connect(
+                addr_type,
+                ip.as_ptr(),
+                port,
+                flow_info,
+                scope_id,
+                timeout_ms(timeout),
+                &mut id as *mut u64,
+            )
+        };
+        value_result(result, id).map(TcpStream::from)
+    }
+
+    /// Returns the read timeout of this stream.
+    pub fn read_timeout(&self) -> Option<Duration> {
+        duration(self.read_timeout)
+    }
+
+    /// Returns the write timeout of this stream.
+    pub fn write_timeout(&self) -> Option<Duration> {
+        duration(self.write_timeout)
+    }
+}
+
+// Peeking, shutting down, addresses and socket options need host functions that the lunatic
+// v0.6.0 runtime doesn't provide.
+#[cfg(feature = "unstable-host")]
+impl TcpStream {
+    /// Reads data without removing it from the stream, so that the next read returns it again.
+    ///
+    /// Returns the number of bytes read.
+    pub fn peek(&self, buf: &mut [u8]) -> Result<usize> {
+        let mut nread_or_error_id: u64 = 0;
+        let result = unsafe {
+            host_api::networking::tcp_peek(
+                self.id,
+                buf.as_mut_ptr(),
+                buf.len(),
+                self.read_timeout,
+                &mut nread_or_error_id as *mut u64,
+            )
+        };
+        value_result(result, nread_or_error_id).map(|nread| nread as usize)
+    }
+
+    /// Shuts down the read, write, or both halves of this connection.
+    ///
+    /// The shutdown affects all handles to the same socket.
+    pub fn shutdown(&self, how: Shutdown) -> Result<()> {
+        let how = match how {
+            Shutdown::Read => 0,
+            Shutdown::Write => 1,
+            Shutdown::Both => 2,
+        };
+        let mut error_id = 0;
+        let result =
+            unsafe { host_api::networking::tcp_shutdown(self.id, how, &mut error_id as *mut u64) };
+        unit_result(result, error_id)
+    }
+
+    /// Returns the local address of this connection.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        let mut dns_iter_or_error_id = 0;
+        let result = unsafe {
+            host_api::networking::tcp_local_addr(self.id, &mut dns_iter_or_error_id as *mut u64)
+        };
+        addr_result(result, dns_iter_or_error_id)
+    }
+
+    /// Returns the address of the remote peer of this connection.
+    pub fn peer_addr(&self) -> Result<SocketAddr> {
+        let mut dns_iter_or_error_id = 0;
+        let result = unsafe {
+            host_api::networking::tcp_peer_addr(self.id, &mut dns_iter_or_error_id as *mut u64)
+        };
+        addr_result(result, dns_iter_or_error_id)
+    }
+
+    /// Sets the `TCP_NODELAY` option, that disables Nagle's algorithm when set. Small writes are
+    /// then sent right away, instead of being collected into bigger packets.
+    pub fn set_nodelay(&self, nodelay: bool) -> Result<()> {
+        let mut error_id = 0;
+        let result = unsafe {
+            host_api::networking::set_tcp_stream_nodelay(
+                self.id,
+                nodelay as u32,
+                &mut error_id as *mut u64,
+            )
+        };
+        unit_result(result, error_id)
+    }
+
+    /// Returns the value of the `TCP_NODELAY` option.
+    pub fn nodelay(&self) -> Result<bool> {
+        let mut value_or_error_id = 0;
+        let result = unsafe {
+            host_api::networking::get_tcp_stream_nodelay(
+                self.id,
+                &mut value_or_error_id as *mut u64,
+            )
+        };
+        value_result(result, value_or_error_id).map(|value| value != 0)
+    }
+
+    /// Sets the `IP_TTL` option, the time-to-live of packets sent from this stream.
+    pub fn set_ttl(&self, ttl: u32) -> Result<()> {
+        let mut error_id = 0;
+        let result = unsafe {
+            host_api::networking::set_tcp_stream_ttl(self.id, ttl, &mut error_id as *mut u64)
+        };
+        unit_result(result, error_id)
+    }
+
+    /// Returns the value of the `IP_TTL` option.
+    pub fn ttl(&self) -> Result<u32> {
+        let mut value_or_error_id = 0;
+        let result = unsafe {
+            host_api::networking::get_tcp_stream_ttl(self.id, &mut value_or_error_id as *mut u64)
+        };
+        value_result(result, value_or_error_id).map(|value| value as u32)
+    }
+
+    /// Enables TCP keepalive probes, that are sent after the connection was idle for
+    /// `keepalive`. `None` disables them.
+    pub fn set_keepalive(&self, keepalive: Option<Duration>) -> Result<()> {
+        let interval_ms = match keepalive {
+            // Keepalive intervals are counted in whole seconds by most systems.
+            Some(keepalive) => keepalive.as_millis().max(1000) as u64,
+            None => 0,
+        };
+        let mut error_id = 0;
+        let result = unsafe {
+            host_api::networking::set_tcp_stream_keepalive(
+                self.id,
+                interval_ms,
+                &mut error_id as *mut u64,
+            )
+        };
+        unit_result(result, error_id)
+    }
+
+    /// Returns the idle time after which keepalive probes are sent, or `None` if they are
+    /// disabled.
+    pub fn keepalive(&self) -> Result<Option<Duration>> {
+        let mut value_or_error_id = 0;
+        let result = unsafe {
+            host_api::networking::get_tcp_stream_keepalive(
+                self.id,
+                &mut value_or_error_id as *mut u64,
+            )
+        };
+        value_result(result, value_or_error_id).map(|interval_ms| match interval_ms {
+            0 => None,
+            ms => Some(Duration::from_millis(ms)),
+        })
     }
 }
 
@@ -224,7 +309,7 @@ impl Write for TcpStream {
             Ok(nwritten_or_error_id as usize)
         } else {
             let lunatic_error = LunaticError::from(nwritten_or_error_id);
-            Err(Error::new(ErrorKind::Other, lunatic_error))
+            Err(Error::from(lunatic_error))
         }
     }
 
@@ -234,7 +319,7 @@ impl Write for TcpStream {
             0 => Ok(()),
             _ => {
                 let lunatic_error = LunaticError::from(error_id);
-                Err(Error::new(ErrorKind::Other, lunatic_error))
+                Err(Error::from(lunatic_error))
             }
         }
     }
@@ -256,7 +341,7 @@ impl Read for TcpStream {
             Ok(nread_or_error_id as usize)
         } else {
             let lunatic_error = LunaticError::from(nread_or_error_id);
-            Err(Error::new(ErrorKind::Other, lunatic_error))
+            Err(Error::from(lunatic_error))
         }
     }
 }
@@ -0,0 +1,270 @@
+use std::cell::UnsafeCell;
+use std::io::{IoSlice, Read, Result, Write};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::{duration, timeout_ms, unit_result, value_result, TcpStream};
+use crate::{host_api, resource::transferable};
+
+/// Starts the client side of TLS connections.
+///
+/// By default the server certificate needs to be signed by one of the root certificates of the
+/// Mozilla CA program. Other roots, e.g. of a private CA or a self-signed certificate, can be
+/// trusted with [`add_root_certificates`](TlsConnector::add_root_certificates).
+///
+/// # Example
+///
+/// ```no_run
+/// use lunatic::{
+///     net::{TcpStream, TlsConnector},
+///     Mailbox,
+/// };
+/// use std::io::{Read, Write};
+///
+/// #[lunatic::main]
+/// fn main(_: Mailbox<()>) {
+///     let stream = TcpStream::connect("example.com:443").unwrap();
+///     let mut stream = TlsConnector::new().connect("example.com", stream).unwrap();
+///     stream
+///         .write_all(b"GET / HTTP/1.1\r\nHost: example.com\r\nConnection: close\r\n\r\n")
+///         .unwrap();
+///     let mut response = String::new();
+///     stream.read_to_string(&mut response).unwrap();
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TlsConnector {
+    // PEM encoded root certificates, if empty the default roots are used.
+    roots: Vec<u8>,
+}
+
+impl TlsConnector {
+    /// Creates a connector that trusts the default root certificates.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds PEM encoded root certificates.
+    ///
+    /// As soon as any root certificate is added, the default roots are not trusted anymore.
+    pub fn add_root_certificates(&mut self, pem: &[u8]) {
+        self.roots.extend_from_slice(pem);
+        self.roots.push(b'\n');
+    }
+
+    /// Performs the client handshake over `stream`.
+    ///
+    /// The `domain` is sent to the server with SNI and the certificate of the server needs to be
+    /// valid for it. IP addresses are accepted too. The handshake waits at most for the read
+    /// timeout of the stream, and the TLS stream keeps the read and write timeouts of the TCP
+    /// stream. If the handshake fails the TCP stream is closed.
+    pub fn connect(&self, domain: &str, stream: TcpStream) -> Result<TlsStream> {
+        let (read_timeout, write_timeout) = (stream.read_timeout(), stream.write_timeout());
+        let handshake_timeout = timeout_ms(read_timeout);
+        let tcp_stream_id = stream.into_id();
+        let mut id_or_error_id = 0;
+        let result = unsafe {
+            host_api::networking::tls_client_handshake(
+                tcp_stream_id,
+                domain.as_ptr(),
+                domain.len(),
+                self.roots.as_ptr(),
+                self.roots.len(),
+                handshake_timeout,
+                &mut id_or_error_id as *mut u64,
+            )
+        };
+        let id = value_result(result, id_or_error_id)?;
+        Ok(TlsStream::with_timeouts(id, read_timeout, write_timeout))
+    }
+}
+
+/// Starts the server side of TLS connections.
+///
+/// The acceptor holds the certificate chain and private key of the server and can be sent to
+/// other processes, e.g. to every process that handles a connection.
+///
+/// # Example
+///
+/// ```no_run
+/// use lunatic::{
+///     net::{TcpListener, TlsAcceptor, TlsStream},
+///     process, Mailbox,
+/// };
+///
+/// #[lunatic::main]
+/// fn main(_: Mailbox<()>) {
+///     let acceptor = TlsAcceptor::new(include_bytes!("cert.pem"), include_bytes!("key.pem"));
+///     let listener = TcpListener::bind("127.0.0.1:4433").unwrap();
+///     while let Ok((stream, _)) = listener.accept() {
+///         process::spawn_with((acceptor.clone(), stream), |(acceptor, stream), _: Mailbox<()>| {
+///             let stream: TlsStream = acceptor.accept(stream).unwrap();
+///             // ...
+///         })
+///         .unwrap();
+///     }
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TlsAcceptor {
+    cert_chain: Vec<u8>,
+    key: Vec<u8>,
+}
+
+impl TlsAcceptor {
+    /// Creates an acceptor from a PEM encoded certificate chain and private key.
+    ///
+    /// The chain starts with the certificate of the server, followed by intermediate
+    /// certificates. The key can be a PKCS#8, RSA or SEC1 elliptic curve key. Both are only
+    /// parsed during the handshake, so invalid ones make [`accept`](TlsAcceptor::accept) fail.
+    pub fn new(cert_chain_pem: &[u8], key_pem: &[u8]) -> Self {
+        Self {
+            cert_chain: cert_chain_pem.to_vec(),
+            key: key_pem.to_vec(),
+        }
+    }
+
+    /// Performs the server handshake over `stream`.
+    ///
+    /// The handshake waits at most for the read timeout of the stream, and the TLS stream keeps
+    /// the read and write timeouts of the TCP stream. If the handshake fails the TCP stream is
+    /// closed.
+    pub fn accept(&self, stream: TcpStream) -> Result<TlsStream> {
+        let (read_timeout, write_timeout) = (stream.read_timeout(), stream.write_timeout());
+        let handshake_timeout = timeout_ms(read_timeout);
+        let tcp_stream_id = stream.into_id();
+        let mut id_or_error_id = 0;
+        let result = unsafe {
+            host_api::networking::tls_server_handshake(
+                tcp_stream_id,
+                self.cert_chain.as_ptr(),
+                self.cert_chain.len(),
+                self.key.as_ptr(),
+                self.key.len(),
+                handshake_timeout,
+                &mut id_or_error_id as *mut u64,
+            )
+        };
+        let id = value_result(result, id_or_error_id)?;
+        Ok(TlsStream::with_timeouts(id, read_timeout, write_timeout))
+    }
+}
+
+/// A TLS stream on top of a TCP connection.
+///
+/// TLS streams are created from a [`TcpStream`] by a [`TlsConnector`] or [`TlsAcceptor`]. The
+/// encryption is done by the host, so a TLS stream can be sent to another process inside of a
+/// message like a TCP stream. The receiving process starts without read and write timeouts.
+#[derive(Debug)]
+pub struct TlsStream {
+    id: u64,
+    read_timeout: u32,  // ms
+    write_timeout: u32, // ms
+    // If the TLS stream is serialized it will be removed from our resources, so we can't call
+    // `drop_tls_stream()` anymore on it.
+    consumed: UnsafeCell<bool>,
+}
+
+impl Drop for TlsStream {
+    fn drop(&mut self) {
+        // Only drop stream if it's not already consumed
+        if unsafe { !*self.consumed.get() } {
+            unsafe { host_api::networking::drop_tls_stream(self.id) };
+        }
+    }
+}
+
+transferable!(
+    TlsStream,
+    host_api::message::push_tls_stream,
+    host_api::message::take_tls_stream
+);
+
+impl TlsStream {
+    pub(crate) fn from(id: u64) -> Self {
+        TlsStream::with_timeouts(id, None, None)
+    }
+
+    fn with_timeouts(
+        id: u64,
+        read_timeout: Option<Duration>,
+        write_timeout: Option<Duration>,
+    ) -> Self {
+        TlsStream {
+            id,
+            read_timeout: timeout_ms(read_timeout),
+            write_timeout: timeout_ms(write_timeout),
+            consumed: UnsafeCell::new(false),
+        }
+    }
+
+    /// Sets the read timeout.
+    ///
+    /// If the value specified is `None`, then read calls will block indefinitely.
+    pub fn set_read_timeout(&mut self, duration: Option<Duration>) {
+        self.read_timeout = timeout_ms(duration);
+    }
+
+    /// Sets the write timeout.
+    ///
+    /// If the value specified is `None`, then write calls will block indefinitely.
+    pub fn set_write_timeout(&mut self, duration: Option<Duration>) {
+        self.write_timeout = timeout_ms(duration);
+    }
+
+    /// Returns the read timeout of this stream.
+    pub fn read_timeout(&self) -> Option<Duration> {
+        duration(self.read_timeout)
+    }
+
+    /// Returns the write timeout of this stream.
+    pub fn write_timeout(&self) -> Option<Duration> {
+        duration(self.write_timeout)
+    }
+}
+
+impl Write for TlsStream {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let io_slice = IoSlice::new(buf);
+        self.write_vectored(&[io_slice])
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> Result<usize> {
+        let mut nwritten_or_error_id: u64 = 0;
+        let result = unsafe {
+            host_api::networking::tls_write_vectored(
+                self.id,
+                bufs.as_ptr() as *const u32,
+                bufs.len(),
+                self.write_timeout,
+                &mut nwritten_or_error_id as *mut u64,
+            )
+        };
+        value_result(result, nwritten_or_error_id).map(|written| written as usize)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        let mut error_id = 0;
+        let result = unsafe {
+            host_api::networking::tls_flush(self.id, self.write_timeout, &mut error_id as *mut u64)
+        };
+        unit_result(result, error_id)
+    }
+}
+
+impl Read for TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let mut nread_or_error_id: u64 = 0;
+        let result = unsafe {
+            host_api::networking::tls_read(
+                self.id,
+                buf.as_mut_ptr(),
+                buf.len(),
+                self.read_timeout,
+                &mut nread_or_error_id as *mut u64,
+            )
+        };
+        value_result(result, nread_or_error_id).map(|read| read as usize)
+    }
+}
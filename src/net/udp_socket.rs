@@ -0,0 +1,507 @@
+use std::{
+    cell::UnsafeCell,
+    io::{Error, ErrorKind, Result},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use super::{
+    addr_parts, addr_result, duration, io_error, timeout_ms, unit_result, value_result,
+    SocketAddrIterator,
+};
+use crate::{host_api, resource::transferable};
+
+/// A UDP socket.
+///
+/// After creating a [`UdpSocket`] by [`bind`][`UdpSocket::bind()`]ing it to a socket address,
+/// data can be [sent to] and [received from] any other socket address. It can also be
+/// [`connect`][`UdpSocket::connect()`]ed to a single remote address, so that [`send`] and
+/// [`recv`] can be used.
+///
+/// Cloning a [`UdpSocket`] creates another handle to the same socket. Sockets can be sent to other
+/// processes inside of messages. The socket will be closed when all handles to it are dropped.
+///
+/// The User Datagram Protocol is specified in [IETF RFC 768].
+///
+/// [sent to]: UdpSocket::send_to
+/// [received from]: UdpSocket::recv_from
+/// [`send`]: UdpSocket::send
+/// [`recv`]: UdpSocket::recv
+/// [IETF RFC 768]: https://tools.ietf.org/html/rfc768
+///
+/// # Examples
+///
+/// ```no_run
+/// use lunatic::net::UdpSocket;
+///
+/// let socket = UdpSocket::bind("127.0.0.1:34254").unwrap();
+/// // Receives a single datagram message on the socket. If `buf` is too small to hold the
+/// // message, it will be cut off.
+/// let mut buf = [0; 10];
+/// let (amt, src) = socket.recv_from(&mut buf).unwrap();
+/// // Redeclare `buf` as slice of the received data and send reverse data back to origin.
+/// let buf = &mut buf[..amt];
+/// buf.reverse();
+/// socket.send_to(buf, &src.to_string()).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct UdpSocket {
+    id: u64,
+    read_timeout: u32,  // ms
+    write_timeout: u32, // ms
+    // If the UDP socket is serialized it will be removed from our resources, so we can't call
+    // `drop_udp_socket()` anymore on it.
+    consumed: UnsafeCell<bool>,
+}
+
+impl Drop for UdpSocket {
+    fn drop(&mut self) {
+        // Only drop socket if it's not already consumed
+        if unsafe { !*self.consumed.get() } {
+            unsafe { host_api::networking::drop_udp_socket(self.id) };
+        }
+    }
+}
+
+impl Clone for UdpSocket {
+    fn clone(&self) -> Self {
+        let id = unsafe { host_api::networking::clone_udp_socket(self.id) };
+        Self {
+            id,
+            read_timeout: self.read_timeout,
+            write_timeout: self.write_timeout,
+            consumed: UnsafeCell::new(false),
+        }
+    }
+}
+
+// TODO: Timeout info is not transferred
+transferable!(
+    UdpSocket,
+    host_api::message::push_udp_socket,
+    host_api::message::take_udp_socket
+);
+
+impl UdpSocket {
+    pub(crate) fn from(id: u64) -> Self {
+        UdpSocket {
+            id,
+            read_timeout: 0,
+            write_timeout: 0,
+            consumed: UnsafeCell::new(false),
+        }
+    }
+
+    /// Creates a UDP socket bound to the given address.
+    ///
+    /// Binding with a port number of 0 will request that the operating system assigns an available
+    /// port to this socket.
+    ///
+    /// If `addr` yields multiple addresses, binding will be attempted with each of the addresses
+    /// until one succeeds and returns the socket. If none of the addresses succeed in creating a
+    /// socket, the error from the last attempt is returned.
+    pub fn bind<A>(addr: A) -> Result<Self>
+    where
+        A: super::ToSocketAddrs,
+    {
+        let mut id = 0;
+        for addr in addr.to_socket_addrs()? {
+            let (addr_type, ip, port, flow_info, scope_id) = addr_parts(&addr);
+            let result = unsafe {
+                host_api::networking::udp_bind(
+                    addr_type,
+                    ip.as_ptr(),
+                    port,
+                    flow_info,
+                    scope_id,
+                    &mut id as *mut u64,
+                )
+            };
+            if result == 0 {
+                return Ok(UdpSocket::from(id));
+            }
+        }
+        Err(io_error(id))
+    }
+
+    /// Connects the socket to a remote address.
+    ///
+    /// After connecting, [`send`](UdpSocket::send) and [`recv`](UdpSocket::recv) can be used and
+    /// datagrams from other addresses are dropped.
+    ///
+    /// If `addr` yields multiple addresses, connecting will be attempted with each of the
+    /// addresses until one succeeds. If none of the addresses succeed, the error from the last
+    /// attempt is returned.
+    pub fn connect<A>(&self, addr: A) -> Result<()>
+    where
+        A: super::ToSocketAddrs,
+    {
+        let mut error_id = 0;
+        for addr in addr.to_socket_addrs()? {
+            let (addr_type, ip, port, flow_info, scope_id) = addr_parts(&addr);
+            let result = unsafe {
+                host_api::networking::udp_connect(
+                    self.id,
+                    addr_type,
+                    ip.as_ptr(),
+                    port,
+                    flow_info,
+                    scope_id,
+                    &mut error_id as *mut u64,
+                )
+            };
+            if result == 0 {
+                return Ok(());
+            }
+        }
+        Err(io_error(error_id))
+    }
+
+    /// Sends data on the socket to the connected address and returns the number of bytes
+    /// written.
+    pub fn send(&self, buf: &[u8]) -> Result<usize> {
+        let mut nsent_or_error_id: u64 = 0;
+        let result = unsafe {
+            host_api::networking::udp_send(
+                self.id,
+                buf.as_ptr(),
+                buf.len(),
+                self.write_timeout,
+                &mut nsent_or_error_id as *mut u64,
+            )
+        };
+        if result == 0 {
+            Ok(nsent_or_error_id as usize)
+        } else {
+            Err(io_error(nsent_or_error_id))
+        }
+    }
+
+    /// Sends data on the socket to the given address and returns the number of bytes written.
+    ///
+    /// If `addr` yields multiple addresses, the data is only sent to the first one.
+    pub fn send_to<A>(&self, buf: &[u8], addr: A) -> Result<usize>
+    where
+        A: super::ToSocketAddrs,
+    {
+        let addr = match addr.to_socket_addrs()?.next() {
+            Some(addr) => addr,
+            None => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "no addresses to send data to",
+                ))
+            }
+        };
+        let (addr_type, ip, port, flow_info, scope_id) = addr_parts(&addr);
+        let mut nsent_or_error_id: u64 = 0;
+        let result = unsafe {
+            host_api::networking::udp_send_to(
+                self.id,
+                buf.as_ptr(),
+                buf.len(),
+                addr_type,
+                ip.as_ptr(),
+                port,
+                flow_info,
+                scope_id,
+                self.write_timeout,
+                &mut nsent_or_error_id as *mut u64,
+            )
+        };
+        if result == 0 {
+            Ok(nsent_or_error_id as usize)
+        } else {
+            Err(io_error(nsent_or_error_id))
+        }
+    }
+
+    /// Receives a datagram from the connected address and returns the number of bytes read.
+    ///
+    /// If `buf` is too small to hold the datagram, the rest of it is discarded.
+    pub fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        self.recv_(buf, false)
+    }
+
+    /// Same as [`recv`](UdpSocket::recv), but leaves the datagram in the queue, so that the next
+    /// receive returns it again.
+    pub fn peek(&self, buf: &mut [u8]) -> Result<usize> {
+        self.recv_(buf, true)
+    }
+
+    /// Receives a datagram and returns the number of bytes read and the address it came from.
+    ///
+    /// If `buf` is too small to hold the datagram, the rest of it is discarded.
+    pub fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr)> {
+        self.recv_from_(buf, false)
+    }
+
+    /// Same as [`recv_from`](UdpSocket::recv_from), but leaves the datagram in the queue, so
+    /// that the next receive returns it again.
+    pub fn peek_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr)> {
+        self.recv_from_(buf, true)
+    }
+
+    fn recv_(&self, buf: &mut [u8], peek: bool) -> Result<usize> {
+        let mut nread_or_error_id: u64 = 0;
+        let result = unsafe {
+            host_api::networking::udp_receive(
+                self.id,
+                buf.as_mut_ptr(),
+                buf.len(),
+                peek as u32,
+                self.read_timeout,
+                &mut nread_or_error_id as *mut u64,
+            )
+        };
+        if result == 0 {
+            Ok(nread_or_error_id as usize)
+        } else {
+            Err(io_error(nread_or_error_id))
+        }
+    }
+
+    fn recv_from_(&self, buf: &mut [u8], peek: bool) -> Result<(usize, SocketAddr)> {
+        let mut nread_or_error_id: u64 = 0;
+        let mut dns_iter_id: u64 = 0;
+        let result = unsafe {
+            host_api::networking::udp_receive_from(
+                self.id,
+                buf.as_mut_ptr(),
+                buf.len(),
+                peek as u32,
+                self.read_timeout,
+                &mut nread_or_error_id as *mut u64,
+                &mut dns_iter_id as *mut u64,
+            )
+        };
+        if result == 0 {
+            let mut dns_iter = SocketAddrIterator::from(dns_iter_id);
+            let peer = dns_iter.next().expect("must contain one element");
+            Ok((nread_or_error_id as usize, peer))
+        } else {
+            Err(io_error(nread_or_error_id))
+        }
+    }
+
+    /// Returns the address this socket is bound to.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        let mut dns_iter_or_error_id: u64 = 0;
+        let result = unsafe {
+            host_api::networking::udp_local_addr(self.id, &mut dns_iter_or_error_id as *mut u64)
+        };
+        addr_result(result, dns_iter_or_error_id)
+    }
+
+    /// Returns the address this socket is connected to.
+    pub fn peer_addr(&self) -> Result<SocketAddr> {
+        let mut dns_iter_or_error_id: u64 = 0;
+        let result = unsafe {
+            host_api::networking::udp_peer_addr(self.id, &mut dns_iter_or_error_id as *mut u64)
+        };
+        addr_result(result, dns_iter_or_error_id)
+    }
+
+    /// Sets the read timeout.
+    ///
+    /// If the value specified is `None`, then receive calls will block indefinitely.
+    pub fn set_read_timeout(&mut self, duration: Option<Duration>) {
+        self.read_timeout = timeout_ms(duration);
+    }
+
+    /// Returns the read timeout of this socket.
+    pub fn read_timeout(&self) -> Option<Duration> {
+        duration(self.read_timeout)
+    }
+
+    /// Sets the write timeout.
+    ///
+    /// If the value specified is `None`, then send calls will block indefinitely.
+    pub fn set_write_timeout(&mut self, duration: Option<Duration>) {
+        self.write_timeout = timeout_ms(duration);
+    }
+
+    /// Returns the write timeout of this socket.
+    pub fn write_timeout(&self) -> Option<Duration> {
+        duration(self.write_timeout)
+    }
+
+    /// Sets the `SO_BROADCAST` option, that allows sending to broadcast addresses.
+    pub fn set_broadcast(&self, broadcast: bool) -> Result<()> {
+        let mut error_id = 0;
+        let result = unsafe {
+            host_api::networking::set_udp_socket_broadcast(
+                self.id,
+                broadcast as u32,
+                &mut error_id as *mut u64,
+            )
+        };
+        unit_result(result, error_id)
+    }
+
+    /// Returns the value of the `SO_BROADCAST` option.
+    pub fn broadcast(&self) -> Result<bool> {
+        let mut value_or_error_id = 0;
+        let result = unsafe {
+            host_api::networking::get_udp_socket_broadcast(
+                self.id,
+                &mut value_or_error_id as *mut u64,
+            )
+        };
+        value_result(result, value_or_error_id).map(|value| value != 0)
+    }
+
+    /// Sets the `IP_TTL` option, the time-to-live of packets sent from this socket.
+    pub fn set_ttl(&self, ttl: u32) -> Result<()> {
+        let mut error_id = 0;
+        let result = unsafe {
+            host_api::networking::set_udp_socket_ttl(self.id, ttl, &mut error_id as *mut u64)
+        };
+        unit_result(result, error_id)
+    }
+
+    /// Returns the value of the `IP_TTL` option.
+    pub fn ttl(&self) -> Result<u32> {
+        let mut value_or_error_id = 0;
+        let result = unsafe {
+            host_api::networking::get_udp_socket_ttl(self.id, &mut value_or_error_id as *mut u64)
+        };
+        value_result(result, value_or_error_id).map(|value| value as u32)
+    }
+
+    /// Sets the `IP_MULTICAST_LOOP` option, that controls if IPv4 multicast packets are looped
+    /// back to the local socket.
+    pub fn set_multicast_loop_v4(&self, multicast_loop_v4: bool) -> Result<()> {
+        let mut error_id = 0;
+        let result = unsafe {
+            host_api::networking::set_udp_socket_multicast_loop_v4(
+                self.id,
+                multicast_loop_v4 as u32,
+                &mut error_id as *mut u64,
+            )
+        };
+        unit_result(result, error_id)
+    }
+
+    /// Returns the value of the `IP_MULTICAST_LOOP` option.
+    pub fn multicast_loop_v4(&self) -> Result<bool> {
+        let mut value_or_error_id = 0;
+        let result = unsafe {
+            host_api::networking::get_udp_socket_multicast_loop_v4(
+                self.id,
+                &mut value_or_error_id as *mut u64,
+            )
+        };
+        value_result(result, value_or_error_id).map(|value| value != 0)
+    }
+
+    /// Sets the `IP_MULTICAST_TTL` option, the time-to-live of IPv4 multicast packets sent from
+    /// this socket.
+    pub fn set_multicast_ttl_v4(&self, multicast_ttl_v4: u32) -> Result<()> {
+        let mut error_id = 0;
+        let result = unsafe {
+            host_api::networking::set_udp_socket_multicast_ttl_v4(
+                self.id,
+                multicast_ttl_v4,
+                &mut error_id as *mut u64,
+            )
+        };
+        unit_result(result, error_id)
+    }
+
+    /// Returns the value of the `IP_MULTICAST_TTL` option.
+    pub fn multicast_ttl_v4(&self) -> Result<u32> {
+        let mut value_or_error_id = 0;
+        let result = unsafe {
+            host_api::networking::get_udp_socket_multicast_ttl_v4(
+                self.id,
+                &mut value_or_error_id as *mut u64,
+            )
+        };
+        value_result(result, value_or_error_id).map(|value| value as u32)
+    }
+
+    /// Sets the `IPV6_MULTICAST_LOOP` option, that controls if IPv6 multicast packets are looped
+    /// back to the local socket.
+    pub fn set_multicast_loop_v6(&self, multicast_loop_v6: bool) -> Result<()> {
+        let mut error_id = 0;
+        let result = unsafe {
+            host_api::networking::set_udp_socket_multicast_loop_v6(
+                self.id,
+                multicast_loop_v6 as u32,
+                &mut error_id as *mut u64,
+            )
+        };
+        unit_result(result, error_id)
+    }
+
+    /// Returns the value of the `IPV6_MULTICAST_LOOP` option.
+    pub fn multicast_loop_v6(&self) -> Result<bool> {
+        let mut value_or_error_id = 0;
+        let result = unsafe {
+            host_api::networking::get_udp_socket_multicast_loop_v6(
+                self.id,
+                &mut value_or_error_id as *mut u64,
+            )
+        };
+        value_result(result, value_or_error_id).map(|value| value != 0)
+    }
+
+    /// Joins the IPv4 multicast group `multiaddr` on the network interface with the address
+    /// `interface`. If it's [`Ipv4Addr::UNSPECIFIED`], the system picks the interface.
+    pub fn join_multicast_v4(&self, multiaddr: &Ipv4Addr, interface: &Ipv4Addr) -> Result<()> {
+        let mut error_id = 0;
+        let result = unsafe {
+            host_api::networking::udp_join_multicast_v4(
+                self.id,
+                multiaddr.octets().as_ptr(),
+                interface.octets().as_ptr(),
+                &mut error_id as *mut u64,
+            )
+        };
+        unit_result(result, error_id)
+    }
+
+    /// Leaves the IPv4 multicast group `multiaddr`.
+    pub fn leave_multicast_v4(&self, multiaddr: &Ipv4Addr, interface: &Ipv4Addr) -> Result<()> {
+        let mut error_id = 0;
+        let result = unsafe {
+            host_api::networking::udp_leave_multicast_v4(
+                self.id,
+                multiaddr.octets().as_ptr(),
+                interface.octets().as_ptr(),
+                &mut error_id as *mut u64,
+            )
+        };
+        unit_result(result, error_id)
+    }
+
+    /// Joins the IPv6 multicast group `multiaddr` on the network interface with the index
+    /// `interface`. If it's 0, the system picks the interface.
+    pub fn join_multicast_v6(&self, multiaddr: &Ipv6Addr, interface: u32) -> Result<()> {
+        let mut error_id = 0;
+        let result = unsafe {
+            host_api::networking::udp_join_multicast_v6(
+                self.id,
+                multiaddr.octets().as_ptr(),
+                interface,
+                &mut error_id as *mut u64,
+            )
+        };
+        unit_result(result, error_id)
+    }
+
+    /// Leaves the IPv6 multicast group `multiaddr`.
+    pub fn leave_multicast_v6(&self, multiaddr: &Ipv6Addr, interface: u32) -> Result<()> {
+        let mut error_id = 0;
+        let result = unsafe {
+            host_api::networking::udp_leave_multicast_v6(
+                self.id,
+                multiaddr.octets().as_ptr(),
+                interface,
+                &mut error_id as *mut u64,
+            )
+        };
+        unit_result(result, error_id)
+    }
+}
@@ -0,0 +1,375 @@
+/*! Pools of identical workers that share the load */
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    abstract_process::AbstractProcess,
+    environment::ThisModule,
+    error::LunaticError,
+    host_api,
+    mailbox::{Mailbox, Message, ReceiveError, TransformMailbox},
+    process::{self, spawn_, Context, Process},
+    request::{Request, RequestError},
+    tag::Tag,
+};
+
+/// Decides which worker gets the next request.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dispatch {
+    /// Workers take turns.
+    RoundRobin,
+    /// The worker with the fewest unfinished requests is picked.
+    LeastBusy,
+}
+
+/// Starts a number of identical workers and balances requests between them.
+///
+/// Workers are defined with the [`AbstractProcess`] trait and every one of them is initialized
+/// with a clone of the same argument. All requests go through a pool manager process, that
+/// forwards them to one of the workers. The worker replies directly to the requesting process.
+/// Workers are linked to the manager and replaced if they die. Workers that fail to start are
+/// started again after a short delay, until then requests are handled by the remaining ones.
+/// Requests that were dispatched to a worker that died fail with
+/// [`RequestError::ProcessDied`].
+///
+/// Stateful workers, like database connections, can be taken out of the pool for exclusive use
+/// with [`checkout`](PoolRef::checkout) and returned with [`checkin`](PoolRef::checkin).
+///
+/// # Example
+///
+/// ```no_run
+/// use lunatic::{
+///     pool::{Dispatch, Pool},
+///     AbstractProcess, Mailbox,
+/// };
+///
+/// struct Adder;
+///
+/// impl AbstractProcess for Adder {
+///     type Arg = ();
+///     type State = ();
+///     type Request = (i32, i32);
+///     type Response = i32;
+///     type Message = ();
+///
+///     fn init(_: ()) {}
+///
+///     fn handle_request(_: &mut (), (a, b): (i32, i32)) -> i32 {
+///         a + b
+///     }
+/// }
+///
+/// #[lunatic::main]
+/// fn main(_: Mailbox<()>) {
+///     let mut pool = Pool::<Adder>::new(4, ());
+///     pool.set_dispatch(Dispatch::LeastBusy);
+///     let pool = pool.start().unwrap();
+///     assert_eq!(pool.request((1, 2)).unwrap(), 3);
+/// }
+/// ```
+pub struct Pool<A: AbstractProcess> {
+    size: usize,
+    arg: A::Arg,
+    dispatch: Dispatch,
+}
+
+impl<A> Pool<A>
+where
+    A: AbstractProcess,
+    A::Arg: Clone,
+{
+    /// Creates a pool of `size` workers, each initialized with a clone of `arg`.
+    ///
+    /// By default requests are dispatched round-robin.
+    pub fn new(size: usize, arg: A::Arg) -> Self {
+        assert!(size > 0, "Pool needs at least one worker");
+        Self {
+            size,
+            arg,
+            dispatch: Dispatch::RoundRobin,
+        }
+    }
+
+    /// Sets how requests are dispatched to workers.
+    pub fn set_dispatch(&mut self, dispatch: Dispatch) {
+        self.dispatch = dispatch;
+    }
+
+    /// Starts the pool manager and all workers.
+    pub fn start(self) -> Result<PoolRef<A>, LunaticError> {
+        let manager = process::spawn_with((self.size, self.arg, self.dispatch), manager::<A>)?;
+        Ok(PoolRef { manager })
+    }
+
+    /// Starts the pool manager and all workers inside of `module`.
+    pub fn start_in(self, module: &ThisModule) -> Result<PoolRef<A>, LunaticError> {
+        let manager = module.spawn_with((self.size, self.arg, self.dispatch), manager::<A>)?;
+        Ok(PoolRef { manager })
+    }
+}
+
+// Messages understood by the pool manager.
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+enum PoolMessage<A: AbstractProcess> {
+    // The manager answers with `None` if the worker handling the request dies.
+    Request(Request<A::Request, Option<A::Response>>),
+    Checkout(Request<(), Option<PoolWorker<A>>>),
+    Checkin(Tag),
+    // A worker finished a request that was dispatched by the manager. Carries the tags of the
+    // worker and of the reply.
+    Done(Tag, Tag),
+}
+
+// Messages understood by workers.
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+enum WorkerMessage<A: AbstractProcess> {
+    // Requests dispatched by the manager, that need to be reported as done.
+    Dispatched(Request<A::Request, Option<A::Response>>),
+    // Requests sent directly to a checked out worker.
+    Direct(Request<A::Request, A::Response>),
+}
+
+// Time after which the manager tries to start workers again that failed to start.
+const RESTART_DELAY: Duration = Duration::from_millis(100);
+
+// A worker as seen by the manager.
+struct Slot<A: AbstractProcess> {
+    tag: Tag,
+    // Empty if the worker failed to start.
+    process: Option<Process<WorkerMessage<A>>>,
+    // Dispatched requests that are not done yet, so that they can be failed if the worker dies.
+    outstanding: Vec<(Tag, Process<Option<A::Response>>)>,
+    checked_out: bool,
+}
+
+impl<A: AbstractProcess> Slot<A> {
+    // Returns true if requests can be dispatched to the worker.
+    fn is_available(&self) -> bool {
+        self.process.is_some() && !self.checked_out
+    }
+}
+
+// Entry point of the pool manager.
+fn manager<A>((size, arg, dispatch): (usize, A::Arg, Dispatch), mailbox: Mailbox<PoolMessage<A>>)
+where
+    A: AbstractProcess,
+    A::Arg: Clone,
+{
+    let this = process::this(&mailbox);
+    let mailbox = mailbox.catch_link_panic();
+    let spawn_worker = || {
+        let tag = Tag::new();
+        let context = (this.clone(), tag, arg.clone());
+        let process = spawn_(None, Some(tag), Context::With(worker::<A>, context)).ok();
+        Slot {
+            tag,
+            process,
+            outstanding: Vec::new(),
+            checked_out: false,
+        }
+    };
+    let mut slots: Vec<Slot<A>> = (0..size).map(|_| spawn_worker()).collect();
+    // Requests waiting for a worker, because all of them are checked out.
+    let mut pending: VecDeque<Request<A::Request, Option<A::Response>>> = VecDeque::new();
+    let mut next = 0;
+    // When empty slots are filled again.
+    let mut restart_at: Option<Instant> = None;
+
+    loop {
+        if restart_at.is_none() && slots.iter().any(|slot| slot.process.is_none()) {
+            restart_at = Some(host_api::now() + RESTART_DELAY);
+        }
+        let message = match restart_at {
+            Some(at) => mailbox.receive_timeout(at.saturating_duration_since(host_api::now())),
+            None => mailbox.receive(),
+        };
+        match message {
+            Message::Normal(Ok(PoolMessage::Request(request))) => pending.push_back(request),
+            Message::Normal(Ok(PoolMessage::Checkout(request))) => {
+                // Only idle workers are handed out, so that they don't have requests queued up.
+                let worker = slots
+                    .iter_mut()
+                    .find(|slot| slot.is_available() && slot.outstanding.is_empty())
+                    .and_then(|slot| {
+                        let process = slot.process.clone()?;
+                        slot.checked_out = true;
+                        Some(PoolWorker {
+                            tag: slot.tag,
+                            process,
+                        })
+                    });
+                request.reply(worker);
+            }
+            Message::Normal(Ok(PoolMessage::Checkin(tag))) => {
+                // Workers that died while checked out were already replaced.
+                if let Some(slot) = slots.iter_mut().find(|slot| slot.tag == tag) {
+                    slot.checked_out = false;
+                }
+            }
+            Message::Normal(Ok(PoolMessage::Done(tag, reply_tag))) => {
+                if let Some(slot) = slots.iter_mut().find(|slot| slot.tag == tag) {
+                    slot.outstanding.retain(|(tag, _)| *tag != reply_tag);
+                }
+            }
+            // Timeouts only wake up the manager to restart workers.
+            Message::Normal(Err(ReceiveError::Timeout)) => {}
+            // Messages that can't be deserialized are dropped.
+            Message::Normal(Err(_)) => continue,
+            Message::Signal(tag, _) => {
+                if let Some(index) = slots.iter().position(|slot| slot.tag == tag) {
+                    // A worker that dies right after replying gets a second reply sent for the
+                    // request, but nobody is waiting for it anymore.
+                    for (reply_tag, sender) in slots[index].outstanding.drain(..) {
+                        sender.tag_send(reply_tag, None);
+                    }
+                    slots[index] = spawn_worker();
+                }
+            }
+            Message::Down { .. } => continue,
+        }
+
+        if matches!(restart_at, Some(at) if at <= host_api::now()) {
+            restart_at = None;
+            for slot in slots.iter_mut().filter(|slot| slot.process.is_none()) {
+                *slot = spawn_worker();
+            }
+        }
+
+        while !pending.is_empty() && slots.iter().any(Slot::is_available) {
+            let index = match dispatch {
+                Dispatch::RoundRobin => loop {
+                    let index = next % slots.len();
+                    next = index + 1;
+                    if slots[index].is_available() {
+                        break index;
+                    }
+                },
+                Dispatch::LeastBusy => slots
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, slot)| slot.is_available())
+                    .min_by_key(|(_, slot)| slot.outstanding.len())
+                    .map(|(index, _)| index)
+                    .unwrap(),
+            };
+            let (request, reply_tag, sender) = pending.pop_front().unwrap().into_inner();
+            let slot = &mut slots[index];
+            slot.outstanding.push((reply_tag, sender.clone()));
+            if let Some(process) = &slot.process {
+                let request = Request::new(request, reply_tag, sender);
+                process.send(WorkerMessage::Dispatched(request));
+            }
+        }
+    }
+}
+
+// Entry point of every worker.
+fn worker<A: AbstractProcess>(
+    (manager, tag, arg): (Process<PoolMessage<A>>, Tag, A::Arg),
+    mailbox: Mailbox<WorkerMessage<A>>,
+) {
+    let mut state = A::init(arg);
+    loop {
+        match mailbox.receive() {
+            Ok(WorkerMessage::Dispatched(request)) => {
+                let (request, reply_tag, sender) = request.into_inner();
+                let response = A::handle_request(&mut state, request);
+                sender.tag_send(reply_tag, Some(response));
+                manager.send(PoolMessage::Done(tag, reply_tag));
+            }
+            Ok(WorkerMessage::Direct(request)) => {
+                let (request, reply_tag, sender) = request.into_inner();
+                let response = A::handle_request(&mut state, request);
+                sender.tag_send(reply_tag, response);
+            }
+            // Messages that can't be deserialized are dropped.
+            Err(_) => continue,
+        }
+    }
+}
+
+/// A handle to a running [`Pool`].
+///
+/// The handle can be cloned and sent to other processes.
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct PoolRef<A: AbstractProcess> {
+    manager: Process<PoolMessage<A>>,
+}
+
+impl<A: AbstractProcess> Clone for PoolRef<A> {
+    fn clone(&self) -> Self {
+        Self {
+            manager: self.manager.clone(),
+        }
+    }
+}
+
+impl<A: AbstractProcess> PoolRef<A> {
+    /// Sends a request to one of the workers and waits for the response.
+    pub fn request(&self, request: A::Request) -> Result<A::Response, RequestError> {
+        self.manager
+            .request_wrapped(request, PoolMessage::Request, None)?
+            .ok_or(RequestError::ProcessDied)
+    }
+
+    /// Same as [`request`](PoolRef::request), but only waits for the duration of timeout for the
+    /// response.
+    pub fn request_timeout(
+        &self,
+        request: A::Request,
+        timeout: Duration,
+    ) -> Result<A::Response, RequestError> {
+        self.manager
+            .request_wrapped(request, PoolMessage::Request, Some(timeout))?
+            .ok_or(RequestError::ProcessDied)
+    }
+
+    /// Takes an idle worker out of the pool for exclusive use.
+    ///
+    /// Returns `None` if all workers are busy or already checked out. No requests are dispatched
+    /// to the worker until it's returned with [`checkin`](PoolRef::checkin).
+    pub fn checkout(&self) -> Result<Option<PoolWorker<A>>, RequestError> {
+        self.manager
+            .request_wrapped((), PoolMessage::Checkout, None)
+    }
+
+    /// Returns a checked out worker to the pool.
+    pub fn checkin(&self, worker: PoolWorker<A>) {
+        self.manager.send(PoolMessage::Checkin(worker.tag));
+    }
+}
+
+/// A worker that was checked out of a [`Pool`].
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct PoolWorker<A: AbstractProcess> {
+    tag: Tag,
+    process: Process<WorkerMessage<A>>,
+}
+
+impl<A: AbstractProcess> PoolWorker<A> {
+    /// Sends a request to the worker and waits for the response.
+    pub fn request(&self, request: A::Request) -> Result<A::Response, RequestError> {
+        self.process
+            .request_wrapped(request, WorkerMessage::Direct, None)
+    }
+
+    /// Same as [`request`](PoolWorker::request), but only waits for the duration of timeout for
+    /// the response.
+    pub fn request_timeout(
+        &self,
+        request: A::Request,
+        timeout: Duration,
+    ) -> Result<A::Response, RequestError> {
+        self.process
+            .request_wrapped(request, WorkerMessage::Direct, Some(timeout))
+    }
+}
@@ -1,3 +1,14 @@
+/*!
+Spawning processes and communicating with them.
+
+Processes don't share memory with their parent, everything they start with is passed to them
+when they are spawned. [`spawn_with`] takes this context as a serializable value next to a plain
+function. [`spawn_closure!`](crate::spawn_closure) builds on it for closures: variables are
+moved into the new process only if they are named in its capture list. The list is explicit by
+design, a closure can't silently capture state that would need to be copied into another
+process, and every captured value is visible at the call site.
+*/
+
 use std::{
     cell::UnsafeCell,
     fmt::{self, Debug},
@@ -5,18 +16,22 @@ use std::{
     mem::transmute,
     time::Duration,
 };
+#[cfg(feature = "unstable-host")]
+use std::{panic, sync::Once};
 
+#[cfg(feature = "unstable-host")]
+use crate::timer::{self, Interval, TimerRef};
 use crate::{
+    codec::{Codec, MessagePack},
     environment::{params_to_vec, Param},
     error::LunaticError,
     host_api::{self, message, process},
-    mailbox::{LinkMailbox, Mailbox, MessageRw, TransformMailbox},
-    request::Request,
+    mailbox::{LinkMailbox, Mailbox, MessageRw, RawMessage, TransformMailbox, DOWN, TIMEOUT},
+    request::{Request, RequestError},
     tag::Tag,
     Environment,
 };
 
-use rmp_serde::decode;
 use serde::{
     de::{self, DeserializeOwned, Visitor},
     Deserialize, Deserializer, Serialize, Serializer,
@@ -31,15 +46,18 @@ use serde::{
 /// ### Safety:
 /// It's not safe to use mutable `static` variables to share data between processes, because each
 /// of them is going to see a separate heap and a unique `static` variable.
-pub struct Process<T: Serialize + DeserializeOwned> {
+///
+/// Messages are encoded with the [`Codec`] `C`, that needs to match the codec of the receiving
+/// mailbox.
+pub struct Process<T: Serialize + DeserializeOwned, C: Codec = MessagePack> {
     pub(crate) id: u64,
     // If the process handle is serialized it will be removed from our resources, so we can't call
     // `drop_process()` anymore on it.
     consumed: UnsafeCell<bool>,
-    _phantom: PhantomData<T>,
+    _phantom: PhantomData<(T, C)>,
 }
 
-impl<T: Serialize + DeserializeOwned> PartialEq for Process<T> {
+impl<T: Serialize + DeserializeOwned, C: Codec> PartialEq for Process<T, C> {
     fn eq(&self, other: &Self) -> bool {
         let mut uuid_self: [u8; 16] = [0; 16];
         unsafe { host_api::process::id(self.id, &mut uuid_self as *mut [u8; 16]) };
@@ -49,7 +67,7 @@ impl<T: Serialize + DeserializeOwned> PartialEq for Process<T> {
     }
 }
 
-impl<T: Serialize + DeserializeOwned> Debug for Process<T> {
+impl<T: Serialize + DeserializeOwned, C: Codec> Debug for Process<T, C> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut uuid: [u8; 16] = [0; 16];
         unsafe { host_api::process::id(self.id, &mut uuid as *mut [u8; 16]) };
@@ -59,14 +77,14 @@ impl<T: Serialize + DeserializeOwned> Debug for Process<T> {
     }
 }
 
-impl<T: Serialize + DeserializeOwned> Clone for Process<T> {
+impl<T: Serialize + DeserializeOwned, C: Codec> Clone for Process<T, C> {
     fn clone(&self) -> Self {
         let id = unsafe { host_api::process::clone_process(self.id) };
         Process::from(id)
     }
 }
 
-impl<T: Serialize + DeserializeOwned> Drop for Process<T> {
+impl<T: Serialize + DeserializeOwned, C: Codec> Drop for Process<T, C> {
     fn drop(&mut self) {
         // Only drop process if it's not already consumed
         if unsafe { !*self.consumed.get() } {
@@ -74,7 +92,7 @@ impl<T: Serialize + DeserializeOwned> Drop for Process<T> {
         }
     }
 }
-impl<T: Serialize + DeserializeOwned> Serialize for Process<T> {
+impl<T: Serialize + DeserializeOwned, C: Codec> Serialize for Process<T, C> {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
@@ -86,11 +104,11 @@ impl<T: Serialize + DeserializeOwned> Serialize for Process<T> {
         serializer.serialize_u64(index)
     }
 }
-struct ProcessVisitor<T> {
-    _phantom: PhantomData<T>,
+struct ProcessVisitor<T, C> {
+    _phantom: PhantomData<(T, C)>,
 }
-impl<'de, T: Serialize + DeserializeOwned> Visitor<'de> for ProcessVisitor<T> {
-    type Value = Process<T>;
+impl<'de, T: Serialize + DeserializeOwned, C: Codec> Visitor<'de> for ProcessVisitor<T, C> {
+    type Value = Process<T, C>;
 
     fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
         formatter.write_str("an u64 index")
@@ -105,8 +123,8 @@ impl<'de, T: Serialize + DeserializeOwned> Visitor<'de> for ProcessVisitor<T> {
     }
 }
 
-impl<'de, T: Serialize + DeserializeOwned> Deserialize<'de> for Process<T> {
-    fn deserialize<D>(deserializer: D) -> Result<Process<T>, D::Error>
+impl<'de, T: Serialize + DeserializeOwned, C: Codec> Deserialize<'de> for Process<T, C> {
+    fn deserialize<D>(deserializer: D) -> Result<Process<T, C>, D::Error>
     where
         D: Deserializer<'de>,
     {
@@ -116,7 +134,7 @@ impl<'de, T: Serialize + DeserializeOwned> Deserialize<'de> for Process<T> {
     }
 }
 
-impl<T: Serialize + DeserializeOwned> Process<T> {
+impl<T: Serialize + DeserializeOwned, C: Codec> Process<T, C> {
     pub(crate) fn from(id: u64) -> Self {
         Process {
             id,
@@ -125,6 +143,12 @@ impl<T: Serialize + DeserializeOwned> Process<T> {
         }
     }
 
+    // Gives up ownership of the process resource without dropping it.
+    pub(crate) fn into_id(self) -> u64 {
+        unsafe { *self.consumed.get() = true };
+        self.id
+    }
+
     pub fn id(&self) -> u128 {
         let mut uuid: [u8; 16] = [0; 16];
         unsafe { host_api::process::id(self.id, &mut uuid as *mut [u8; 16]) };
@@ -146,11 +170,67 @@ impl<T: Serialize + DeserializeOwned> Process<T> {
         // Create new message
         unsafe { message::create_data(tag, 0) };
         // During serialization resources will add themself to the message
-        rmp_serde::encode::write(&mut MessageRw {}, &message).unwrap();
+        C::encode(&mut MessageRw {}, &message).unwrap();
         // Send it
         unsafe { message::send(self.id) };
     }
 
+    /// Sends `data` to the process without encoding it.
+    ///
+    /// The receiving process needs to use a [`RawMailbox`](crate::RawMailbox) to read it.
+    pub fn send_bytes(&self, data: &[u8]) {
+        self.send_bytes_(0, data)
+    }
+
+    /// Tag `data` and send it to the process without encoding it.
+    pub fn tag_send_bytes(&self, tag: Tag, data: &[u8]) {
+        self.send_bytes_(tag.id(), data)
+    }
+
+    fn send_bytes_(&self, tag: i64, data: &[u8]) {
+        unsafe {
+            message::create_data(tag, data.len() as u64);
+            message::write_data(data.as_ptr(), data.len());
+            message::send(self.id);
+        }
+    }
+
+    /// Sends a [`RawMessage`] with its attached resources to the process.
+    ///
+    /// The receiving process needs to use a [`RawMailbox`](crate::RawMailbox) to read it.
+    pub fn send_raw(&self, message: RawMessage) {
+        message.write();
+        unsafe { message::send(self.id) };
+    }
+
+    /// Sends a message to the process after `delay`.
+    ///
+    /// The message is serialized right away and delivered by the host, the current process is
+    /// not blocked. The delivery can be cancelled with the returned [`TimerRef`].
+    #[cfg(feature = "unstable-host")]
+    pub fn send_after(&self, message: T, delay: Duration) -> TimerRef {
+        // Create new message
+        unsafe { message::create_data(0, 0) };
+        // During serialization resources will add themself to the message
+        C::encode(&mut MessageRw {}, &message).unwrap();
+        // Hand it over to the host
+        let timer_id = unsafe { host_api::timer::send_after(self.id, delay.as_millis() as u64) };
+        TimerRef::from(timer_id)
+    }
+
+    /// Sends a clone of `message` to the process every `period`.
+    ///
+    /// All ticks are tagged with [`Interval::tag`], so they can be told apart from other messages
+    /// with [`Mailbox::tag_receive`]. Ticks stop when the interval is cancelled or the receiving
+    /// process dies.
+    #[cfg(feature = "unstable-host")]
+    pub fn interval(&self, message: T, period: Duration) -> Result<Interval, LunaticError>
+    where
+        T: Clone,
+    {
+        timer::start_interval(self, message, period)
+    }
+
     /// Links the current process with another one.
     pub fn link(&self) -> Tag {
         let tag = Tag::new();
@@ -162,22 +242,89 @@ impl<T: Serialize + DeserializeOwned> Process<T> {
     pub fn unlink(&self) {
         unsafe { process::unlink(self.id) };
     }
+
+    /// Monitors the process.
+    ///
+    /// When the monitored process dies, for any reason, a [`Message::Down`] notification is
+    /// delivered to the current process. Contrary to links, monitors are one-way and the death of
+    /// the monitored process is never propagated to the current one. Down notifications can only
+    /// be received through a [`LinkMailbox`], that's why a reference to it is required.
+    ///
+    /// [`Message::Down`]: crate::Message::Down
+    #[cfg(feature = "unstable-host")]
+    pub fn monitor<P, D>(&self, _mailbox: &LinkMailbox<P, D>) -> Monitor
+    where
+        P: Serialize + DeserializeOwned,
+        D: Codec,
+    {
+        let tag = Tag::new();
+        unsafe { process::monitor(tag.id(), self.id) };
+        Monitor::from(tag)
+    }
+
+    /// Stops monitoring the process.
+    ///
+    /// If the down notification for this monitor was already delivered, it's removed from the
+    /// mailbox.
+    #[cfg(feature = "unstable-host")]
+    pub fn demonitor(&self, monitor: Monitor) {
+        unsafe { process::demonitor(monitor.tag().id(), self.id) };
+    }
+
+    /// Kills the process.
+    ///
+    /// Processes linked to it are going to be notified the same way as if it failed.
+    #[cfg(feature = "unstable-host")]
+    pub fn kill(&self) {
+        unsafe { process::kill(self.id) };
+    }
+
+    // Processes can only receive one type of messages, but sometimes we need to keep handles to
+    // processes with different message types together. This erases the message type.
+    #[cfg(feature = "unstable-host")]
+    pub(crate) fn cast<U: Serialize + DeserializeOwned>(self) -> Process<U, C> {
+        unsafe { transmute(self) }
+    }
+
+    /// Encode messages sent to the process with a different [`Codec`].
+    ///
+    /// The receiving mailbox needs to use the same codec, e.g. with
+    /// [`Mailbox::with_codec`](crate::Mailbox::with_codec).
+    pub fn with_codec<D: Codec>(self) -> Process<T, D> {
+        unsafe { transmute(self) }
+    }
 }
 
-impl<T, U> Process<Request<T, U>>
+impl<T, U, C> Process<Request<T, U, C>, C>
 where
     T: Serialize + DeserializeOwned,
     U: Serialize + DeserializeOwned,
+    C: Codec,
 {
-    pub fn request(&self, message: T) -> Result<U, decode::Error> {
-        self.request_(message, None)
+    /// Sends a request to the process and waits for the response.
+    pub fn request(&self, message: T) -> Result<U, RequestError> {
+        self.request_wrapped(message, |request| request, None)
     }
 
-    pub fn request_timeout(&self, message: T, timeout: Duration) -> Result<U, decode::Error> {
-        self.request_(message, Some(timeout))
+    /// Same as [`request`](Process::request), but only waits for the duration of timeout for the
+    /// response.
+    pub fn request_timeout(&self, message: T, timeout: Duration) -> Result<U, RequestError> {
+        self.request_wrapped(message, |request| request, Some(timeout))
     }
+}
 
-    fn request_(&self, message: T, timeout: Option<Duration>) -> Result<U, decode::Error> {
+impl<M: Serialize + DeserializeOwned, C: Codec> Process<M, C> {
+    // Sends a request wrapped into the message type of the process and waits for the reply.
+    pub(crate) fn request_wrapped<T, U>(
+        &self,
+        message: T,
+        wrap: fn(Request<T, U, C>) -> M,
+        timeout: Option<Duration>,
+    ) -> Result<U, RequestError>
+    where
+        T: Serialize + DeserializeOwned,
+        U: Serialize + DeserializeOwned,
+    {
         let timeout_ms = match timeout {
             // If waiting time is smaller than 1ms, round it up to 1ms.
             Some(timeout) => match timeout.as_millis() {
@@ -187,23 +334,62 @@ where
             None => 0,
         };
         // The response can be an arbitrary type and doesn't need to match the the current one.
-        let one_time_mailbox = unsafe { Mailbox::<U>::new() };
+        let one_time_mailbox = unsafe { Mailbox::<U, C>::new() };
         let sender_process = this(&one_time_mailbox);
         let tag = Tag::new();
-        let request = Request::new(message, tag, sender_process);
+        let request = wrap(Request::new(message, tag, sender_process));
         // Create new message
         unsafe { message::create_data(tag.id(), 0) };
         // During serialization resources will add themself to the message
-        rmp_serde::encode::write(&mut MessageRw {}, &request).unwrap();
+        C::encode(&mut MessageRw {}, &request)?;
+        // Monitor the receiver with the same tag as the request. If it dies before replying, the
+        // down notification is going to match the tag and stop the wait.
+        #[cfg(feature = "unstable-host")]
+        unsafe {
+            process::monitor(tag.id(), self.id)
+        };
         // Send it and wait for an reply
-        unsafe { message::send_receive_skip_search(self.id, timeout_ms) };
-        // Read the message out from the scratch buffer
-        rmp_serde::from_read(MessageRw {})
+        let message_type = unsafe { message::send_receive_skip_search(self.id, timeout_ms) };
+        #[cfg(feature = "unstable-host")]
+        unsafe {
+            process::demonitor(tag.id(), self.id)
+        };
+        match message_type {
+            TIMEOUT => Err(RequestError::Timeout),
+            DOWN => Err(RequestError::ProcessDied),
+            // Read the message out from the scratch buffer
+            _ => Ok(C::decode(&mut MessageRw {})?),
+        }
+    }
+}
+
+/// A reference to a monitor created with [`Process::monitor`].
+///
+/// Down notifications carry the monitor they belong to.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct Monitor {
+    tag: Tag,
+}
+
+impl Monitor {
+    #[cfg(feature = "unstable-host")]
+    pub(crate) fn from(tag: Tag) -> Self {
+        Self { tag }
+    }
+
+    /// Returns the tag of down notifications belonging to this monitor.
+    pub fn tag(&self) -> Tag {
+        self.tag
     }
 }
 
 /// Returns a handle to the current process.
-pub fn this<T: Serialize + DeserializeOwned, U: TransformMailbox<T>>(_mailbox: &U) -> Process<T> {
+pub fn this<T, C, U>(_mailbox: &U) -> Process<T, C>
+where
+    T: Serialize + DeserializeOwned,
+    C: Codec,
+    U: TransformMailbox<T, C>,
+{
     let id = unsafe { process::this() };
     Process::from(id)
 }
@@ -284,6 +470,60 @@ pub fn spawn_with<C: Serialize + DeserializeOwned, T: Serialize + DeserializeOwn
     spawn_(None, None, Context::With(function, context))
 }
 
+/// Spawns a new process from a closure and the variables it captures.
+///
+/// Processes don't share memory, so a closure can't just capture variables from the parent. This
+/// macro takes a list of variables that are moved into the new process. They are serialized the
+/// same way a context passed to [`spawn_with`] is, so each of them needs to implement
+/// [`Serialize + DeserializeOwned`]. Resources, like [`Process`] or
+/// [`TcpStream`](crate::net::TcpStream), are moved to the new process. Inside of the closure the
+/// variables have the same names as in the parent.
+///
+/// The closure can't use any other variables from the parent, this is checked at compile time.
+/// The mailbox argument can be any pattern, e.g. `mut mailbox` or `_`, with an optional type.
+///
+/// # Example
+///
+/// ```no_run
+/// use lunatic::{process, spawn_closure, Mailbox};
+///
+/// #[lunatic::main]
+/// fn main(m: Mailbox<String>) {
+///     let parent = process::this(&m);
+///     let greeting = "Hello".to_string();
+///     spawn_closure!([parent, greeting] |mailbox: Mailbox<String>| {
+///         let name = mailbox.receive().unwrap();
+///         parent.send(format!("{} {}!", greeting, name));
+///     })
+///     .unwrap()
+///     .send("World".to_string());
+///     assert_eq!(m.receive().unwrap(), "Hello World!");
+/// }
+/// ```
+#[macro_export]
+macro_rules! spawn_closure {
+    // A `pat` fragment can't be followed by a type annotation, so typed mailboxes are matched by
+    // the patterns that can bind them.
+    ([$($capture:ident),* $(,)?] $(move)? |mut $mailbox:ident $(: $mailbox_type:ty)?| $body:expr) => {
+        $crate::spawn_closure!(@spawn [$($capture),*] [mut $mailbox] [$($mailbox_type)?] $body)
+    };
+    ([$($capture:ident),* $(,)?] $(move)? |$mailbox:ident $(: $mailbox_type:ty)?| $body:expr) => {
+        $crate::spawn_closure!(@spawn [$($capture),*] [$mailbox] [$($mailbox_type)?] $body)
+    };
+    ([$($capture:ident),* $(,)?] $(move)? |_ $(: $mailbox_type:ty)?| $body:expr) => {
+        $crate::spawn_closure!(@spawn [$($capture),*] [_] [$($mailbox_type)?] $body)
+    };
+    ([$($capture:ident),* $(,)?] $(move)? |$mailbox:pat| $body:expr) => {
+        $crate::spawn_closure!(@spawn [$($capture),*] [$mailbox] [] $body)
+    };
+    (@spawn [$($capture:ident),*] [$($mailbox:tt)*] [$($mailbox_type:ty)?] $body:expr) => {
+        $crate::process::spawn_with(
+            ($($capture,)*),
+            |($($capture,)*), $($mailbox)* $(: $mailbox_type)?| $body,
+        )
+    };
+}
+
 /// Spawns a new process from a function and context, and links it to the parent.
 ///
 /// - `context` is  data that we want to pass to the newly spawned process. It needs to impl.
@@ -361,7 +601,7 @@ pub(crate) fn spawn_<C: Serialize + DeserializeOwned, T: Serialize + Deserialize
         Context::With(func, _) => (type_helper_wrapper_context::<C, T> as usize, func as usize),
         Context::Without(func) => (type_helper_wrapper::<T> as usize, func as usize),
     };
-    let params = params_to_vec(&[Param::I32(type_helper as i32), Param::I32(func as i32)]);
+    let params = params_to_vec(&[pointer_param(type_helper), pointer_param(func)]);
     let mut id = 0;
     let func = "_lunatic_spawn_by_index";
     let link = match link {
@@ -393,7 +633,7 @@ pub(crate) fn spawn_<C: Serialize + DeserializeOwned, T: Serialize + Deserialize
         match context {
             // If context exists, send it as first message to the new process
             Context::With(_, context) => {
-                let child = Process {
+                let child: Process<C> = Process {
                     id,
                     consumed: UnsafeCell::new(false),
                     _phantom: PhantomData,
@@ -430,14 +670,92 @@ fn type_helper_wrapper<T: Serialize + DeserializeOwned>(function: usize) {
 fn type_helper_wrapper_context<C: Serialize + DeserializeOwned, T: Serialize + DeserializeOwned>(
     function: usize,
 ) {
-    let context = unsafe { Mailbox::new() }.receive().unwrap();
+    // Nothing can be left behind by a selective receive yet, so the context is the first message
+    // in the host queue.
+    let (context, _) = unsafe { Mailbox::<C>::new() }
+        .receive_host(0, None)
+        .unwrap();
     let mailbox = unsafe { Mailbox::new() };
     let function: fn(C, Mailbox<T>) = unsafe { transmute(function) };
     function(context, mailbox);
 }
 
+// Function pointers are indexes into the function table on wasm32, but native builds using the
+// mock host have 64 bit pointers.
+#[cfg(target_pointer_width = "32")]
+fn pointer_param(pointer: usize) -> Param {
+    Param::I32(pointer as i32)
+}
+
+#[cfg(target_pointer_width = "64")]
+fn pointer_param(pointer: usize) -> Param {
+    Param::I64(pointer as i64)
+}
+
+// Serializes function pointers as their index, the same way spawned processes receive their
+// entry function, e.g. with `#[serde(with = "crate::process::function")]`. The index is only
+// valid inside of the same module and deserializing turns any number into a function, so this
+// must only be used by private types that the crate sends to processes it spawned itself.
+#[cfg(feature = "http")]
+pub(crate) mod function {
+    use std::mem::{size_of, transmute_copy};
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub(crate) trait FunctionPointer: Copy {}
+    impl<A, R> FunctionPointer for fn(A) -> R {}
+    impl<A, B, R> FunctionPointer for fn(A, B) -> R {}
+
+    pub(crate) fn serialize<F: FunctionPointer, S: Serializer>(
+        function: &F,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        assert_eq!(size_of::<F>(), size_of::<usize>());
+        let index: usize = unsafe { transmute_copy(function) };
+        serializer.serialize_u64(index as u64)
+    }
+
+    pub(crate) fn deserialize<'de, F: FunctionPointer, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<F, D::Error> {
+        assert_eq!(size_of::<F>(), size_of::<usize>());
+        let index = u64::deserialize(deserializer)? as usize;
+        Ok(unsafe { transmute_copy(&index) })
+    }
+}
+
 #[export_name = "_lunatic_spawn_by_index"]
 extern "C" fn _lunatic_spawn_by_index(type_helper: usize, function: usize) {
+    spawn_by_index(type_helper, function);
+}
+
+// Called by the mock host directly, so that panics can unwind the native thread of a process.
+pub(crate) fn spawn_by_index(type_helper: usize, function: usize) {
+    #[cfg(feature = "unstable-host")]
+    set_panic_hook();
     let type_helper: fn(usize) = unsafe { transmute(type_helper) };
     type_helper(function);
 }
+
+// A panic traps the process. Before that happens, the panic message is passed to the host, so that
+// linked processes can receive it as the exit reason.
+#[cfg(feature = "unstable-host")]
+fn set_panic_hook() {
+    // With the mock host all processes share one native panic hook.
+    static SET_HOOK: Once = Once::new();
+    SET_HOOK.call_once(|| {
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let payload = info.payload();
+            let message = match payload.downcast_ref::<&str>() {
+                Some(message) => message.to_string(),
+                None => match payload.downcast_ref::<String>() {
+                    Some(message) => message.clone(),
+                    None => info.to_string(),
+                },
+            };
+            unsafe { process::set_panic_message(message.as_ptr(), message.len()) };
+            default_hook(info);
+        }));
+    });
+}
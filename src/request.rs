@@ -1,26 +1,36 @@
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
 
-use crate::{process::Process, tag::Tag};
+use crate::{
+    codec::{Codec, DecodeError, EncodeError, MessagePack},
+    process::Process,
+    tag::Tag,
+};
 
+/// A message that expects a reply.
+///
+/// The reply is encoded with the same [`Codec`] as the request.
 #[derive(Serialize, Deserialize)]
-#[serde(bound(deserialize = "T: Deserialize<'de>"))]
-pub struct Request<T, U>
+#[serde(bound(serialize = "T: Serialize", deserialize = "T: Deserialize<'de>"))]
+pub struct Request<T, U, C = MessagePack>
 where
     T: Serialize,
     U: Serialize + DeserializeOwned,
+    C: Codec,
 {
     message: T,
     tag: Tag,
-    sender_process: Process<U>,
+    sender_process: Process<U, C>,
 }
 
-impl<T, U> Request<T, U>
+impl<T, U, C> Request<T, U, C>
 where
     T: Serialize + DeserializeOwned,
     U: Serialize + DeserializeOwned,
+    C: Codec,
 {
     /// Create a new request
-    pub(crate) fn new(message: T, tag: Tag, sender_process: Process<U>) -> Self {
+    pub(crate) fn new(message: T, tag: Tag, sender_process: Process<U, C>) -> Self {
         Self {
             message,
             tag,
@@ -44,7 +54,27 @@ where
     }
 
     /// Get a reference to the sender process.
-    pub fn sender(&self) -> &Process<U> {
+    pub fn sender(&self) -> &Process<U, C> {
         &self.sender_process
     }
+
+    // Takes the request apart, so that the message can be consumed before replying.
+    pub(crate) fn into_inner(self) -> (T, Tag, Process<U, C>) {
+        (self.message, self.tag, self.sender_process)
+    }
+}
+
+/// Represents an error while making a request.
+#[derive(Error, Debug)]
+pub enum RequestError {
+    #[error("Timed out while waiting for response")]
+    Timeout,
+    /// Without the `unstable-host` feature this is only detected for requests to a pool, other
+    /// requests wait until they time out.
+    #[error("Process died before responding")]
+    ProcessDied,
+    #[error("Deserialization failed")]
+    Deserialization(#[from] DecodeError),
+    #[error("Serialization failed")]
+    Serialization(#[from] EncodeError),
 }
@@ -0,0 +1,37 @@
+/*! Moving host resources between processes inside of messages */
+
+// Implements `Serialize` and `Deserialize` for a resource handle.
+//
+// The handle needs an `id` and a `consumed: UnsafeCell<bool>` field, and a `from(id)`
+// constructor. Serializing moves the resource into the message that is currently written and
+// marks the handle as consumed, so that it doesn't drop the resource anymore. Only the index of
+// the resource inside of the message is encoded, deserializing takes it out of the received
+// message again.
+macro_rules! transferable {
+    ($type:ty, $push:path, $take:path) => {
+        impl serde::Serialize for $type {
+            fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+            where
+                S: serde::Serializer,
+            {
+                // Mark resource as consumed
+                unsafe { *self.consumed.get() = true };
+                let index = unsafe { $push(self.id) };
+                serializer.serialize_u64(index)
+            }
+        }
+
+        impl<'de> serde::Deserialize<'de> for $type {
+            fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+            where
+                D: serde::Deserializer<'de>,
+            {
+                let index = <u64 as serde::Deserialize>::deserialize(deserializer)?;
+                let id = unsafe { $take(index) };
+                Ok(<$type>::from(id))
+            }
+        }
+    };
+}
+
+pub(crate) use transferable;
@@ -0,0 +1,141 @@
+/*! Deterministic simulations of process systems on the mock host */
+
+use std::{
+    env,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+
+use crate::{mailbox::Mailbox, mock};
+
+/// Runs processes on a single deterministic scheduler.
+///
+/// Inside of a simulation only one process runs at a time. A random number generator, seeded
+/// with the [`seed`](Simulation::seed), decides which process runs next and when messages, link
+/// signals and down notifications are delivered. Timeouts, [`sleep`](crate::process::sleep) and
+/// timers use virtual time, that jumps forward when all processes are waiting. Running the same
+/// code with the same seed always results in the same schedule, so a failure found with a random
+/// seed can be replayed.
+///
+/// The simulation ends when the root process finishes and all processes still running are killed
+/// at that point. The code under test needs to be deterministic itself for replays to work, e.g.
+/// it shouldn't depend on the iteration order of a `HashMap` or on the system clock. Networking
+/// calls are not simulated and block the whole simulation.
+///
+/// # Example
+///
+/// ```no_run
+/// use lunatic::{process, simulation::Simulation, Mailbox};
+///
+/// let result = Simulation::new(42).run(|mailbox: Mailbox<u64>| {
+///     let this = process::this(&mailbox);
+///     process::spawn_with(this, |parent, _: Mailbox<()>| parent.send(1)).unwrap();
+///     assert_eq!(mailbox.receive().unwrap(), 1);
+/// });
+/// assert!(result.is_ok());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Simulation {
+    seed: u64,
+    max_steps: u64,
+}
+
+impl Simulation {
+    /// Creates a simulation with the given `seed`.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            max_steps: 1_000_000,
+        }
+    }
+
+    /// Creates a simulation with the seed from the `LUNATIC_SEED` environment variable, or a
+    /// random one if it's not set.
+    pub fn from_env() -> Self {
+        let seed = env::var("LUNATIC_SEED")
+            .ok()
+            .and_then(|seed| seed.parse().ok())
+            .unwrap_or_else(random_seed);
+        Self::new(seed)
+    }
+
+    /// Returns the seed of the simulation.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Sets the maximum number of scheduling steps, after which the simulation fails with
+    /// [`SimulationError::StepLimit`]. This catches processes that never stop.
+    ///
+    /// By default the limit is 1 000 000 steps.
+    pub fn set_max_steps(&mut self, max_steps: u64) {
+        self.max_steps = max_steps;
+    }
+
+    /// Runs `entry` as the root process of the simulation and waits until it finishes.
+    pub fn run<T, F>(&self, entry: F) -> Result<(), SimulationError>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce(Mailbox<T>) + Send + 'static,
+    {
+        mock::simulate(self.seed, self.max_steps, move || {
+            entry(unsafe { Mailbox::new() })
+        })
+    }
+}
+
+/// Runs `entry` in `runs` simulations with random seeds and panics on the first failure.
+///
+/// The panic message contains the failing seed. Setting the `LUNATIC_SEED` environment variable
+/// to it runs only the failing simulation again.
+pub fn check<T, F>(runs: usize, entry: F)
+where
+    T: Serialize + DeserializeOwned,
+    F: Fn(Mailbox<T>) + Clone + Send + 'static,
+{
+    let seeds: Vec<u64> = match env::var("LUNATIC_SEED") {
+        Ok(_) => vec![Simulation::from_env().seed()],
+        Err(_) => (0..runs).map(|_| random_seed()).collect(),
+    };
+    for seed in seeds {
+        if let Err(error) = Simulation::new(seed).run(entry.clone()) {
+            panic!(
+                "Simulation failed with seed {}: {}. Replay it with LUNATIC_SEED={}",
+                seed, error, seed
+            );
+        }
+    }
+}
+
+fn random_seed() -> u64 {
+    // Seeds only need to differ between runs, the clock is good enough for that.
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_nanos() as u64)
+        .unwrap_or_default();
+    let count = SEEDS.with(|seeds| {
+        seeds.set(seeds.get() + 1);
+        seeds.get()
+    });
+    nanos ^ count.wrapping_mul(0x9e37_79b9_7f4a_7c15)
+}
+
+thread_local! {
+    // Keeps seeds created in the same clock tick apart.
+    static SEEDS: std::cell::Cell<u64> = const { std::cell::Cell::new(0) };
+}
+
+/// Reasons for a simulation to fail.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum SimulationError {
+    #[error("Root process panicked: {0}")]
+    Panic(String),
+    #[error("Root process was killed")]
+    Killed,
+    #[error("All processes are waiting without a timeout")]
+    Deadlock,
+    #[error("Step limit exceeded")]
+    StepLimit,
+}
@@ -0,0 +1,234 @@
+/*! Supervisors that restart failed processes */
+
+use std::{
+    collections::VecDeque,
+    ops::Range,
+    time::{Duration, Instant},
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    error::LunaticError,
+    host_api,
+    mailbox::{ExitReason, Mailbox, Message, TransformMailbox},
+    process::{spawn_, Context, Process},
+    tag::Tag,
+};
+
+/// Decides which children are restarted when one of them fails.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// Only the failed child is restarted.
+    OneForOne,
+    /// All children are terminated and restarted if one of them fails.
+    OneForAll,
+    /// The failed child and all children that were started after it are terminated and
+    /// restarted.
+    RestForOne,
+}
+
+/// Describes how a child process is started.
+///
+/// Children are started from a function, the same way [`process::spawn`](crate::process::spawn)
+/// and [`process::spawn_with`](crate::process::spawn_with) start them. Because a child can be
+/// restarted many times, the context needs to be [`Clone`].
+pub struct ChildSpec {
+    start: Box<dyn Fn(Tag) -> Result<Process<()>, LunaticError>>,
+}
+
+impl ChildSpec {
+    /// Creates a child specification from a function.
+    pub fn new<T>(function: fn(Mailbox<T>)) -> Self
+    where
+        T: Serialize + DeserializeOwned + 'static,
+    {
+        let start = move |tag| {
+            let proc = spawn_(None, Some(tag), Context::<(), _>::Without(function))?;
+            Ok(proc.cast())
+        };
+        Self {
+            start: Box::new(start),
+        }
+    }
+
+    /// Creates a child specification from a function and context.
+    ///
+    /// Every time the child is (re)started it receives a clone of `context`.
+    pub fn with<C, T>(context: C, function: fn(C, Mailbox<T>)) -> Self
+    where
+        C: Serialize + DeserializeOwned + Clone + 'static,
+        T: Serialize + DeserializeOwned + 'static,
+    {
+        let start = move |tag| {
+            let proc = spawn_(None, Some(tag), Context::With(function, context.clone()))?;
+            Ok(proc.cast())
+        };
+        Self {
+            start: Box::new(start),
+        }
+    }
+}
+
+/// Starts a group of linked children and restarts them if they fail.
+///
+/// Every child is spawned with a unique [`Tag`]. When a link signal arrives, the tag is used to
+/// find out which child died and the [`Strategy`] decides what children are restarted. If more
+/// than `max_restarts` restarts happen inside of `period`, the supervisor gives up, terminates
+/// the remaining children and returns from [`supervise`](Supervisor::supervise).
+///
+/// Children that finish normally are not restarted on their own, only together with a failed one
+/// if the strategy requires it.
+///
+/// # Example
+///
+/// ```no_run
+/// use lunatic::{
+///     supervisor::{ChildSpec, Strategy, Supervisor},
+///     Mailbox,
+/// };
+///
+/// #[lunatic::main]
+/// fn main(mailbox: Mailbox<()>) {
+///     let mut supervisor = Supervisor::new(Strategy::OneForOne);
+///     supervisor.add_child(ChildSpec::new(worker));
+///     supervisor.add_child(ChildSpec::with(100, counter));
+///     let error = supervisor.supervise(mailbox);
+///     println!("Supervisor stopped: {}", error);
+/// }
+///
+/// fn worker(_: Mailbox<()>) {}
+///
+/// fn counter(_start: u64, _: Mailbox<()>) {}
+/// ```
+pub struct Supervisor {
+    strategy: Strategy,
+    max_restarts: usize,
+    period: Duration,
+    children: Vec<ChildSpec>,
+}
+
+impl Supervisor {
+    /// Creates a new supervisor without children.
+    ///
+    /// By default the supervisor gives up if more than 3 restarts happen inside of 5 seconds.
+    pub fn new(strategy: Strategy) -> Self {
+        Self {
+            strategy,
+            max_restarts: 3,
+            period: Duration::from_secs(5),
+            children: Vec::new(),
+        }
+    }
+
+    /// Sets the maximum number of restarts that are allowed inside of `period`.
+    pub fn set_intensity(&mut self, max_restarts: usize, period: Duration) {
+        self.max_restarts = max_restarts;
+        self.period = period;
+    }
+
+    /// Adds a child to the supervisor.
+    ///
+    /// Children are started in the order they were added and terminated in the reverse order.
+    pub fn add_child(&mut self, child: ChildSpec) {
+        self.children.push(child);
+    }
+
+    /// Starts all children and supervises them.
+    ///
+    /// The supervisor takes over the mailbox of the current process and blocks it. Regular
+    /// messages arriving to the mailbox are ignored. This function only returns if a child can't
+    /// be started or the restart intensity is exceeded. All remaining children are terminated
+    /// before returning.
+    pub fn supervise<P, M>(self, mailbox: M) -> SupervisorError
+    where
+        P: Serialize + DeserializeOwned + 'static,
+        M: TransformMailbox<P>,
+    {
+        let mailbox = mailbox.catch_link_panic();
+        let mut running: Vec<Option<(Tag, Process<()>)>> =
+            self.children.iter().map(|_| None).collect();
+        if let Err(error) = self.start(&mut running, 0..self.children.len()) {
+            self.terminate(&mut running, 0..self.children.len());
+            return error;
+        }
+
+        let mut restarts: VecDeque<Instant> = VecDeque::new();
+        loop {
+            let (tag, reason) = match mailbox.receive() {
+                Message::Signal(tag, reason) => (tag, reason),
+                Message::Normal(_) | Message::Down { .. } => continue,
+            };
+            // Signals from children that were already terminated by the supervisor are ignored.
+            let index = match running
+                .iter()
+                .position(|child| matches!(child, Some((child_tag, _)) if *child_tag == tag))
+            {
+                Some(index) => index,
+                None => continue,
+            };
+            running[index] = None;
+            if reason == ExitReason::Normal {
+                continue;
+            }
+
+            let now = host_api::now();
+            restarts.push_back(now);
+            while let Some(first) = restarts.front() {
+                if now.duration_since(*first) > self.period {
+                    restarts.pop_front();
+                } else {
+                    break;
+                }
+            }
+            if restarts.len() > self.max_restarts {
+                self.terminate(&mut running, 0..self.children.len());
+                return SupervisorError::RestartIntensityExceeded;
+            }
+
+            let restart = match self.strategy {
+                Strategy::OneForOne => index..index + 1,
+                Strategy::OneForAll => 0..self.children.len(),
+                Strategy::RestForOne => index..self.children.len(),
+            };
+            self.terminate(&mut running, restart.clone());
+            if let Err(error) = self.start(&mut running, restart) {
+                self.terminate(&mut running, 0..self.children.len());
+                return error;
+            }
+        }
+    }
+
+    fn start(
+        &self,
+        running: &mut [Option<(Tag, Process<()>)>],
+        range: Range<usize>,
+    ) -> Result<(), SupervisorError> {
+        for index in range {
+            let tag = Tag::new();
+            let proc = (self.children[index].start)(tag)?;
+            running[index] = Some((tag, proc));
+        }
+        Ok(())
+    }
+
+    fn terminate(&self, running: &mut [Option<(Tag, Process<()>)>], range: Range<usize>) {
+        for index in range.rev() {
+            if let Some((_, proc)) = running[index].take() {
+                // Unlink first, so that the supervisor doesn't get notified about the kill.
+                proc.unlink();
+                proc.kill();
+            }
+        }
+    }
+}
+
+/// Returned from [`Supervisor::supervise`] to indicate why the supervisor stopped.
+#[derive(Error, Debug)]
+pub enum SupervisorError {
+    #[error("Failed to start child: {0}")]
+    StartFailed(#[from] LunaticError),
+    #[error("Restart intensity exceeded")]
+    RestartIntensityExceeded,
+}
@@ -12,15 +12,19 @@ impl Tag {
     }
 }
 
-static mut COUNTER: i64 = 0;
+thread_local! {
+    // Each process runs on a single thread, this also keeps the counter per process when the
+    // mock host runs processes on native threads.
+    static COUNTER: std::cell::Cell<i64> = const { std::cell::Cell::new(0) };
+}
 
 impl Tag {
     // Returns a unique tag inside of the process.
     pub fn new() -> Tag {
-        unsafe {
-            COUNTER += 1;
-            Tag(COUNTER)
-        }
+        COUNTER.with(|counter| {
+            counter.set(counter.get() + 1);
+            Tag(counter.get())
+        })
     }
 }
 
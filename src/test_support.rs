@@ -0,0 +1,11 @@
+/*! Helpers for the tests of this crate, not part of the public API */
+
+use crate::mock;
+
+/// Returns how many times the mock host looked up the name `name`, or the name of the IP address
+/// `name`, e.g. to check that a [`Resolver`](crate::net::Resolver) caches results.
+///
+/// Lookups of every process are counted, whether it runs inside of a simulation or not.
+pub fn host_lookups(name: &str) -> u64 {
+    mock::networking::lookup_count(name)
+}
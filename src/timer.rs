@@ -0,0 +1,106 @@
+/*! Timers that deliver messages in the future */
+
+use std::time::Duration;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    codec::Codec,
+    error::LunaticError,
+    host_api,
+    mailbox::{self, Mailbox, Message, ReceiveError, TransformMailbox},
+    process::{self, Process},
+    request::Request,
+    tag::Tag,
+};
+
+/// A reference to a message scheduled with [`Process::send_after`].
+///
+/// Dropping the reference doesn't cancel the timer.
+#[derive(Debug)]
+pub struct TimerRef {
+    id: u64,
+}
+
+impl TimerRef {
+    pub(crate) fn from(id: u64) -> Self {
+        TimerRef { id }
+    }
+
+    /// Cancels the timer.
+    ///
+    /// Returns `false` if the message was already delivered.
+    pub fn cancel(self) -> bool {
+        unsafe { host_api::timer::cancel_timer(self.id) == 1 }
+    }
+}
+
+/// A periodic timer started with [`Process::interval`].
+///
+/// Dropping the interval doesn't stop it, it needs to be cancelled with
+/// [`cancel`](Interval::cancel).
+#[derive(Debug)]
+pub struct Interval {
+    tag: Tag,
+    ticker: Process<Request<(), ()>>,
+}
+
+impl Interval {
+    /// Returns the tag attached to every tick.
+    pub fn tag(&self) -> Tag {
+        self.tag
+    }
+
+    /// Stops the interval.
+    ///
+    /// No more ticks arrive after this call returns. Ticks that were sent before and are still
+    /// waiting in the mailbox of the current process are dropped.
+    pub fn cancel(self) {
+        // The ticker answers right before it stops, so every tick it sent already arrived. If it
+        // stopped on its own, because the receiver died, the request fails right away.
+        let _ = self.ticker.request(());
+        mailbox::drop_tagged(self.tag);
+    }
+}
+
+pub(crate) fn start_interval<T, C>(
+    target: &Process<T, C>,
+    message: T,
+    period: Duration,
+) -> Result<Interval, LunaticError>
+where
+    T: Serialize + DeserializeOwned + Clone,
+    C: Codec,
+{
+    let tag = Tag::new();
+    let context = (target.clone(), message, tag, period);
+    let ticker = process::spawn_with(context, ticker::<T, C>)?;
+    Ok(Interval { tag, ticker })
+}
+
+// Entry point of the process driving an interval.
+fn ticker<T, C>(
+    (target, message, tag, period): (Process<T, C>, T, Tag, Duration),
+    mailbox: Mailbox<Request<(), ()>>,
+) where
+    T: Serialize + DeserializeOwned + Clone,
+    C: Codec,
+{
+    // The down notification of the receiver is used to stop ticking.
+    let mailbox = mailbox.catch_link_panic();
+    target.monitor(&mailbox);
+    // Ticks are scheduled from the start time, so that slow sends don't make the interval drift.
+    let mut next = host_api::now() + period;
+    loop {
+        let wait = next.saturating_duration_since(host_api::now());
+        match mailbox.receive_timeout(wait) {
+            Message::Normal(Err(ReceiveError::Timeout)) => {
+                target.tag_send(tag, message.clone());
+                next += period;
+            }
+            Message::Normal(Ok(cancel)) => return cancel.reply(()),
+            Message::Down { .. } => return,
+            _ => continue,
+        }
+    }
+}
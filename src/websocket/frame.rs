@@ -0,0 +1,162 @@
+use serde::{Deserialize, Serialize};
+
+use super::WebSocketError;
+
+// Largest payload of control frames.
+pub(crate) const MAX_CONTROL_PAYLOAD: usize = 125;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum OpCode {
+    Continue,
+    Text,
+    Binary,
+    Close,
+    Ping,
+    Pong,
+}
+
+impl OpCode {
+    fn from_bits(bits: u8) -> Option<Self> {
+        match bits {
+            0x0 => Some(OpCode::Continue),
+            0x1 => Some(OpCode::Text),
+            0x2 => Some(OpCode::Binary),
+            0x8 => Some(OpCode::Close),
+            0x9 => Some(OpCode::Ping),
+            0xA => Some(OpCode::Pong),
+            _ => None,
+        }
+    }
+
+    fn bits(self) -> u8 {
+        match self {
+            OpCode::Continue => 0x0,
+            OpCode::Text => 0x1,
+            OpCode::Binary => 0x2,
+            OpCode::Close => 0x8,
+            OpCode::Ping => 0x9,
+            OpCode::Pong => 0xA,
+        }
+    }
+
+    pub(crate) fn is_control(self) -> bool {
+        matches!(self, OpCode::Close | OpCode::Ping | OpCode::Pong)
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Frame {
+    pub(crate) fin: bool,
+    pub(crate) opcode: OpCode,
+    pub(crate) payload: Vec<u8>,
+}
+
+// Appends a frame to `buffer`. Clients need to mask every frame they send, servers never do.
+pub(crate) fn encode(
+    fin: bool,
+    opcode: OpCode,
+    payload: &[u8],
+    mask: Option<[u8; 4]>,
+    buffer: &mut Vec<u8>,
+) {
+    let fin_bit = if fin { 0x80 } else { 0 };
+    buffer.push(fin_bit | opcode.bits());
+    let mask_bit = if mask.is_some() { 0x80 } else { 0 };
+    match payload.len() {
+        len if len < 126 => buffer.push(mask_bit | len as u8),
+        len if len <= u16::MAX as usize => {
+            buffer.push(mask_bit | 126);
+            buffer.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            buffer.push(mask_bit | 127);
+            buffer.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    match mask {
+        Some(mask) => {
+            buffer.extend_from_slice(&mask);
+            let start = buffer.len();
+            buffer.extend_from_slice(payload);
+            apply_mask(&mut buffer[start..], mask);
+        }
+        None => buffer.extend_from_slice(payload),
+    }
+}
+
+// Removes the first frame from `buffer`, or returns `None` if it isn't complete yet.
+//
+// `masked` tells if the peer is a client and needs to mask its frames. Frames with a payload
+// larger than `max_size` are rejected before the payload is read.
+pub(crate) fn decode(
+    buffer: &mut Vec<u8>,
+    masked: bool,
+    max_size: usize,
+) -> Result<Option<Frame>, WebSocketError> {
+    if buffer.len() < 2 {
+        return Ok(None);
+    }
+    let fin = buffer[0] & 0x80 != 0;
+    if buffer[0] & 0x70 != 0 {
+        return Err(WebSocketError::Protocol("reserved bits are set"));
+    }
+    let opcode =
+        OpCode::from_bits(buffer[0] & 0x0F).ok_or(WebSocketError::Protocol("unknown opcode"))?;
+    if (buffer[1] & 0x80 != 0) != masked {
+        return Err(WebSocketError::Protocol(if masked {
+            "frame from the client is not masked"
+        } else {
+            "frame from the server is masked"
+        }));
+    }
+
+    let (len, mut offset) = match buffer[1] & 0x7F {
+        126 if buffer.len() < 4 => return Ok(None),
+        126 => (u16::from_be_bytes([buffer[2], buffer[3]]) as u64, 4),
+        127 if buffer.len() < 10 => return Ok(None),
+        127 => {
+            let mut len = [0; 8];
+            len.copy_from_slice(&buffer[2..10]);
+            (u64::from_be_bytes(len), 10)
+        }
+        len => (len as u64, 2),
+    };
+    if opcode.is_control() && (!fin || len > MAX_CONTROL_PAYLOAD as u64) {
+        return Err(WebSocketError::Protocol("invalid control frame"));
+    }
+    if len > max_size as u64 {
+        return Err(WebSocketError::TooLarge(len as usize));
+    }
+    let len = len as usize;
+
+    let mask = if masked {
+        if buffer.len() < offset + 4 {
+            return Ok(None);
+        }
+        let mut mask = [0; 4];
+        mask.copy_from_slice(&buffer[offset..offset + 4]);
+        offset += 4;
+        Some(mask)
+    } else {
+        None
+    };
+    if buffer.len() < offset + len {
+        return Ok(None);
+    }
+    let mut payload: Vec<u8> = buffer.drain(..offset + len).skip(offset).collect();
+    if let Some(mask) = mask {
+        apply_mask(&mut payload, mask);
+    }
+    Ok(Some(Frame {
+        fin,
+        opcode,
+        payload,
+    }))
+}
+
+// Masking and unmasking are the same operation.
+fn apply_mask(data: &mut [u8], mask: [u8; 4]) {
+    for (byte, mask) in data.iter_mut().zip(mask.iter().cycle()) {
+        *byte ^= mask;
+    }
+}
@@ -0,0 +1,582 @@
+/*! WebSocket connections over TCP, with a process per connection
+
+[`WebSocket`] implements the handshake and framing of RFC 6455 on top of a
+[`TcpStream`](crate::net::TcpStream). Servers [`accept`](WebSocket::accept) connections and
+clients [`connect`](WebSocket::connect) to a `ws://` URL. Messages that are split into fragments
+are joined together when they are received, pings are answered and the closing handshake is
+completed automatically.
+
+[`serve`] upgrades every accepted connection in a new process and calls a handler, that receives
+the incoming messages through its [`Mailbox`]. The handler is the only process writing to the
+connection, so it passes pings and close messages to [`WebSocket::answer`].
+
+```no_run
+use lunatic::{
+    http::Request,
+    net::TcpListener,
+    websocket::{self, Message, WebSocket},
+    Mailbox,
+};
+
+#[lunatic::main]
+fn main(_: Mailbox<()>) {
+    let listener = TcpListener::bind("127.0.0.1:8080").unwrap();
+    websocket::serve(&listener, echo).unwrap();
+}
+
+fn echo((mut socket, _): (WebSocket, Request), mailbox: Mailbox<Message>) {
+    while let Ok(message) = mailbox.receive() {
+        match message {
+            Message::Text(_) | Message::Binary(_) => socket.send(message).unwrap(),
+            Message::Close(_) => return socket.answer(&message).unwrap(),
+            _ => socket.answer(&message).unwrap(),
+        }
+    }
+}
+```
+*/
+
+mod frame;
+mod handshake;
+
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::Shutdown,
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    http::{Connection, HttpError, Method, Request, Response, Url, Version},
+    net::{TcpListener, TcpStream},
+    process, Mailbox,
+};
+use frame::{Frame, OpCode, MAX_CONTROL_PAYLOAD};
+
+// Largest accepted size of the HTTP head of a handshake.
+const MAX_HEAD_SIZE: usize = 16 * 1024;
+
+/// A message sent over a WebSocket connection.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    /// A UTF-8 text message.
+    Text(String),
+    /// A binary message.
+    Binary(Vec<u8>),
+    /// A ping with up to 125 bytes of data, that the peer answers with a pong.
+    Ping(Vec<u8>),
+    /// The answer to a ping, or an unsolicited heartbeat.
+    Pong(Vec<u8>),
+    /// Starts or completes the closing handshake.
+    Close(Option<CloseFrame>),
+}
+
+/// The status code and reason of a [`Message::Close`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CloseFrame {
+    /// The status code, e.g. `1000` for a normal closure.
+    pub code: u16,
+    /// A reason of up to 123 bytes.
+    pub reason: String,
+}
+
+/// Error returned when a handshake or sending or receiving a message fails.
+#[derive(Error, Debug)]
+pub enum WebSocketError {
+    #[error("i/o error: {0}")]
+    Io(#[from] io::Error),
+    #[error("http error: {0}")]
+    Http(#[from] HttpError),
+    #[error("handshake failed: {0}")]
+    Handshake(&'static str),
+    #[error("protocol violation: {0}")]
+    Protocol(&'static str),
+    #[error("message of {0} bytes exceeds the size limit")]
+    TooLarge(usize),
+    #[error("text message is not valid UTF-8")]
+    InvalidUtf8,
+    #[error("the connection is closed")]
+    Closed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+enum Role {
+    Client,
+    Server,
+}
+
+/// A WebSocket connection.
+///
+/// Received messages are limited to 16 MiB by default. A WebSocket can be sent to another
+/// process inside of a message, together with the data that was already read from the stream.
+#[derive(Serialize, Deserialize)]
+pub struct WebSocket {
+    stream: TcpStream,
+    role: Role,
+    max_message_size: usize,
+    fragment_size: Option<usize>,
+    // Data that was read from the stream, but doesn't form a complete frame yet.
+    buffer: Vec<u8>,
+    // The type and data of a fragmented message that is being received.
+    fragments: Option<(OpCode, Vec<u8>)>,
+    close_sent: bool,
+    close_received: bool,
+}
+
+impl WebSocket {
+    fn new(stream: TcpStream, role: Role, buffer: Vec<u8>) -> Self {
+        Self {
+            stream,
+            role,
+            max_message_size: 16 * 1024 * 1024,
+            fragment_size: None,
+            buffer,
+            fragments: None,
+            close_sent: false,
+            close_received: false,
+        }
+    }
+
+    /// Performs the server side of the handshake over `stream`.
+    ///
+    /// Returns the connection together with the upgrade request, so that the path and header
+    /// fields can be inspected. Invalid requests are answered with `400 Bad Request`, or with
+    /// `426 Upgrade Required` if the client speaks an unsupported version of the protocol.
+    pub fn accept(stream: TcpStream) -> Result<(Self, Request), WebSocketError> {
+        let peer_addr = stream.peer_addr().ok();
+        let read_timeout = stream.read_timeout();
+        let mut connection = Connection::new(stream, read_timeout);
+        let head = connection
+            .read_head(MAX_HEAD_SIZE, None)?
+            .ok_or_else(|| unexpected_eof("connection closed before the handshake"))?;
+        let mut request = Request::parse(&head)?;
+        if let Some(peer_addr) = peer_addr {
+            request.set_peer_addr(peer_addr);
+        }
+
+        if let Err(error) = check_request(&request) {
+            let mut response = Response::text(400, error);
+            if request.header("Sec-WebSocket-Version").map(str::trim) != Some("13") {
+                response.set_status(426);
+                response.set_header("Sec-WebSocket-Version", "13");
+            }
+            response.set_header("Connection", "close");
+            let _ = response.write(connection.stream(), false, false);
+            return Err(WebSocketError::Handshake(error));
+        }
+        let key = request.header("Sec-WebSocket-Key").unwrap_or_default();
+        let mut response = Response::new(101);
+        response.set_header("Upgrade", "websocket");
+        response.set_header("Connection", "Upgrade");
+        response.set_header("Sec-WebSocket-Accept", &handshake::accept_key(key));
+        response.write(connection.stream(), false, false)?;
+
+        let (stream, buffer) = connection.into_parts();
+        Ok((Self::new(stream, Role::Server, buffer), request))
+    }
+
+    /// Connects to a `ws://` URL and performs the client side of the handshake.
+    ///
+    /// Returns the connection together with the `101 Switching Protocols` response.
+    pub fn connect(url: &str) -> Result<(Self, Response), WebSocketError> {
+        let stream = TcpStream::connect(websocket_url(url)?.authority())?;
+        Self::client(stream, url)
+    }
+
+    /// Performs the client side of the handshake over an open `stream`.
+    ///
+    /// The `url` is only used to fill in the request target and the `Host` header field.
+    pub fn client(stream: TcpStream, url: &str) -> Result<(Self, Response), WebSocketError> {
+        let url = websocket_url(url)?;
+        let key = handshake::new_key();
+        let mut request = Request::new(Method::Get, &url.path);
+        request.set_header("Host", &url.host_header());
+        request.set_header("Upgrade", "websocket");
+        request.set_header("Connection", "Upgrade");
+        request.set_header("Sec-WebSocket-Key", &key);
+        request.set_header("Sec-WebSocket-Version", "13");
+
+        let read_timeout = stream.read_timeout();
+        let mut connection = Connection::new(stream, read_timeout);
+        request.write(connection.stream(), &url.path)?;
+        let head = connection
+            .read_head(MAX_HEAD_SIZE, None)?
+            .ok_or_else(|| unexpected_eof("connection closed during the handshake"))?;
+        let (response, _) = Response::parse(&head)?;
+
+        let headers = response.headers();
+        if response.status() != 101 {
+            return Err(WebSocketError::Handshake("server didn't switch protocols"));
+        }
+        if !headers.has_token("Upgrade", "websocket") || !headers.has_token("Connection", "upgrade")
+        {
+            return Err(WebSocketError::Handshake(
+                "server didn't upgrade to websocket",
+            ));
+        }
+        if headers.get("Sec-WebSocket-Accept").map(str::trim)
+            != Some(&handshake::accept_key(&key)[..])
+        {
+            return Err(WebSocketError::Handshake("invalid accept key"));
+        }
+
+        let (stream, buffer) = connection.into_parts();
+        Ok((Self::new(stream, Role::Client, buffer), response))
+    }
+
+    /// Sets the largest accepted size of a received message in bytes.
+    ///
+    /// Larger messages are rejected before they are read into memory and close the connection.
+    pub fn set_max_message_size(&mut self, size: usize) {
+        self.max_message_size = size;
+    }
+
+    /// Splits sent text and binary messages into fragments of at most `size` bytes.
+    ///
+    /// If the value specified is `None`, every message is sent in a single frame.
+    pub fn set_fragment_size(&mut self, size: Option<usize>) {
+        self.fragment_size = size;
+    }
+
+    /// Sends a message.
+    ///
+    /// After a [`Message::Close`] was sent, no other messages can be sent and
+    /// [`Closed`](WebSocketError::Closed) is returned. The payload of control messages is limited
+    /// to 125 bytes.
+    pub fn send(&mut self, message: Message) -> Result<(), WebSocketError> {
+        if self.close_sent {
+            return Err(WebSocketError::Closed);
+        }
+        match message {
+            Message::Text(text) => self.write_data(OpCode::Text, text.as_bytes()),
+            Message::Binary(data) => self.write_data(OpCode::Binary, &data),
+            Message::Ping(data) => self.write_frame(true, OpCode::Ping, &data),
+            Message::Pong(data) => self.write_frame(true, OpCode::Pong, &data),
+            Message::Close(frame) => {
+                let mut payload = Vec::new();
+                if let Some(frame) = frame {
+                    payload.extend_from_slice(&frame.code.to_be_bytes());
+                    payload.extend_from_slice(frame.reason.as_bytes());
+                }
+                self.write_frame(true, OpCode::Close, &payload)?;
+                self.close_sent = true;
+                // Nothing can be written after the close frame. The server closes the connection
+                // as soon as the client answered, so that the client doesn't wait in `TIME_WAIT`.
+                if self.role == Role::Server {
+                    let how = match self.close_received {
+                        true => Shutdown::Both,
+                        false => Shutdown::Write,
+                    };
+                    let _ = self.stream.shutdown(how);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Starts the closing handshake, see [`send`](WebSocket::send).
+    ///
+    /// Messages can still be received until the peer answers with its own close message.
+    pub fn close(&mut self, frame: Option<CloseFrame>) -> Result<(), WebSocketError> {
+        self.send(Message::Close(frame))
+    }
+
+    /// Receives the next message.
+    ///
+    /// Pings are answered with a pong before they are returned. A received close message is
+    /// answered with the same status code, unless a close message was already sent. Afterwards
+    /// [`Closed`](WebSocketError::Closed) is returned. If the peer violates the protocol, the
+    /// connection is closed with the matching status code and the error is returned.
+    pub fn receive(&mut self) -> Result<Message, WebSocketError> {
+        if self.close_received {
+            return Err(WebSocketError::Closed);
+        }
+        let message = match self.read_message() {
+            Ok(message) => message,
+            Err(error) => {
+                if let Some(code) = close_code(&error).filter(|_| !self.close_sent) {
+                    let reason = String::new();
+                    let _ = self.close(Some(CloseFrame { code, reason }));
+                }
+                return Err(error);
+            }
+        };
+        self.answer(&message)?;
+        Ok(message)
+    }
+
+    /// Answers a received ping or close message, like [`receive`](WebSocket::receive) does.
+    ///
+    /// This is needed if the messages are read by another process, e.g. by the handlers of
+    /// [`serve`]. Other messages are ignored. A close message with the status code `1006` means
+    /// that the connection is already gone, it's shut down without sending anything.
+    pub fn answer(&mut self, message: &Message) -> Result<(), WebSocketError> {
+        match message {
+            Message::Ping(data) if !self.close_sent => self.write_frame(true, OpCode::Pong, data),
+            Message::Close(frame) => {
+                self.close_received = true;
+                let gone = matches!(frame, Some(CloseFrame { code: 1006, .. }));
+                if self.close_sent || gone {
+                    self.close_sent = true;
+                    if self.role == Role::Server || gone {
+                        let _ = self.stream.shutdown(Shutdown::Both);
+                    }
+                } else {
+                    let reply = frame.as_ref().map(|frame| CloseFrame {
+                        code: frame.code,
+                        reason: String::new(),
+                    });
+                    // The peer may already be gone, it doesn't wait for the answer.
+                    let _ = self.close(reply);
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns a reference to the underlying stream.
+    pub fn get_ref(&self) -> &TcpStream {
+        &self.stream
+    }
+
+    /// Returns a mutable reference to the underlying stream, e.g. to set timeouts.
+    ///
+    /// Reading from or writing to the stream directly corrupts the connection.
+    pub fn get_mut(&mut self) -> &mut TcpStream {
+        &mut self.stream
+    }
+
+    // Returns a handle for the process that writes to the connection, while this one only reads
+    // from it.
+    fn writer(&self) -> Self {
+        Self {
+            stream: self.stream.clone(),
+            role: self.role,
+            max_message_size: self.max_message_size,
+            fragment_size: self.fragment_size,
+            buffer: Vec::new(),
+            fragments: None,
+            close_sent: self.close_sent,
+            close_received: self.close_received,
+        }
+    }
+
+    fn write_data(&mut self, opcode: OpCode, data: &[u8]) -> Result<(), WebSocketError> {
+        let size = self.fragment_size.unwrap_or(usize::MAX).max(1);
+        if data.len() <= size {
+            return self.write_frame(true, opcode, data);
+        }
+        let mask = self.mask();
+        let mut buffer = Vec::with_capacity(data.len() + 14 * (data.len() / size + 1));
+        let mut chunks = data.chunks(size).peekable();
+        let mut opcode = opcode;
+        while let Some(chunk) = chunks.next() {
+            frame::encode(chunks.peek().is_none(), opcode, chunk, mask, &mut buffer);
+            opcode = OpCode::Continue;
+        }
+        self.stream.write_all(&buffer)?;
+        Ok(())
+    }
+
+    fn write_frame(
+        &mut self,
+        fin: bool,
+        opcode: OpCode,
+        data: &[u8],
+    ) -> Result<(), WebSocketError> {
+        if opcode.is_control() && data.len() > MAX_CONTROL_PAYLOAD {
+            return Err(WebSocketError::TooLarge(data.len()));
+        }
+        let mut buffer = Vec::with_capacity(data.len() + 14);
+        frame::encode(fin, opcode, data, self.mask(), &mut buffer);
+        self.stream.write_all(&buffer)?;
+        Ok(())
+    }
+
+    // Clients mask their frames with a new random key each time, servers don't mask.
+    fn mask(&self) -> Option<[u8; 4]> {
+        match self.role {
+            Role::Client => Some((handshake::random() as u32).to_ne_bytes()),
+            Role::Server => None,
+        }
+    }
+
+    fn read_message(&mut self) -> Result<Message, WebSocketError> {
+        loop {
+            let Frame {
+                fin,
+                opcode,
+                payload,
+            } = self.read_frame()?;
+            match opcode {
+                OpCode::Continue => {
+                    let (_, data) = self
+                        .fragments
+                        .as_mut()
+                        .ok_or(WebSocketError::Protocol("unexpected continuation frame"))?;
+                    if data.len() + payload.len() > self.max_message_size {
+                        return Err(WebSocketError::TooLarge(data.len() + payload.len()));
+                    }
+                    data.extend_from_slice(&payload);
+                    if fin {
+                        let (opcode, data) = self.fragments.take().unwrap_or((opcode, Vec::new()));
+                        return data_message(opcode, data);
+                    }
+                }
+                OpCode::Text | OpCode::Binary => {
+                    if self.fragments.is_some() {
+                        return Err(WebSocketError::Protocol("expected continuation frame"));
+                    }
+                    if fin {
+                        return data_message(opcode, payload);
+                    }
+                    self.fragments = Some((opcode, payload));
+                }
+                OpCode::Ping => return Ok(Message::Ping(payload)),
+                OpCode::Pong => return Ok(Message::Pong(payload)),
+                OpCode::Close => return close_message(payload),
+            }
+        }
+    }
+
+    fn read_frame(&mut self) -> Result<Frame, WebSocketError> {
+        let masked = self.role == Role::Server;
+        let mut chunk = [0; 4096];
+        loop {
+            if let Some(frame) = frame::decode(&mut self.buffer, masked, self.max_message_size)? {
+                return Ok(frame);
+            }
+            let read = self.stream.read(&mut chunk)?;
+            if read == 0 {
+                return Err(unexpected_eof("connection closed without a close message").into());
+            }
+            self.buffer.extend_from_slice(&chunk[..read]);
+        }
+    }
+}
+
+// Returns the reason why `request` can't be upgraded, if any.
+fn check_request(request: &Request) -> Result<(), &'static str> {
+    let headers = request.headers();
+    if request.method() != &Method::Get || request.version() != Version::Http11 {
+        return Err("upgrade requires a HTTP/1.1 GET request");
+    }
+    if !headers.has_token("Upgrade", "websocket") || !headers.has_token("Connection", "upgrade") {
+        return Err("missing upgrade to websocket");
+    }
+    if headers.get("Sec-WebSocket-Version").map(str::trim) != Some("13") {
+        return Err("unsupported websocket version");
+    }
+    if !headers.contains("Sec-WebSocket-Key") {
+        return Err("missing websocket key");
+    }
+    Ok(())
+}
+
+fn websocket_url(url: &str) -> Result<Url, WebSocketError> {
+    match url.split_once("://") {
+        Some((scheme, rest)) if scheme.eq_ignore_ascii_case("ws") => {
+            Ok(Url::parse(&format!("http://{}", rest))?)
+        }
+        _ => Err(HttpError::InvalidUrl("unsupported scheme").into()),
+    }
+}
+
+// Returns the status code of the close message that answers a protocol violation.
+fn close_code(error: &WebSocketError) -> Option<u16> {
+    match error {
+        WebSocketError::Protocol(_) => Some(1002),
+        WebSocketError::InvalidUtf8 => Some(1007),
+        WebSocketError::TooLarge(_) => Some(1009),
+        _ => None,
+    }
+}
+
+fn unexpected_eof(message: &'static str) -> io::Error {
+    io::Error::new(ErrorKind::UnexpectedEof, message)
+}
+
+fn data_message(opcode: OpCode, data: Vec<u8>) -> Result<Message, WebSocketError> {
+    match opcode {
+        OpCode::Text => String::from_utf8(data)
+            .map(Message::Text)
+            .map_err(|_| WebSocketError::InvalidUtf8),
+        _ => Ok(Message::Binary(data)),
+    }
+}
+
+fn close_message(payload: Vec<u8>) -> Result<Message, WebSocketError> {
+    match payload.len() {
+        0 => Ok(Message::Close(None)),
+        1 => Err(WebSocketError::Protocol("invalid close frame")),
+        _ => {
+            let code = u16::from_be_bytes([payload[0], payload[1]]);
+            let reason = String::from_utf8(payload[2..].to_vec())
+                .map_err(|_| WebSocketError::InvalidUtf8)?;
+            Ok(Message::Close(Some(CloseFrame { code, reason })))
+        }
+    }
+}
+
+/// A function that handles an upgraded connection inside of its own process.
+///
+/// It receives the connection and the upgrade request, and the incoming messages arrive in its
+/// mailbox. The connection should only be used to send messages and to
+/// [`answer`](WebSocket::answer) pings and close messages.
+pub type Handler = fn((WebSocket, Request), Mailbox<Message>);
+
+/// Accepts connections from `listener` and upgrades each of them in a new process.
+///
+/// After a successful handshake `handler` is spawned in another process. The process that did
+/// the handshake keeps reading from the connection and forwards every message into the mailbox
+/// of the handler, but never writes to it. The handler is the only writer, it needs to
+/// [`answer`](WebSocket::answer) pings and the closing handshake.
+///
+/// The last forwarded message is always a [`Message::Close`]. If the peer violated the protocol,
+/// it carries the status code that the connection should be closed with, e.g. `1002`, and if
+/// the connection ended without a closing handshake the status code `1006`. In both cases the
+/// reason is the error. A failing handler also stops the reader, which closes the connection.
+///
+/// This function blocks the current process and only returns if accepting a connection or
+/// spawning a process fails. Handlers are function pointers, so they can only be used by
+/// processes spawned from the same module.
+pub fn serve(listener: &TcpListener, handler: Handler) -> io::Result<()> {
+    loop {
+        let (stream, _) = listener.accept()?;
+        process::spawn_with(Upgrade { stream, handler }, upgrade)?;
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Upgrade {
+    stream: TcpStream,
+    #[serde(with = "crate::process::function")]
+    handler: Handler,
+}
+
+// Entry point of the processes that upgrade connections and read from them.
+fn upgrade(Upgrade { stream, handler }: Upgrade, _: Mailbox<()>) {
+    let (mut socket, request) = match WebSocket::accept(stream) {
+        Ok(accepted) => accepted,
+        Err(_) => return,
+    };
+    let handler = match process::spawn_with((socket.writer(), request), handler) {
+        Ok(handler) => handler,
+        Err(_) => return,
+    };
+    handler.link();
+    // Only the handler writes to the connection, so messages are read without answering them.
+    loop {
+        match socket.read_message() {
+            Ok(message @ Message::Close(_)) => return handler.send(message),
+            Ok(message) => handler.send(message),
+            Err(error) => {
+                let code = close_code(&error).unwrap_or(1006);
+                let reason = error.to_string();
+                return handler.send(Message::Close(Some(CloseFrame { code, reason })));
+            }
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use lunatic::{
+    process::{self, Process},
+    AbstractProcess, Config, Environment, Mailbox,
+};
+
+struct Counter;
+
+impl AbstractProcess for Counter {
+    type Arg = u64;
+    type State = u64;
+    type Request = ();
+    type Response = u64;
+    type Message = u64;
+
+    fn init(start: u64) -> u64 {
+        start
+    }
+
+    fn handle_request(count: &mut u64, _: ()) -> u64 {
+        *count
+    }
+
+    fn handle_message(count: &mut u64, add: u64) {
+        *count += add;
+    }
+}
+
+#[lunatic::test]
+fn request_and_send(_: Mailbox<()>) {
+    let counter = Counter::start(5).unwrap();
+    assert_eq!(counter.request(()).unwrap(), 5);
+    counter.send(10);
+    counter.send(20);
+    assert_eq!(counter.request(()).unwrap(), 35);
+}
+
+#[lunatic::test]
+fn shared_handle(m: Mailbox<u64>) {
+    let counter = Counter::start(0).unwrap();
+    let this = process::this(&m);
+    process::spawn_with(
+        (counter.clone(), this),
+        |(counter, parent), _: Mailbox<()>| {
+            counter.send(1);
+            parent.send(counter.request(()).unwrap());
+        },
+    )
+    .unwrap();
+    assert_eq!(m.receive().unwrap(), 1);
+}
+
+#[lunatic::test]
+fn start_in_environment(_: Mailbox<()>) {
+    let mut config = Config::new(10_000_000, None);
+    config.allow_namespace("lunatic::");
+    config.allow_namespace("wasi_snapshot_preview1::");
+    let mut env = Environment::new(config).unwrap();
+    let module = env.add_this_module().unwrap();
+    let counter = Counter::start_in(&module, 5).unwrap();
+    assert_eq!(counter.request(()).unwrap(), 5);
+    counter.send(10);
+    assert_eq!(counter.request(()).unwrap(), 15);
+}
+
+struct Terminating;
+
+impl AbstractProcess for Terminating {
+    type Arg = Process<u64>;
+    type State = Process<u64>;
+    type Request = ();
+    type Response = ();
+    type Message = ();
+
+    fn init(parent: Process<u64>) -> Process<u64> {
+        parent
+    }
+
+    fn handle_request(_: &mut Process<u64>, _: ()) {}
+
+    fn terminate(parent: Process<u64>) {
+        parent.send(42);
+    }
+}
+
+#[lunatic::test]
+fn shutdown(m: Mailbox<u64>) {
+    let this = process::this(&m);
+    let server = Terminating::start(this).unwrap();
+    server.request(()).unwrap();
+    server.shutdown().unwrap();
+    assert_eq!(m.receive().unwrap(), 42);
+}
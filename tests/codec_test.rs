@@ -0,0 +1,50 @@
+use lunatic::{
+    codec::{Codec, MessagePack},
+    process::{self, Process},
+    Mailbox, Request,
+};
+
+// Receives a process handle and a number, and sends the number increased by one back.
+fn increment<C: Codec>(mailbox: Mailbox<(Process<u64, C>, u64)>) {
+    let mailbox = mailbox.with_codec::<C>();
+    let (parent, value) = mailbox.receive().unwrap();
+    parent.send(value + 1);
+}
+
+fn add<C: Codec>(mailbox: Mailbox<Request<(i32, i32), i32, C>>) {
+    let mailbox = mailbox.with_codec::<C>();
+    loop {
+        let request = mailbox.receive().unwrap();
+        let (a, b) = *request.data();
+        request.reply(a + b);
+    }
+}
+
+fn round_trip<C: Codec>(mailbox: Mailbox<u64>) {
+    let mailbox = mailbox.with_codec::<C>();
+    let this = process::this(&mailbox);
+    // Resources are sent next to the encoded message.
+    let child = process::spawn(increment::<C>).unwrap().with_codec::<C>();
+    child.send((this, 41));
+    assert_eq!(mailbox.receive().unwrap(), 42);
+    // Replies use the codec of the request.
+    let server = process::spawn(add::<C>).unwrap().with_codec::<C>();
+    assert_eq!(server.request((1, 2)).unwrap(), 3);
+}
+
+#[lunatic::test]
+fn message_pack(m: Mailbox<u64>) {
+    round_trip::<MessagePack>(m);
+}
+
+#[cfg(feature = "bincode")]
+#[lunatic::test]
+fn bincode(m: Mailbox<u64>) {
+    round_trip::<lunatic::codec::Bincode>(m);
+}
+
+#[cfg(feature = "json")]
+#[lunatic::test]
+fn json(m: Mailbox<u64>) {
+    round_trip::<lunatic::codec::Json>(m);
+}
@@ -0,0 +1,112 @@
+#![cfg(feature = "unstable-host")]
+
+use std::io::Write;
+
+use lunatic::{
+    framed::{FrameError, Framed, Framing, LengthDelimited, Lines},
+    net::{TcpListener, TcpStream},
+    process, Mailbox,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+// Returns both ends of a TCP connection.
+fn connection() -> (TcpStream, TcpStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (server, _) = listener.accept().unwrap();
+    (client, server)
+}
+
+#[lunatic::test]
+fn framed_length_delimited(_: Mailbox<()>) {
+    let (client, server) = connection();
+    let mut client = Framed::new(client);
+    let mut server = Framed::<_, Point>::new(server);
+
+    client.send(&Point { x: 1, y: 2 }).unwrap();
+    client.send(&Point { x: 3, y: 4 }).unwrap();
+    assert_eq!(server.receive().unwrap(), Some(Point { x: 1, y: 2 }));
+    assert_eq!(server.receive().unwrap(), Some(Point { x: 3, y: 4 }));
+
+    drop(client);
+    assert_eq!(server.receive().unwrap(), None);
+}
+
+#[lunatic::test]
+fn framed_max_frame_size(_: Mailbox<()>) {
+    let (client, server) = connection();
+    let mut client = Framed::new(client);
+    let mut server =
+        Framed::<_, String>::with_framing(server, LengthDelimited::with_max_frame_size(8));
+
+    client.send(&"a long message".to_string()).unwrap();
+    assert!(matches!(server.receive(), Err(FrameError::TooLarge(15))));
+
+    let mut small =
+        Framed::with_framing(server.into_inner(), LengthDelimited::with_max_frame_size(8));
+    assert!(matches!(
+        small.send(&"a long message".to_string()),
+        Err(FrameError::TooLarge(_))
+    ));
+}
+
+#[lunatic::test]
+fn framed_lines(_: Mailbox<()>) {
+    let (mut client, server) = connection();
+    let mut server = Framed::with_framing(server, Lines::new());
+
+    client.write_all(b"first\r\nsecond\nthi").unwrap();
+    assert_eq!(server.receive().unwrap().unwrap(), "first");
+    assert_eq!(server.receive().unwrap().unwrap(), "second");
+
+    server.send(&"reply".to_string()).unwrap();
+    assert!(matches!(
+        server.send(&"two\nlines".to_string()),
+        Err(FrameError::LineBreak)
+    ));
+    let mut client = Framed::with_framing(client, Lines::new());
+    assert_eq!(client.receive().unwrap().unwrap(), "reply");
+}
+
+#[lunatic::test]
+fn framed_max_line_length(_: Mailbox<()>) {
+    let mut lines = Lines::with_max_line_length(5);
+    // A read can end between the `\r` and `\n` of a line with the maximum length.
+    let mut buffer = b"hello\r".to_vec();
+    assert!(matches!(lines.decode(&mut buffer), Ok(None)));
+    buffer.push(b'\n');
+    assert_eq!(lines.decode(&mut buffer).unwrap().unwrap(), "hello");
+
+    let mut buffer = b"hello!\r".to_vec();
+    assert!(matches!(
+        lines.decode(&mut buffer),
+        Err(FrameError::TooLarge(7))
+    ));
+    let mut buffer = b"hello!\n".to_vec();
+    assert!(matches!(
+        lines.decode(&mut buffer),
+        Err(FrameError::TooLarge(6))
+    ));
+}
+
+#[lunatic::test]
+fn framed_reader_process(mailbox: Mailbox<Point>) {
+    let (client, server) = connection();
+    let mut client = Framed::new(client);
+    let mut server = Framed::<_, Point>::new(server);
+
+    // The second frame can already be buffered when the reader takes over.
+    client.send(&Point { x: 1, y: 2 }).unwrap();
+    client.send(&Point { x: 3, y: 4 }).unwrap();
+    assert_eq!(server.receive().unwrap(), Some(Point { x: 1, y: 2 }));
+    server.spawn_reader(process::this(&mailbox)).unwrap();
+    assert_eq!(mailbox.receive().unwrap(), Point { x: 3, y: 4 });
+    client.send(&Point { x: 5, y: 6 }).unwrap();
+    assert_eq!(mailbox.receive().unwrap(), Point { x: 5, y: 6 });
+}
@@ -0,0 +1,66 @@
+#![cfg(feature = "unstable-host")]
+
+use std::time::Duration;
+
+use lunatic::{
+    group::{self, Group},
+    process, Mailbox, ReceiveError, TransformMailbox,
+};
+
+#[lunatic::test]
+fn broadcast(m: Mailbox<u64>) {
+    group::start().unwrap();
+    let this = process::this(&m);
+    // Members forward everything they get back to the parent.
+    let group = Group::<u64>::new("numbers");
+    for _ in 0..3 {
+        let member = process::spawn_with(this.clone(), |parent, mailbox: Mailbox<u64>| loop {
+            parent.send(mailbox.receive().unwrap());
+        })
+        .unwrap();
+        group.join(&member).unwrap();
+    }
+    assert_eq!(group.members().unwrap().len(), 3);
+    group.broadcast(7).unwrap();
+    for _ in 0..3 {
+        assert_eq!(m.receive().unwrap(), 7);
+    }
+}
+
+#[lunatic::test]
+fn leave(m: Mailbox<u64>) {
+    group::start().unwrap();
+    let this = process::this(&m);
+    let group = Group::<u64>::new("leave");
+    group.join(&this).unwrap();
+    group.leave(&this).unwrap();
+    assert!(group.members().unwrap().is_empty());
+    group.broadcast(7).unwrap();
+    let result = m.receive_timeout(Duration::from_millis(10));
+    assert!(matches!(result, Err(ReceiveError::Timeout)));
+}
+
+#[lunatic::test]
+fn leave_on_death(m: Mailbox<u64>) {
+    group::start().unwrap();
+    let m = m.catch_link_panic();
+    let group = Group::<u64>::new("leave_on_death");
+    let member = process::spawn(|mailbox: Mailbox<u64>| {
+        mailbox.receive().unwrap();
+    })
+    .unwrap();
+    group.join(&member).unwrap();
+    member.monitor(&m);
+    group.broadcast(1).unwrap();
+    assert!(m.receive().is_down());
+    // The coordinator is notified about the death independently, ask until it removed the member.
+    let mut attempts = 0;
+    while !group.members().unwrap().is_empty() {
+        attempts += 1;
+        assert!(
+            attempts < 100,
+            "The dead member wasn't removed from the group"
+        );
+        process::sleep(10);
+    }
+}
@@ -0,0 +1,278 @@
+#![cfg(feature = "http")]
+
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::SocketAddr,
+    time::Duration,
+};
+
+use lunatic::{
+    http::{Client, HttpError, Response, Router, Server},
+    net::{TcpListener, TcpStream},
+    process, Mailbox,
+};
+
+// Starts a server in a new process and returns its address. The header and body timeouts and the
+// body size limit are only changed if they are given.
+fn start(timeout: Option<Duration>, max_body_size: Option<usize>) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    process::spawn_with(
+        (listener, timeout, max_body_size),
+        |(listener, timeout, max_body_size), _: Mailbox<()>| {
+            let mut server = Server::new(router());
+            if timeout.is_some() {
+                server.set_header_timeout(timeout);
+                server.set_body_timeout(timeout);
+            }
+            if let Some(size) = max_body_size {
+                server.set_max_body_size(size);
+            }
+            server.serve(&listener).unwrap();
+        },
+    )
+    .unwrap();
+    addr
+}
+
+fn router() -> Router {
+    let mut router = Router::new();
+    router.get("/hello/:name", |request| {
+        Response::text(200, &format!("Hello {}!", request.param("name").unwrap()))
+    });
+    router.post("/echo", |request| {
+        let mut response = Response::new(200);
+        response.set_body(request.into_body());
+        response
+    });
+    router.get("/chunks", |_| {
+        let mut response = Response::new(200);
+        response.set_chunks(vec![b"Hello ".to_vec(), b"chunks".to_vec()]);
+        response
+    });
+    router.get("/files/*path", |request| {
+        Response::text(200, request.param("path").unwrap())
+    });
+    router.get("/peer", |request| {
+        Response::text(200, &request.peer_addr().unwrap().to_string())
+    });
+    router.get("/redirect", |_| {
+        let mut response = Response::new(302);
+        response.set_header("Location", "hello/redirect");
+        response
+    });
+    router.get("/panic", |_| panic!("handler failed"));
+    router
+}
+
+// Reads a response with a `Content-Length` and returns the head and body.
+fn read_response(stream: &mut TcpStream) -> (String, Vec<u8>) {
+    let mut head = Vec::new();
+    let mut byte = [0];
+    while !head.ends_with(b"\r\n\r\n") {
+        assert_eq!(stream.read(&mut byte).unwrap(), 1, "connection closed");
+        head.push(byte[0]);
+    }
+    let head = String::from_utf8(head).unwrap();
+    let length = head
+        .lines()
+        .find_map(|line| line.strip_prefix("Content-Length: "))
+        .map_or(0, |length| length.parse().unwrap());
+    let mut body = vec![0; length];
+    stream.read_exact(&mut body).unwrap();
+    (head, body)
+}
+
+#[lunatic::test]
+fn http_routing(_: Mailbox<()>) {
+    let addr = start(None, None);
+    let mut stream = TcpStream::connect(addr).unwrap();
+
+    stream
+        .write_all(b"GET /hello/lunatic HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+    let (head, body) = read_response(&mut stream);
+    assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert_eq!(body, b"Hello lunatic!");
+
+    stream
+        .write_all(b"GET /files/a/b.txt?raw=1 HTTP/1.1\r\n\r\n")
+        .unwrap();
+    let (head, body) = read_response(&mut stream);
+    assert!(head.contains("Content-Type: text/plain; charset=utf-8\r\n"));
+    assert_eq!(body, b"a/b.txt");
+
+    // The connection is kept alive between requests.
+    stream
+        .write_all(b"GET /missing HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+    let (head, _) = read_response(&mut stream);
+    assert!(head.starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+    stream
+        .write_all(b"DELETE /echo HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+    let (head, _) = read_response(&mut stream);
+    assert!(head.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+    assert!(head.contains("Allow: POST\r\n"));
+
+    // `HEAD` requests are served by `GET` routes without a body.
+    stream
+        .write_all(b"HEAD /hello/lunatic HTTP/1.1\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.contains("Content-Length: 14\r\n"));
+    assert!(response.contains("Connection: close\r\n"));
+    assert!(response.ends_with("\r\n\r\n"));
+}
+
+#[lunatic::test]
+fn http_chunked(_: Mailbox<()>) {
+    let addr = start(None, None);
+    let mut stream = TcpStream::connect(addr).unwrap();
+
+    // Pipelined requests, the first one with a chunked body.
+    stream
+        .write_all(
+            b"POST /echo HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+              5\r\nHello\r\n7;ext=1\r\n chunks\r\n0\r\n\r\n\
+              GET /chunks HTTP/1.1\r\nConnection: close\r\n\r\n",
+        )
+        .unwrap();
+    let (_, body) = read_response(&mut stream);
+    assert_eq!(body, b"Hello chunks");
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.contains("Transfer-Encoding: chunked\r\n"));
+    assert!(response.ends_with("\r\n\r\n6\r\nHello \r\n6\r\nchunks\r\n0\r\n\r\n"));
+}
+
+#[lunatic::test]
+fn http_panic_closes_only_connection(_: Mailbox<()>) {
+    let addr = start(None, None);
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"GET /panic HTTP/1.1\r\n\r\n").unwrap();
+    let mut buffer = Vec::new();
+    let _ = stream.read_to_end(&mut buffer);
+    assert!(buffer.is_empty());
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .write_all(b"GET /hello/again HTTP/1.1\r\n\r\n")
+        .unwrap();
+    let (_, body) = read_response(&mut stream);
+    assert_eq!(body, b"Hello again!");
+}
+
+#[lunatic::test]
+fn http_header_injection(_: Mailbox<()>) {
+    let mut response = Response::new(200);
+    response.set_header("X-Name", "a\r\nSet-Cookie: evil=1");
+    response.set_header("X-Bad\r\nName:", "b\tc");
+    assert_eq!(response.header("X-BadName"), Some("b\tc"));
+    assert_eq!(response.header("X-Name"), Some("aSet-Cookie: evil=1"));
+    response.set_header("X-BadName", "d");
+    assert_eq!(response.header("X-BadName"), Some("d"));
+    assert_eq!(response.headers().len(), 2);
+    assert!(!response.headers().contains("Set-Cookie"));
+}
+
+#[lunatic::test]
+fn http_slow_client(_: Mailbox<()>) {
+    let addr = start(Some(Duration::from_millis(100)), None);
+
+    // The head never completes, so the server gives up after the header timeout.
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"GET /hello/slow HTTP/1.1\r\n").unwrap();
+    let (head, _) = read_response(&mut stream);
+    assert!(head.starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+    assert_eq!(stream.read(&mut [0; 16]).unwrap(), 0);
+
+    // The same goes for a body that never completes.
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .write_all(b"POST /echo HTTP/1.1\r\nContent-Length: 10\r\n\r\nHel")
+        .unwrap();
+    let (head, _) = read_response(&mut stream);
+    assert!(head.starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+    assert_eq!(stream.read(&mut [0; 16]).unwrap(), 0);
+}
+
+#[lunatic::test]
+fn http_limits(_: Mailbox<()>) {
+    let addr = start(None, Some(4));
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .write_all(b"POST /echo HTTP/1.1\r\nContent-Length: 5\r\n\r\nHello")
+        .unwrap();
+    let (head, _) = read_response(&mut stream);
+    assert!(head.starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+
+    // A chunk size that overflows together with the body read so far.
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let request = format!(
+        "POST /echo HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n1\r\nH\r\n{:x}\r\n",
+        usize::MAX
+    );
+    stream.write_all(request.as_bytes()).unwrap();
+    let (head, _) = read_response(&mut stream);
+    assert!(head.starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"NOT HTTP\r\n\r\n").unwrap();
+    let (head, _) = read_response(&mut stream);
+    assert!(head.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+}
+
+#[lunatic::test]
+fn http_client(_: Mailbox<()>) {
+    let addr = start(None, None);
+    let url = |path: &str| format!("http://{}{}", addr, path);
+    let mut client = Client::new();
+
+    let response = client.get(&url("/hello/client")).unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body(), b"Hello client!");
+
+    // Both requests use the same connection.
+    let first = client.get(&url("/peer")).unwrap().into_body();
+    let second = client.get(&url("/peer")).unwrap().into_body();
+    assert_eq!(first, second);
+
+    let response = client.post(&url("/echo"), "Hello echo").unwrap();
+    assert_eq!(response.body(), b"Hello echo");
+
+    let response = client.get(&url("/chunks")).unwrap();
+    assert_eq!(response.body(), b"Hello chunks");
+
+    let response = client.get(&url("/redirect")).unwrap();
+    assert_eq!(response.body(), b"Hello redirect!");
+    client.set_max_redirects(0);
+    let response = client.get(&url("/redirect")).unwrap();
+    assert_eq!(response.status(), 302);
+
+    assert!(matches!(
+        client.get("https://localhost/"),
+        Err(HttpError::InvalidUrl(_))
+    ));
+}
+
+#[lunatic::test]
+fn http_client_timeout(_: Mailbox<()>) {
+    // The connection is never accepted, so no response arrives.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut client = Client::new();
+    client.set_read_timeout(Some(Duration::from_millis(50)));
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    match client.get(&url) {
+        Err(HttpError::Io(error)) => assert!(matches!(
+            error.kind(),
+            ErrorKind::WouldBlock | ErrorKind::TimedOut
+        )),
+        other => panic!("expected a timeout, got {:?}", other),
+    }
+}
@@ -1,10 +1,26 @@
-use std::{process::exit, time::Duration};
+#[cfg(feature = "unstable-host")]
+use std::io::{Read, Write};
+#[cfg(not(feature = "mock"))]
+use std::process::exit;
+use std::time::Duration;
 
+#[cfg(feature = "unstable-host")]
+use lunatic::{
+    net::{TcpListener, TcpStream},
+    Config, Environment, ThisModule,
+};
 use lunatic::{
     process::{self, Process},
-    Mailbox, ReceiveError, Request,
+    spawn_closure, Mailbox, RawMessage, ReceiveError, Request, RequestError, Tag,
 };
 
+// `std::process::exit` would stop the whole mock host and can't be intercepted, because std
+// doesn't allow unwinding out of it. Panicking fails the process the same way.
+#[cfg(feature = "mock")]
+fn exit(_code: i32) -> ! {
+    panic!("exit")
+}
+
 #[lunatic::test]
 fn message_integer(m: Mailbox<u64>) {
     let this = process::this(&m);
@@ -72,6 +88,128 @@ fn message_resource(m: Mailbox<Proc>) {
     let _ = m.receive();
 }
 
+#[cfg(feature = "unstable-host")]
+#[lunatic::test]
+fn message_environment(m: Mailbox<u64>) {
+    let this = process::this(&m);
+    let mut config = Config::new(10_000_000, None);
+    config.allow_namespace("lunatic::");
+    config.allow_namespace("wasi_snapshot_preview1::");
+    let mut env = Environment::new(config).unwrap();
+    let module = env.add_this_module().unwrap();
+    // A spawner service gets the module and spawns processes into the environment.
+    let spawner = process::spawn_with(
+        this,
+        |parent, mailbox: Mailbox<(Environment, ThisModule)>| {
+            let (_env, module) = mailbox.receive().unwrap();
+            module
+                .spawn_with(parent, |parent, _: Mailbox<()>| parent.send(42))
+                .unwrap();
+        },
+    )
+    .unwrap();
+    spawner.send((env, module));
+    assert_eq!(m.receive().unwrap(), 42);
+}
+
+#[cfg(feature = "unstable-host")]
+#[lunatic::test]
+fn message_tcp_listener(m: Mailbox<u64>) {
+    let this = process::this(&m);
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let acceptor = process::spawn_with(this, |parent, mailbox: Mailbox<TcpListener>| {
+        let listener = mailbox.receive().unwrap();
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut buffer = [0; 1];
+        stream.read_exact(&mut buffer).unwrap();
+        parent.send(buffer[0] as u64);
+    })
+    .unwrap();
+    acceptor.send(listener);
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(&[7]).unwrap();
+    assert_eq!(m.receive().unwrap(), 7);
+}
+
+#[lunatic::test]
+fn message_closure(m: Mailbox<(u64, Proc)>) {
+    let parent = process::this(&m);
+    let value = 1337;
+    let empty_proc = Proc(process::spawn(|_: Mailbox<i32>| {}).unwrap());
+    let child = spawn_closure!([parent, value, empty_proc] |mailbox: Mailbox<u64>| {
+        let add = mailbox.receive().unwrap();
+        parent.send((value + add, empty_proc));
+    })
+    .unwrap();
+    child.send(1);
+    assert_eq!(m.receive().unwrap().0, 1338);
+}
+
+// The mailbox argument is a pattern, the binding doesn't need to be mutable.
+#[allow(unused_mut)]
+#[lunatic::test]
+fn message_closure_patterns(m: Mailbox<u64>) {
+    let parent = process::this(&m);
+    let child = spawn_closure!([parent] |mut mailbox: Mailbox<u64>| {
+        parent.send(mailbox.receive().unwrap() + 1);
+    })
+    .unwrap();
+    child.send(1);
+    assert_eq!(m.receive().unwrap(), 2);
+
+    let parent = process::this(&m);
+    spawn_closure!([parent] |_: Mailbox<()>| parent.send(3)).unwrap();
+    assert_eq!(m.receive().unwrap(), 3);
+}
+
+#[lunatic::test]
+fn selective_receive(m: Mailbox<u64>) {
+    let this = process::this(&m);
+    process::spawn_with(this, |parent, _: Mailbox<()>| {
+        parent.send(1);
+        parent.send(2);
+        parent.send(3);
+    })
+    .unwrap();
+    assert_eq!(m.receive_matching(|message| *message == 3).unwrap(), 3);
+    // Messages that didn't match stay in the mailbox in order.
+    assert_eq!(m.receive().unwrap(), 1);
+    assert_eq!(m.receive().unwrap(), 2);
+    let result = m.receive_matching_timeout(|message| *message == 4, Duration::from_millis(10));
+    assert!(matches!(result, Err(ReceiveError::Timeout)));
+}
+
+#[lunatic::test]
+fn selective_receive_other_type(m: Mailbox<u64>) {
+    let this = process::this(&m);
+    this.send(1);
+    this.send(2);
+    assert_eq!(m.receive_matching(|message| *message == 2).unwrap(), 2);
+    // The saved message is only returned to a mailbox of its own type.
+    let other = unsafe { Mailbox::<String>::new() };
+    process::this(&other).send("hello".to_string());
+    assert_eq!(other.receive().unwrap(), "hello");
+    let result = other.receive_matching_timeout(|_| true, Duration::from_millis(10));
+    assert!(matches!(result, Err(ReceiveError::Timeout)));
+    assert_eq!(m.receive().unwrap(), 1);
+}
+
+#[lunatic::test]
+fn receive_any_tag(m: Mailbox<u64>) {
+    let this = process::this(&m);
+    let (a, b, c) = (Tag::new(), Tag::new(), Tag::new());
+    process::spawn_with((this, a, b, c), |(parent, a, b, c), _: Mailbox<()>| {
+        parent.tag_send(a, 1);
+        parent.tag_send(b, 2);
+        parent.tag_send(c, 3);
+    })
+    .unwrap();
+    assert_eq!(m.receive_any_tag(&[c, b]).unwrap(), (2, b));
+    assert_eq!(m.tag_receive(c).unwrap(), 3);
+    assert_eq!(m.receive_with_tag().unwrap(), (1, a));
+}
+
 #[lunatic::test]
 fn request_reply(m: Mailbox<u64>) {
     // Spawn a server that fills our mailbox with u64 messages.
@@ -97,6 +235,68 @@ fn request_reply(m: Mailbox<u64>) {
     }
 }
 
+#[lunatic::test]
+fn request_timeout(_: Mailbox<()>) {
+    // A server that never replies.
+    let server = process::spawn(|mailbox: Mailbox<Request<(), ()>>| loop {
+        let _request = mailbox.receive();
+    })
+    .unwrap();
+    let result = server.request_timeout((), Duration::from_millis(10));
+    assert!(matches!(result, Err(RequestError::Timeout)));
+}
+
+#[lunatic::test]
+fn request_process_died(_: Mailbox<()>) {
+    // A server that dies while handling the request.
+    let server = process::spawn(|mailbox: Mailbox<Request<(), ()>>| {
+        let _request = mailbox.receive();
+        panic!("fail");
+    })
+    .unwrap();
+    let result = server.request(());
+    assert!(matches!(result, Err(RequestError::ProcessDied)));
+}
+
+#[lunatic::test]
+fn raw_bytes(m: Mailbox<()>) {
+    let this = process::this(&m);
+    let m = m.into_raw();
+    let tag = Tag::new();
+    process::spawn_with((this, tag), |(parent, tag), _: Mailbox<()>| {
+        parent.send_bytes(&[1, 2, 3]);
+        parent.tag_send_bytes(tag, &[4]);
+        parent.send_bytes(&[]);
+    })
+    .unwrap();
+    assert_eq!(m.tag_receive(tag).unwrap(), vec![4]);
+    let mut buffer = vec![9; 16];
+    m.receive_into(&mut buffer).unwrap();
+    assert_eq!(buffer, vec![1, 2, 3]);
+    m.receive_into(&mut buffer).unwrap();
+    assert!(buffer.is_empty());
+}
+
+#[lunatic::test]
+fn raw_message_resource(m: Mailbox<()>) {
+    let this = process::this(&m);
+    let m = m.into_raw();
+    process::spawn_with(this, |parent, mailbox: Mailbox<()>| {
+        let mut message = RawMessage::new(b"ping".to_vec());
+        let index = message.attach_process(process::this(&mailbox));
+        assert_eq!(index, 0);
+        parent.send_raw(message);
+        let mailbox = mailbox.into_raw();
+        assert_eq!(mailbox.receive().unwrap(), b"pong");
+        parent.send_bytes(b"done");
+    })
+    .unwrap();
+    assert_eq!(m.receive().unwrap(), b"ping");
+    let child: Process<()> = unsafe { m.take_process(0) };
+    child.send_bytes(b"pong");
+    assert_eq!(m.receive().unwrap(), b"done");
+}
+
 #[lunatic::test]
 fn timeout(m: Mailbox<u64>) {
     let result = m.receive_timeout(Duration::new(0, 1000));
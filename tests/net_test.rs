@@ -0,0 +1,206 @@
+#![cfg(feature = "unstable-host")]
+
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{Shutdown, SocketAddr},
+    time::Duration,
+};
+
+use lunatic::{
+    net::{ConnectError, TcpListener, TcpStream, ToSocketAddrs, UdpSocket},
+    process, Mailbox,
+};
+
+#[lunatic::test]
+fn tcp_addresses(_: Mailbox<()>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    assert_ne!(addr.port(), 0);
+    let client = TcpStream::connect(addr).unwrap();
+    let server = listener.incoming().next().unwrap().unwrap();
+    assert_eq!(client.peer_addr().unwrap(), addr);
+    assert_eq!(server.peer_addr().unwrap(), client.local_addr().unwrap());
+}
+
+#[lunatic::test]
+fn tcp_peek_shutdown(_: Mailbox<()>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (mut server, _) = listener.accept().unwrap();
+
+    client.write_all(b"ping").unwrap();
+    let mut buffer = [0; 4];
+    assert_eq!(server.peek(&mut buffer).unwrap(), 4);
+    server.read_exact(&mut buffer).unwrap();
+    assert_eq!(&buffer, b"ping");
+
+    // After shutting down the write half, the other side reads the end of the stream.
+    client.shutdown(Shutdown::Write).unwrap();
+    assert_eq!(server.read(&mut buffer).unwrap(), 0);
+}
+
+#[lunatic::test]
+fn tcp_options(_: Mailbox<()>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    stream.set_nodelay(true).unwrap();
+    assert!(stream.nodelay().unwrap());
+    stream.set_ttl(42).unwrap();
+    assert_eq!(stream.ttl().unwrap(), 42);
+    stream.set_keepalive(Some(Duration::from_secs(60))).unwrap();
+    assert_eq!(stream.keepalive().unwrap(), Some(Duration::from_secs(60)));
+    stream.set_keepalive(None).unwrap();
+    assert_eq!(stream.keepalive().unwrap(), None);
+}
+
+#[lunatic::test]
+fn tcp_error_kinds(_: Mailbox<()>) {
+    // Nothing listens on the port of a listener that was dropped.
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let error = TcpStream::connect(addr).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::ConnectionRefused);
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let error = TcpListener::bind(listener.local_addr().unwrap()).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::AddrInUse);
+
+    let mut stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    stream.set_read_timeout(Some(Duration::from_millis(10)));
+    let error = stream.read(&mut [0; 1]).unwrap_err();
+    assert!(matches!(
+        error.kind(),
+        ErrorKind::WouldBlock | ErrorKind::TimedOut
+    ));
+
+    // Timeouts below a millisecond are rounded up instead of blocking forever.
+    stream.set_read_timeout(Some(Duration::from_micros(100)));
+    assert_eq!(stream.read_timeout(), Some(Duration::from_millis(1)));
+    let error = stream.read(&mut [0; 1]).unwrap_err();
+    assert!(matches!(
+        error.kind(),
+        ErrorKind::WouldBlock | ErrorKind::TimedOut
+    ));
+}
+
+#[lunatic::test]
+fn tcp_connect_race(_: Mailbox<()>) {
+    let refused = |_| {
+        TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+    };
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    // The first address that accepts the connection wins.
+    let addrs = [refused(0), addr];
+    let stream = TcpStream::connect(&addrs[..]).unwrap();
+    assert_eq!(stream.peer_addr().unwrap(), addr);
+
+    // If no address accepts it, the error of every attempt is reported.
+    let addrs: Vec<_> = (0..3).map(refused).collect();
+    let error = TcpStream::connect(&addrs[..]).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::ConnectionRefused);
+    let error = error
+        .get_ref()
+        .and_then(|error| error.downcast_ref::<ConnectError>())
+        .unwrap();
+    assert_eq!(error.attempts().len(), 3);
+    for ((attempted, error), addr) in error.attempts().iter().zip(&addrs) {
+        assert_eq!(attempted, addr);
+        assert_eq!(error.kind(), ErrorKind::ConnectionRefused);
+    }
+}
+
+#[lunatic::test]
+fn tcp_connect_race_leftovers(m: Mailbox<u64>) {
+    let a = TcpListener::bind("127.0.0.1:0").unwrap();
+    let b = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addrs = [a.local_addr().unwrap(), b.local_addr().unwrap()];
+    TcpStream::connect(&addrs[..]).unwrap();
+
+    // Attempts that lost the race have time to finish, but their outcomes never reach the
+    // mailbox of the caller.
+    process::sleep(500);
+    process::this(&m).send(42);
+    assert_eq!(m.receive().unwrap(), 42);
+}
+
+#[lunatic::test]
+fn socket_addr_forms(_: Mailbox<()>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    TcpStream::connect(("localhost", addr.port())).unwrap();
+    TcpStream::connect(("127.0.0.1".to_string(), addr.port())).unwrap();
+    TcpStream::connect([addr]).unwrap();
+    let addrs = vec![addr];
+    TcpStream::connect(&addrs).unwrap();
+    TcpStream::connect(addrs).unwrap();
+
+    // The port of the tuple replaces the one of the resolved addresses.
+    let addrs: Vec<_> = ("::1", 80).to_socket_addrs().unwrap().collect();
+    assert_eq!(addrs, [SocketAddr::from(([0, 0, 0, 0, 0, 0, 0, 1], 80))]);
+    let mut addrs = ("localhost", 443).to_socket_addrs().unwrap().peekable();
+    assert!(addrs.peek().is_some());
+    assert!(addrs.all(|addr| addr.port() == 443));
+}
+
+#[lunatic::test]
+fn udp_send_receive(_: Mailbox<()>) {
+    let a = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let b = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let a_addr = a.local_addr().unwrap();
+    let b_addr = b.local_addr().unwrap();
+
+    a.send_to(b"hello", b_addr).unwrap();
+    let mut buffer = [0; 16];
+    // Peeking leaves the datagram in the queue.
+    let (read, from) = b.peek_from(&mut buffer).unwrap();
+    assert_eq!((&buffer[..read], from), (&b"hello"[..], a_addr));
+    let (read, from) = b.recv_from(&mut buffer).unwrap();
+    assert_eq!((&buffer[..read], from), (&b"hello"[..], a_addr));
+
+    b.connect(a_addr).unwrap();
+    assert_eq!(b.peer_addr().unwrap(), a_addr);
+    b.send(b"world").unwrap();
+    let read = a.recv(&mut buffer).unwrap();
+    assert_eq!(&buffer[..read], b"world");
+}
+
+#[lunatic::test]
+fn udp_options(_: Mailbox<()>) {
+    let mut socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.set_broadcast(true).unwrap();
+    assert!(socket.broadcast().unwrap());
+    socket.set_ttl(42).unwrap();
+    assert_eq!(socket.ttl().unwrap(), 42);
+
+    socket.set_read_timeout(Some(Duration::from_millis(10)));
+    assert_eq!(socket.read_timeout(), Some(Duration::from_millis(10)));
+    let mut buffer = [0; 16];
+    assert!(socket.recv_from(&mut buffer).is_err());
+}
+
+#[lunatic::test]
+fn udp_socket_message(m: Mailbox<u64>) {
+    let this = process::this(&m);
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = socket.local_addr().unwrap();
+    // The socket is moved to another process, that reports what it receives.
+    let receiver = process::spawn_with(this, |parent, mailbox: Mailbox<UdpSocket>| {
+        let socket = mailbox.receive().unwrap();
+        let mut buffer = [0; 1];
+        socket.recv(&mut buffer).unwrap();
+        parent.send(buffer[0] as u64);
+    })
+    .unwrap();
+    receiver.send(socket);
+
+    let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+    sender.send_to(&[9], addr).unwrap();
+    assert_eq!(m.receive().unwrap(), 9);
+}
@@ -0,0 +1,107 @@
+use std::time::Duration;
+
+use lunatic::{
+    pool::{Dispatch, Pool, PoolRef},
+    process::{self, Process},
+    AbstractProcess, Mailbox, RequestError,
+};
+
+struct Worker;
+
+impl AbstractProcess for Worker {
+    type Arg = ();
+    type State = u64;
+    type Request = bool;
+    type Response = u64;
+    type Message = ();
+
+    fn init(_: ()) -> u64 {
+        0
+    }
+
+    // Returns the number of requests handled by the worker or fails if asked to.
+    fn handle_request(count: &mut u64, fail: bool) -> u64 {
+        if fail {
+            panic!("fail");
+        }
+        *count += 1;
+        *count
+    }
+}
+
+#[lunatic::test]
+fn round_robin(_: Mailbox<()>) {
+    let pool = Pool::<Worker>::new(3, ()).start().unwrap();
+    let counts: Vec<u64> = (0..6).map(|_| pool.request(false).unwrap()).collect();
+    assert_eq!(counts, vec![1, 1, 1, 2, 2, 2]);
+}
+
+#[lunatic::test]
+fn least_busy(_: Mailbox<()>) {
+    let mut pool = Pool::<Worker>::new(2, ());
+    pool.set_dispatch(Dispatch::LeastBusy);
+    let pool = pool.start().unwrap();
+    // All workers are idle, so the first one is always picked.
+    assert_eq!(pool.request(false).unwrap(), 1);
+    assert_eq!(pool.request(false).unwrap(), 2);
+}
+
+#[lunatic::test]
+fn replace_dead_worker(_: Mailbox<()>) {
+    let pool = Pool::<Worker>::new(1, ()).start().unwrap();
+    assert_eq!(pool.request(false).unwrap(), 1);
+    let result = pool.request(true);
+    assert!(matches!(result, Err(RequestError::ProcessDied)));
+    // The replacement starts with a fresh state.
+    assert_eq!(pool.request(false).unwrap(), 1);
+}
+
+struct SlowWorker;
+
+impl AbstractProcess for SlowWorker {
+    type Arg = ();
+    type State = ();
+    type Request = ();
+    type Response = ();
+    type Message = ();
+
+    fn init(_: ()) {}
+
+    // Dies while other requests are queued up behind the first one.
+    fn handle_request(_: &mut (), _: ()) {
+        process::sleep(100);
+        panic!("fail");
+    }
+}
+
+#[lunatic::test]
+fn fail_requests_of_dead_worker(m: Mailbox<bool>) {
+    let pool = Pool::<SlowWorker>::new(1, ()).start().unwrap();
+    for _ in 0..2 {
+        let context = (process::this(&m), pool.clone());
+        process::spawn_with(
+            context,
+            |(parent, pool): (Process<bool>, PoolRef<SlowWorker>), _: Mailbox<()>| {
+                let result = pool.request(());
+                parent.send(matches!(result, Err(RequestError::ProcessDied)));
+            },
+        )
+        .unwrap();
+    }
+    assert!(m.receive().unwrap());
+    assert!(m.receive().unwrap());
+}
+
+#[lunatic::test]
+fn checkout_checkin(_: Mailbox<()>) {
+    let pool = Pool::<Worker>::new(1, ()).start().unwrap();
+    let worker = pool.checkout().unwrap().unwrap();
+    assert_eq!(worker.request(false).unwrap(), 1);
+    // The only worker is checked out, requests wait for it.
+    assert!(pool.checkout().unwrap().is_none());
+    let result = pool.request_timeout(false, Duration::from_millis(50));
+    assert!(matches!(result, Err(RequestError::Timeout)));
+    pool.checkin(worker);
+    // The waiting request is handled first.
+    assert_eq!(pool.request(false).unwrap(), 3);
+}
@@ -1,9 +1,22 @@
-use std::{num::Wrapping, ops::Add, process::exit};
+#[cfg(not(feature = "mock"))]
+use std::process::exit;
+#[cfg(feature = "unstable-host")]
+use std::time::Duration;
+use std::{num::Wrapping, ops::Add};
 
 use lunatic::{
     process::{self, Process},
     Config, Environment, Mailbox, Message,
 };
+#[cfg(feature = "unstable-host")]
+use lunatic::{ExitReason, ReceiveError, TransformMailbox};
+
+// `std::process::exit` would stop the whole mock host and can't be intercepted, because std
+// doesn't allow unwinding out of it. Panicking fails the process the same way.
+#[cfg(feature = "mock")]
+fn exit(_code: i32) -> ! {
+    panic!("exit")
+}
 
 #[lunatic::test]
 fn spawn_link(m: Mailbox<()>) {
@@ -12,7 +25,27 @@ fn spawn_link(m: Mailbox<()>) {
     assert!(link_mailbox.receive().is_signal());
 }
 
+#[cfg(feature = "unstable-host")]
 #[lunatic::test]
+fn link_exit_reason(m: Mailbox<()>) {
+    let (_child, tag, m) = process::spawn_link(m, |_: Mailbox<()>| panic!("Failed")).unwrap();
+    match m.receive() {
+        Message::Signal(signal_tag, ExitReason::Panic(message)) => {
+            assert_eq!(signal_tag, tag);
+            assert_eq!(message, "Failed");
+        }
+        _ => exit(1),
+    }
+    let (child, _, m) = process::spawn_link(m, |m: Mailbox<()>| m.receive().unwrap()).unwrap();
+    child.kill();
+    match m.receive() {
+        Message::Signal(_, reason) => assert_eq!(reason, ExitReason::Killed),
+        _ => exit(1),
+    }
+}
+
+#[lunatic::test]
+#[cfg_attr(feature = "mock", ignore = "the mock host doesn't enforce limits")]
 fn memory_limit(m: Mailbox<u64>) {
     let mut config = Config::new(1_200_000, None); // ~1Mb and unlimited CPU instructions
     config.allow_namespace("lunatic::");
@@ -36,6 +69,7 @@ fn allocate((parent, input): (Process<u64>, usize), _: Mailbox<()>) {
 }
 
 #[lunatic::test]
+#[cfg_attr(feature = "mock", ignore = "the mock host doesn't enforce limits")]
 fn compute_limit(m: Mailbox<u64>) {
     let mut config = Config::new(2_000_000, Some(1)); // ~2Mb and ~ 100k CPU instructions
     config.allow_namespace("lunatic::");
@@ -79,29 +113,90 @@ fn link_with_tags(m: Mailbox<u64>) {
 
     child2.send(());
     match m.receive() {
-        Message::Signal(tag) => assert_eq!(tag, tag2),
+        Message::Signal(tag, _) => assert_eq!(tag, tag2),
         _ => exit(1),
     }
 
     child4.send(());
     match m.receive() {
-        Message::Signal(tag) => assert_eq!(tag, tag4),
+        Message::Signal(tag, _) => assert_eq!(tag, tag4),
         _ => exit(1),
     }
 
     child3.send(());
     match m.receive() {
-        Message::Signal(tag) => assert_eq!(tag, tag3),
+        Message::Signal(tag, _) => assert_eq!(tag, tag3),
         _ => exit(1),
     }
 
     child1.send(());
     match m.receive() {
-        Message::Signal(tag) => assert_eq!(tag, tag1),
+        Message::Signal(tag, _) => assert_eq!(tag, tag1),
+        _ => exit(1),
+    }
+}
+
+#[cfg(feature = "unstable-host")]
+#[lunatic::test]
+fn monitor(m: Mailbox<()>) {
+    let m = m.catch_link_panic();
+    let child = process::spawn(|m: Mailbox<()>| {
+        m.receive().unwrap();
+        panic!("Failed");
+    })
+    .unwrap();
+    let monitor = child.monitor(&m);
+    child.send(());
+    match m.receive() {
+        Message::Down {
+            monitor: down_monitor,
+            process,
+            reason,
+        } => {
+            assert_eq!(down_monitor, monitor);
+            assert_eq!(process, child.id());
+            assert_eq!(reason, ExitReason::Panic("Failed".to_string()));
+        }
+        _ => exit(1),
+    }
+    // Monitored processes that finish normally also deliver a notification.
+    let child = process::spawn(|_: Mailbox<()>| {}).unwrap();
+    child.monitor(&m);
+    match m.receive() {
+        Message::Down { reason, .. } => assert_eq!(reason, ExitReason::Normal),
         _ => exit(1),
     }
 }
 
+#[cfg(feature = "unstable-host")]
+#[lunatic::test]
+fn monitor_then_panic_if_link_panics(m: Mailbox<u64>) {
+    let m = m.catch_link_panic();
+    let child = process::spawn(|_: Mailbox<()>| {}).unwrap();
+    child.monitor(&m);
+    assert!(m.receive().is_down());
+    // Monitoring the dead process again leaves a notification in the mailbox.
+    child.monitor(&m);
+    let m = m.panic_if_link_panics();
+    process::this(&m).send(1);
+    assert_eq!(m.receive().unwrap(), 1);
+}
+
+#[cfg(feature = "unstable-host")]
+#[lunatic::test]
+fn demonitor(m: Mailbox<()>) {
+    let m = m.catch_link_panic();
+    let child = process::spawn(|m: Mailbox<()>| m.receive().unwrap()).unwrap();
+    let monitor = child.monitor(&m);
+    child.demonitor(monitor);
+    child.kill();
+    let result = m.receive_timeout(Duration::from_millis(100));
+    assert!(matches!(
+        result,
+        Message::Normal(Err(ReceiveError::Timeout))
+    ));
+}
+
 fn fail_on_message(mailbox: Mailbox<()>) {
     let _ = mailbox.receive();
     exit(1);
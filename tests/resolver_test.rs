@@ -0,0 +1,82 @@
+#![cfg(feature = "unstable-host")]
+
+#[cfg(feature = "mock")]
+use std::net::IpAddr;
+use std::{io::ErrorKind, net::SocketAddr, time::Duration};
+
+#[cfg(feature = "mock")]
+use lunatic::{net::reverse_lookup, process, test_support::host_lookups};
+use lunatic::{
+    net::{resolve_all, Resolver, TcpListener, TcpStream},
+    Mailbox,
+};
+
+#[lunatic::test]
+fn resolver_lookup(_: Mailbox<()>) {
+    let mut resolver = Resolver::new();
+    resolver.set_negative_ttl(Duration::from_millis(200));
+    resolver.start().unwrap();
+
+    let addrs = resolve_all("localhost", 80).unwrap();
+    assert!(!addrs.is_empty());
+    assert!(addrs.iter().all(|addr| addr.ip().is_loopback()));
+    assert!(addrs.iter().all(|addr| addr.port() == 80));
+    let addrs = resolve_all("localhost:8080", 80).unwrap();
+    assert!(addrs.iter().all(|addr| addr.port() == 8080));
+    let loopback = SocketAddr::from(([0, 0, 0, 0, 0, 0, 0, 1], 80));
+    assert_eq!(resolve_all("[::1]", 80).unwrap(), vec![loopback]);
+    let error = resolve_all("localhost:http", 80).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidInput);
+
+    // Cached failures are returned the same way as the first one.
+    let name = "resolver-lookup.invalid";
+    let error = resolve_all(name, 80).unwrap_err();
+    let cached = resolve_all(name, 80).unwrap_err();
+    assert_eq!(error.kind(), cached.kind());
+
+    // Strings are resolved by the resolver.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    TcpStream::connect(format!("localhost:{}", port)).unwrap();
+}
+
+// Counting lookups on the host needs the mock.
+#[cfg(feature = "mock")]
+#[lunatic::test]
+fn resolver_cache(m: Mailbox<Vec<SocketAddr>>) {
+    // Tests share the registered resolver on the mock, so both start it with the same settings.
+    let mut resolver = Resolver::new();
+    resolver.set_negative_ttl(Duration::from_millis(200));
+    resolver.start().unwrap();
+
+    // Failures are cached for the negative TTL.
+    let name = "resolver-ttl.invalid";
+    resolve_all(name, 80).unwrap_err();
+    resolve_all(name, 80).unwrap_err();
+    assert_eq!(host_lookups(name), 1);
+    process::sleep(300);
+    resolve_all(name, 80).unwrap_err();
+    assert_eq!(host_lookups(name), 2);
+
+    // Concurrent lookups of the same name only ask the host once.
+    let name = "resolver-burst.invalid";
+    let this = process::this(&m);
+    for _ in 0..3 {
+        process::spawn_with(this.clone(), |parent, _: Mailbox<()>| {
+            let name = "resolver-burst.invalid";
+            parent.send(resolve_all(name, 443).unwrap_or_default());
+        })
+        .unwrap();
+    }
+    resolve_all(name, 443).unwrap_err();
+    for _ in 0..3 {
+        assert_eq!(m.receive().unwrap(), vec![]);
+    }
+    assert_eq!(host_lookups(name), 1);
+
+    // Reverse lookups are cached too, whether the address has a name or not.
+    let ip = IpAddr::from([127, 0, 0, 1]);
+    let name = reverse_lookup(ip).ok();
+    assert_eq!(reverse_lookup(ip).ok(), name);
+    assert_eq!(host_lookups("127.0.0.1"), 1);
+}
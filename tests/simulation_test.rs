@@ -0,0 +1,93 @@
+#![cfg(feature = "mock")]
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use lunatic::{
+    process,
+    simulation::{self, Simulation, SimulationError},
+    Mailbox, ReceiveError,
+};
+
+// Spawns a few processes that race to send their id and returns the order they arrived in.
+fn race(seed: u64) -> Vec<u64> {
+    let order = Arc::new(Mutex::new(Vec::new()));
+    let result = order.clone();
+    Simulation::new(seed)
+        .run(move |mailbox: Mailbox<u64>| {
+            let this = process::this(&mailbox);
+            for id in 0..5 {
+                process::spawn_with((this.clone(), id), |(parent, id), _: Mailbox<()>| {
+                    parent.send(id)
+                })
+                .unwrap();
+            }
+            for _ in 0..5 {
+                order.lock().unwrap().push(mailbox.receive().unwrap());
+            }
+        })
+        .unwrap();
+    let order = result.lock().unwrap().clone();
+    order
+}
+
+#[test]
+fn replay_seed() {
+    for seed in 0..10 {
+        assert_eq!(race(seed), race(seed));
+    }
+    // Different seeds explore different schedules.
+    let first = race(0);
+    assert!((1..20).any(|seed| race(seed) != first));
+}
+
+#[test]
+fn virtual_time() {
+    let start = Instant::now();
+    Simulation::new(1)
+        .run(|mailbox: Mailbox<()>| {
+            process::sleep(60_000);
+            let result = mailbox.receive_timeout(Duration::from_secs(3600));
+            assert!(matches!(result, Err(ReceiveError::Timeout)));
+            let this = process::this(&mailbox);
+            this.send_after((), Duration::from_secs(60));
+            mailbox.receive().unwrap();
+        })
+        .unwrap();
+    assert!(start.elapsed() < Duration::from_secs(10));
+}
+
+#[test]
+fn failures() {
+    let result = Simulation::new(1).run(|mailbox: Mailbox<()>| mailbox.receive().unwrap());
+    assert_eq!(result, Err(SimulationError::Deadlock));
+
+    let result = Simulation::new(1).run(|_: Mailbox<()>| panic!("Failed"));
+    assert_eq!(result, Err(SimulationError::Panic("Failed".to_string())));
+
+    let mut simulation = Simulation::new(1);
+    simulation.set_max_steps(100);
+    let result = simulation.run(|mailbox: Mailbox<()>| {
+        let this = process::this(&mailbox);
+        loop {
+            this.send(());
+            mailbox.receive().unwrap();
+        }
+    });
+    assert_eq!(result, Err(SimulationError::StepLimit));
+}
+
+#[test]
+fn check_links() {
+    simulation::check(20, |mailbox: Mailbox<()>| {
+        let (child, tag, mailbox) =
+            process::spawn_link(mailbox, |m: Mailbox<()>| m.receive().unwrap()).unwrap();
+        child.kill();
+        match mailbox.receive() {
+            lunatic::Message::Signal(signal_tag, _) => assert_eq!(signal_tag, tag),
+            _ => panic!("Expected a signal"),
+        }
+    });
+}
@@ -0,0 +1,101 @@
+#![cfg(feature = "unstable-host")]
+
+use std::time::Duration;
+
+use lunatic::{
+    process::{self, Process},
+    supervisor::{ChildSpec, Strategy, Supervisor, SupervisorError},
+    Mailbox,
+};
+
+#[lunatic::test]
+fn restart_intensity(m: Mailbox<u64>) {
+    let this = process::this(&m);
+    process::spawn_with(this, |parent, mailbox: Mailbox<()>| {
+        let mut supervisor = Supervisor::new(Strategy::OneForOne);
+        supervisor.add_child(ChildSpec::with(parent.clone(), report_and_fail));
+        match supervisor.supervise(mailbox) {
+            SupervisorError::RestartIntensityExceeded => parent.send(0),
+            SupervisorError::StartFailed(_) => panic!("child failed to start"),
+        }
+    })
+    .unwrap();
+    // The first start and 3 restarts.
+    for _ in 0..4 {
+        assert_eq!(m.receive().unwrap(), 1);
+    }
+    assert_eq!(m.receive().unwrap(), 0);
+}
+
+fn report_and_fail(parent: Process<u64>, _: Mailbox<()>) {
+    parent.send(1);
+    panic!("fail");
+}
+
+#[lunatic::test]
+fn normal_exit_is_not_restarted(m: Mailbox<u64>) {
+    let this = process::this(&m);
+    process::spawn_with(this, |parent, mailbox: Mailbox<()>| {
+        let mut supervisor = Supervisor::new(Strategy::OneForOne);
+        supervisor.add_child(ChildSpec::with(
+            parent,
+            |parent: Process<u64>, _: Mailbox<()>| parent.send(1),
+        ));
+        supervisor.supervise(mailbox);
+    })
+    .unwrap();
+    assert_eq!(m.receive().unwrap(), 1);
+    assert!(m.receive_timeout(Duration::from_millis(100)).is_err());
+}
+
+#[lunatic::test]
+fn one_for_all(m: Mailbox<(u64, Process<()>)>) {
+    start_supervisor(&m, Strategy::OneForAll, 2);
+    let children = receive_starts(&m, 2);
+    children[1].1.send(());
+    // Both children are restarted.
+    assert_eq!(ids(&receive_starts(&m, 2)), vec![0, 1]);
+}
+
+#[lunatic::test]
+fn rest_for_one(m: Mailbox<(u64, Process<()>)>) {
+    start_supervisor(&m, Strategy::RestForOne, 3);
+    let children = receive_starts(&m, 3);
+    children[1].1.send(());
+    // Only the failed child and the one started after it are restarted.
+    assert_eq!(ids(&receive_starts(&m, 2)), vec![1, 2]);
+    assert!(m.receive_timeout(Duration::from_millis(100)).is_err());
+}
+
+// Starts a supervisor with `count` children that fail when they receive a message.
+fn start_supervisor(m: &Mailbox<(u64, Process<()>)>, strategy: Strategy, count: u64) {
+    let this = process::this(m);
+    process::spawn_with(
+        (this, strategy, count),
+        |(parent, strategy, count), mailbox: Mailbox<()>| {
+            let mut supervisor = Supervisor::new(strategy);
+            for id in 0..count {
+                supervisor.add_child(ChildSpec::with((parent.clone(), id), report_and_wait));
+            }
+            supervisor.supervise(mailbox);
+        },
+    )
+    .unwrap();
+}
+
+fn report_and_wait((parent, id): (Process<(u64, Process<()>)>, u64), mailbox: Mailbox<()>) {
+    parent.send((id, process::this(&mailbox)));
+    let _ = mailbox.receive();
+    panic!("fail");
+}
+
+// Receives the start reports of `count` children, ordered by their ids.
+fn receive_starts(m: &Mailbox<(u64, Process<()>)>, count: usize) -> Vec<(u64, Process<()>)> {
+    let mut started: Vec<_> = (0..count).map(|_| m.receive().unwrap()).collect();
+    started.sort_by_key(|(id, _)| *id);
+    started
+}
+
+fn ids(started: &[(u64, Process<()>)]) -> Vec<u64> {
+    started.iter().map(|(id, _)| *id).collect()
+}
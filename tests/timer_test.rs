@@ -0,0 +1,36 @@
+#![cfg(feature = "unstable-host")]
+
+use std::time::Duration;
+
+use lunatic::{process, Mailbox, ReceiveError};
+
+#[lunatic::test]
+fn send_after(m: Mailbox<u64>) {
+    let this = process::this(&m);
+    this.send_after(1, Duration::from_millis(20));
+    this.send(2);
+    // The delayed message arrives after the one sent right away.
+    assert_eq!(m.receive().unwrap(), 2);
+    assert_eq!(m.receive().unwrap(), 1);
+}
+
+#[lunatic::test]
+fn cancel_send_after(m: Mailbox<u64>) {
+    let this = process::this(&m);
+    let timer = this.send_after(1, Duration::from_millis(20));
+    assert!(timer.cancel());
+    let result = m.receive_timeout(Duration::from_millis(50));
+    assert!(matches!(result, Err(ReceiveError::Timeout)));
+}
+
+#[lunatic::test]
+fn interval(m: Mailbox<u64>) {
+    let this = process::this(&m);
+    let interval = this.interval(7, Duration::from_millis(5)).unwrap();
+    for _ in 0..3 {
+        assert_eq!(m.tag_receive(interval.tag()).unwrap(), 7);
+    }
+    interval.cancel();
+    let result = m.receive_timeout(Duration::from_millis(20));
+    assert!(matches!(result, Err(ReceiveError::Timeout)));
+}
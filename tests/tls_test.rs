@@ -0,0 +1,84 @@
+#![cfg(feature = "tls")]
+
+use std::io::{ErrorKind, Read, Write};
+
+use lunatic::{
+    net::{TcpListener, TcpStream, TlsAcceptor, TlsConnector, TlsStream},
+    process, Mailbox,
+};
+
+// A self-signed certificate for `localhost` and `127.0.0.1`.
+const CERT: &[u8] = include_bytes!("certs/cert.pem");
+const KEY: &[u8] = include_bytes!("certs/key.pem");
+
+// Spawns a process that accepts one TLS connection and echoes everything back.
+fn echo_server() -> std::net::SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    process::spawn_with(listener, |listener, _: Mailbox<()>| {
+        let (stream, _) = listener.accept().unwrap();
+        let acceptor = TlsAcceptor::new(CERT, KEY);
+        let mut stream = acceptor.accept(stream).unwrap();
+        let mut buffer = [0; 1024];
+        loop {
+            match stream.read(&mut buffer).unwrap() {
+                0 => break,
+                read => stream.write_all(&buffer[..read]).unwrap(),
+            }
+        }
+    })
+    .unwrap();
+    addr
+}
+
+fn connector() -> TlsConnector {
+    let mut connector = TlsConnector::new();
+    connector.add_root_certificates(CERT);
+    connector
+}
+
+#[lunatic::test]
+fn tls_echo(_: Mailbox<()>) {
+    let stream = TcpStream::connect(echo_server()).unwrap();
+    let mut stream = connector().connect("localhost", stream).unwrap();
+    stream.write_all(b"hello over tls").unwrap();
+    let mut buffer = [0; 14];
+    stream.read_exact(&mut buffer).unwrap();
+    assert_eq!(&buffer, b"hello over tls");
+}
+
+#[lunatic::test]
+fn tls_untrusted_certificate(_: Mailbox<()>) {
+    let stream = TcpStream::connect(echo_server()).unwrap();
+    // The self-signed certificate is not trusted by the default roots.
+    let error = TlsConnector::new()
+        .connect("localhost", stream)
+        .unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+
+    // The certificate is not valid for other names.
+    let stream = TcpStream::connect(echo_server()).unwrap();
+    assert!(connector().connect("example.com", stream).is_err());
+}
+
+#[lunatic::test]
+fn tls_move_stream(mailbox: Mailbox<Vec<u8>>) {
+    let stream = TcpStream::connect(echo_server()).unwrap();
+    let mut stream = connector().connect("127.0.0.1", stream).unwrap();
+    stream.write_all(b"first").unwrap();
+    let mut buffer = [0; 5];
+    stream.read_exact(&mut buffer).unwrap();
+    assert_eq!(&buffer, b"first");
+
+    // The session continues in another process.
+    let context = (stream, process::this(&mailbox));
+    process::spawn_with(context, |(mut stream, parent), _: Mailbox<()>| {
+        let stream: &mut TlsStream = &mut stream;
+        stream.write_all(b"second").unwrap();
+        let mut buffer = vec![0; 6];
+        stream.read_exact(&mut buffer).unwrap();
+        parent.send(buffer);
+    })
+    .unwrap();
+    assert_eq!(mailbox.receive().unwrap(), b"second");
+}
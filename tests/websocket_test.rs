@@ -0,0 +1,165 @@
+#![cfg(feature = "websocket")]
+
+use std::io::{Read, Write};
+
+use lunatic::{
+    http::Request,
+    net::{TcpListener, TcpStream},
+    process,
+    websocket::{self, CloseFrame, Message, WebSocket, WebSocketError},
+    Mailbox,
+};
+
+// Spawns a process that accepts one connection and echoes every message until it's closed.
+fn echo_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("ws://{}/echo", listener.local_addr().unwrap());
+    process::spawn_with(listener, |listener, _: Mailbox<()>| {
+        let (stream, _) = listener.accept().unwrap();
+        let (mut socket, request) = WebSocket::accept(stream).unwrap();
+        assert_eq!(request.path(), "/echo");
+        while let Ok(message) = socket.receive() {
+            if let Message::Text(_) | Message::Binary(_) = message {
+                socket.send(message).unwrap();
+            }
+        }
+    })
+    .unwrap();
+    url
+}
+
+#[lunatic::test]
+fn websocket_echo(_: Mailbox<()>) {
+    let (mut socket, response) = WebSocket::connect(&echo_server()).unwrap();
+    assert_eq!(response.status(), 101);
+
+    socket.send(Message::Text("hello".to_string())).unwrap();
+    assert_eq!(
+        socket.receive().unwrap(),
+        Message::Text("hello".to_string())
+    );
+    socket.send(Message::Binary(vec![0; 70_000])).unwrap();
+    assert_eq!(socket.receive().unwrap(), Message::Binary(vec![0; 70_000]));
+
+    // Pings are answered by the server.
+    socket.send(Message::Ping(b"beat".to_vec())).unwrap();
+    assert_eq!(socket.receive().unwrap(), Message::Pong(b"beat".to_vec()));
+
+    let code = CloseFrame {
+        code: 1000,
+        reason: "bye".to_string(),
+    };
+    socket.close(Some(code)).unwrap();
+    assert!(matches!(
+        socket.send(Message::Text("late".to_string())),
+        Err(WebSocketError::Closed)
+    ));
+    let reply = socket.receive().unwrap();
+    assert!(matches!(
+        reply,
+        Message::Close(Some(CloseFrame { code: 1000, .. }))
+    ));
+    assert!(matches!(socket.receive(), Err(WebSocketError::Closed)));
+}
+
+#[lunatic::test]
+fn websocket_fragments(_: Mailbox<()>) {
+    let (mut socket, _) = WebSocket::connect(&echo_server()).unwrap();
+    socket.set_fragment_size(Some(3));
+    socket
+        .send(Message::Text("fragmented ünicode".to_string()))
+        .unwrap();
+    assert_eq!(
+        socket.receive().unwrap(),
+        Message::Text("fragmented ünicode".to_string())
+    );
+
+    socket.set_max_message_size(8);
+    socket.send(Message::Binary(vec![1; 16])).unwrap();
+    assert!(matches!(socket.receive(), Err(WebSocketError::TooLarge(_))));
+}
+
+#[lunatic::test]
+fn websocket_protocol_errors(_: Mailbox<()>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    // A plain HTTP request is rejected.
+    let mut client = TcpStream::connect(addr).unwrap();
+    client
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+    let (stream, _) = listener.accept().unwrap();
+    assert!(matches!(
+        WebSocket::accept(stream),
+        Err(WebSocketError::Handshake(_))
+    ));
+    let mut response = String::new();
+    client.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 426"));
+
+    // Frames from clients need to be masked.
+    let mut client = TcpStream::connect(addr).unwrap();
+    client
+        .write_all(
+            b"GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\n\
+              Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+              Sec-WebSocket-Version: 13\r\n\r\n",
+        )
+        .unwrap();
+    let (stream, _) = listener.accept().unwrap();
+    let (mut server, _) = WebSocket::accept(stream).unwrap();
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        let mut byte = [0];
+        client.read_exact(&mut byte).unwrap();
+        head.push(byte[0]);
+    }
+    let head = String::from_utf8(head).unwrap();
+    assert!(head.starts_with("HTTP/1.1 101"));
+    assert!(head.contains("s3pPLMBiTxaQ9kYGzzhZRbK+xOo="));
+
+    client.write_all(&[0x81, 0x02, b'h', b'i']).unwrap();
+    assert!(matches!(server.receive(), Err(WebSocketError::Protocol(_))));
+    // The server closes the connection with `1002 Protocol Error`.
+    let mut close = Vec::new();
+    client.read_to_end(&mut close).unwrap();
+    assert_eq!(close, [0x88, 0x02, 0x03, 0xEA]);
+}
+
+#[lunatic::test]
+fn websocket_serve(_: Mailbox<()>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("ws://{}/chat", listener.local_addr().unwrap());
+    process::spawn_with(listener, |listener, _: Mailbox<()>| {
+        websocket::serve(&listener, shout).unwrap();
+    })
+    .unwrap();
+
+    for _ in 0..2 {
+        let (mut socket, _) = WebSocket::connect(&url).unwrap();
+        socket.send(Message::Text("hello".to_string())).unwrap();
+        assert_eq!(
+            socket.receive().unwrap(),
+            Message::Text("/chat: HELLO".to_string())
+        );
+        // Pings are forwarded to the handler, which answers them.
+        socket.send(Message::Ping(b"beat".to_vec())).unwrap();
+        assert_eq!(socket.receive().unwrap(), Message::Pong(b"beat".to_vec()));
+        socket.close(None).unwrap();
+        assert_eq!(socket.receive().unwrap(), Message::Close(None));
+    }
+}
+
+fn shout((mut socket, request): (WebSocket, Request), mailbox: Mailbox<Message>) {
+    while let Ok(message) = mailbox.receive() {
+        match message {
+            Message::Text(text) => {
+                let reply = format!("{}: {}", request.path(), text.to_uppercase());
+                socket.send(Message::Text(reply)).unwrap();
+            }
+            Message::Close(_) => return socket.answer(&message).unwrap(),
+            _ => socket.answer(&message).unwrap(),
+        }
+    }
+}